@@ -0,0 +1,338 @@
+//! Module for converting and manipulating `Color` values.
+//!
+//! Everything here is plain integer math with no hardware access, so the
+//! same code can run in a game, in a build script or asset tool, or in host
+//! tests.
+//!
+//! The blends match the arithmetic that the hardware uses for `BLDALPHA` and
+//! `BLDY`, so you can predict exactly what a blended pixel will look like.
+
+use super::*;
+
+/// Splits a color into its `(red, green, blue)` channels.
+const fn channels(color: Color) -> (u16, u16, u16) {
+  (color.0 & 0x1F, (color.0 >> 5) & 0x1F, (color.0 >> 10) & 0x1F)
+}
+
+/// Spreads the channels of a color out so that they can all be multiplied by
+/// a value up to 32 at once without spilling into each other.
+const fn spread(color: Color) -> u32 {
+  let c = color.0 as u32;
+  (c & 0x7C1F) | ((c & 0x03E0) << 16)
+}
+
+/// Undoes `spread` after a multiply and `>> 5`, dropping the fraction bits.
+const fn unspread(x: u32) -> Color {
+  Color(((x & 0x7C1F) | ((x >> 16) & 0x03E0)) as u16)
+}
+
+/// Clamps a channel value to 31.
+const fn min_31(c: u16) -> u16 {
+  if c > 31 {
+    31
+  } else {
+    c
+  }
+}
+
+/// Expands a 5 bit channel to 8 bits, so that 31 becomes 255.
+const fn expand_channel(c: u16) -> u8 {
+  ((c << 3) | (c >> 2)) as u8
+}
+
+impl Color {
+  /// Black, all channels 0.
+  pub const BLACK: Color = Color(0);
+
+  /// White, all channels 31.
+  pub const WHITE: Color = Color(0x7FFF);
+
+  /// Makes a color from 8 bit channels, dropping the low 3 bits of each.
+  pub const fn from_rgb888(r: u8, g: u8, b: u8) -> Color {
+    Color::from_rgb((r >> 3) as u16, (g >> 3) as u16, (b >> 3) as u16)
+  }
+
+  /// Makes a color from a `0xRRGGBB` value, like the ones used on the web.
+  ///
+  /// ```
+  /// # use gba::Color;
+  /// const ORANGE: Color = Color::from_hex(0xFF8000);
+  /// assert_eq!(ORANGE, Color::from_rgb(31, 16, 0));
+  /// ```
+  pub const fn from_hex(hex: u32) -> Color {
+    Color::from_rgb888((hex >> 16) as u8, (hex >> 8) as u8, hex as u8)
+  }
+
+  /// Gives the color as 8 bit channels `(r, g, b)`.
+  ///
+  /// The low bits are filled in from the high bits, so 31 becomes 255 and 0
+  /// stays 0.
+  pub const fn to_rgb888(self) -> (u8, u8, u8) {
+    let (r, g, b) = channels(self);
+    (expand_channel(r), expand_channel(g), expand_channel(b))
+  }
+
+  /// Gives the color as a `0xRRGGBB` value, see `to_rgb888`.
+  pub const fn to_hex(self) -> u32 {
+    let (r, g, b) = self.to_rgb888();
+    (r as u32) << 16 | (g as u32) << 8 | b as u32
+  }
+
+  /// Makes a color from hue, saturation and value.
+  ///
+  /// * `hue` is in degrees, and wraps at 360.
+  /// * `saturation` and `value` go from 0 to 255.
+  pub const fn from_hsv(hue: u16, saturation: u8, value: u8) -> Color {
+    let hue = (hue % 360) as u32;
+    let (s, v) = (saturation as u32, value as u32);
+    let rem = (hue % 60) * 255 / 60;
+    let p = v * (255 - s) / 255;
+    let q = v * (255 - s * rem / 255) / 255;
+    let t = v * (255 - s * (255 - rem) / 255) / 255;
+    let (r, g, b) = match hue / 60 {
+      0 => (v, t, p),
+      1 => (q, v, p),
+      2 => (p, v, t),
+      3 => (p, q, v),
+      4 => (t, p, v),
+      _ => (v, p, q),
+    };
+    Color::from_rgb888(r as u8, g as u8, b as u8)
+  }
+
+  /// Gives the color as `(hue, saturation, value)`, see `from_hsv`.
+  ///
+  /// Grays have a hue of 0.
+  pub const fn to_hsv(self) -> (u16, u8, u8) {
+    let (r, g, b) = self.to_rgb888();
+    let (r, g, b) = (r as i32, g as i32, b as i32);
+    let max = if r > g { if r > b { r } else { b } } else if g > b { g } else { b };
+    let min = if r < g { if r < b { r } else { b } } else if g < b { g } else { b };
+    let delta = max - min;
+    if delta == 0 {
+      return (0, 0, max as u8);
+    }
+    let hue = if max == r {
+      60 * (g - b) / delta
+    } else if max == g {
+      120 + 60 * (b - r) / delta
+    } else {
+      240 + 60 * (r - g) / delta
+    };
+    let hue = if hue < 0 { hue + 360 } else { hue };
+    (hue as u16, (255 * delta / max) as u8, max as u8)
+  }
+
+  /// Linear interpolation from `self` to `other` by `level`, from 0 (all
+  /// `self`) to 32 (all `other`).
+  ///
+  /// This uses a single multiply per color, so it's cheap enough to run over
+  /// whole palettes every frame.
+  pub const fn lerp(self, other: Color, level: u8) -> Color {
+    let level = if level > 32 { 32 } else { level as u32 };
+    unspread((spread(self) * (32 - level) + spread(other) * level) >> 5)
+  }
+
+  /// Alpha blends two colors the same way as the hardware's `BLDALPHA`.
+  ///
+  /// Each channel is `min(31, (top * eva + bottom * evb) / 16)`, with the
+  /// coefficients clamped to 16.
+  pub const fn blend_alpha(top: Color, eva: u16, bottom: Color, evb: u16) -> Color {
+    let eva = if eva > 16 { 16 } else { eva };
+    let evb = if evb > 16 { 16 } else { evb };
+    let (tr, tg, tb) = channels(top);
+    let (br, bg, bb) = channels(bottom);
+    Color::from_rgb(
+      min_31((tr * eva + br * evb) >> 4),
+      min_31((tg * eva + bg * evb) >> 4),
+      min_31((tb * eva + bb * evb) >> 4),
+    )
+  }
+
+  /// Brightens towards white the same way as the hardware's `BLDY` with the
+  /// `BrightnessIncrease` effect.
+  ///
+  /// Each channel is `c + (31 - c) * evy / 16`, with `evy` clamped to 16.
+  pub const fn brighten(self, evy: u16) -> Color {
+    let evy = if evy > 16 { 16 } else { evy };
+    let (r, g, b) = channels(self);
+    Color::from_rgb(
+      r + (((31 - r) * evy) >> 4),
+      g + (((31 - g) * evy) >> 4),
+      b + (((31 - b) * evy) >> 4),
+    )
+  }
+
+  /// Darkens towards black the same way as the hardware's `BLDY` with the
+  /// `BrightnessDecrease` effect.
+  ///
+  /// Each channel is `c - c * evy / 16`, with `evy` clamped to 16.
+  pub const fn darken(self, evy: u16) -> Color {
+    let evy = if evy > 16 { 16 } else { evy };
+    let (r, g, b) = channels(self);
+    Color::from_rgb(r - ((r * evy) >> 4), g - ((g * evy) >> 4), b - ((b * evy) >> 4))
+  }
+
+  /// Adds each channel, saturating at 31.
+  pub const fn saturating_add(self, other: Color) -> Color {
+    let (ar, ag, ab) = channels(self);
+    let (br, bg, bb) = channels(other);
+    Color::from_rgb(min_31(ar + br), min_31(ag + bg), min_31(ab + bb))
+  }
+
+  /// Subtracts each channel, saturating at 0.
+  pub const fn saturating_sub(self, other: Color) -> Color {
+    let (ar, ag, ab) = channels(self);
+    let (br, bg, bb) = channels(other);
+    Color::from_rgb(ar.saturating_sub(br), ag.saturating_sub(bg), ab.saturating_sub(bb))
+  }
+
+  /// The average of each channel, rounding down.
+  pub const fn average(self, other: Color) -> Color {
+    Color((self.0 & other.0 & 0x7FFF) + (((self.0 ^ other.0) & 0x7BDE) >> 1))
+  }
+
+  /// The perceived brightness of the color, from 0 to 31.
+  pub const fn luma(self) -> u16 {
+    let (r, g, b) = channels(self);
+    (r * 77 + g * 150 + b * 29) >> 8
+  }
+
+  /// A gray with the same brightness as this color, see `luma`.
+  pub const fn to_grayscale(self) -> Color {
+    let y = self.luma();
+    Color::from_rgb(y, y, y)
+  }
+
+  /// Gives the color as 8 bit channels `(r, g, b)`, corrected to look like it
+  /// does on the GBA's LCD.
+  ///
+  /// The GBA screen is darker and less saturated than a modern monitor, so
+  /// colors shown with `to_rgb888` on a PC look harsher than on hardware.
+  /// This uses the widely used color model by Near (byuu): a gamma of 4 for
+  /// the LCD, a mix of the channels to model how they bleed together, and a
+  /// gamma of 2.2 for the output.
+  pub fn to_lcd_rgb888(self) -> (u8, u8, u8) {
+    let (r, g, b) = channels(self);
+    let (lr, lg, lb) =
+      (LCD_LINEAR[r as usize], LCD_LINEAR[g as usize], LCD_LINEAR[b as usize]);
+    let encode = |linear: u32| LCD_GAMMA[isqrt(linear / 255) as usize];
+    (
+      encode(255 * lr + 50 * lg),
+      encode(10 * lr + 230 * lg + 30 * lb),
+      encode(50 * lr + 10 * lg + 220 * lb),
+    )
+  }
+}
+
+/// The integer square root, rounding down.
+pub(crate) fn isqrt(n: u32) -> u32 {
+  let mut root = 0;
+  let mut bit = 1 << 30;
+  let mut n = n;
+  while bit > n {
+    bit >>= 2;
+  }
+  while bit != 0 {
+    if n >= root + bit {
+      n -= root + bit;
+      root = (root >> 1) + bit;
+    } else {
+      root >>= 1;
+    }
+    bit >>= 2;
+  }
+  root
+}
+
+/// Each channel value with the LCD's gamma of 4 applied, from 0 to `255*255`.
+const LCD_LINEAR: [u32; 32] = [
+  0, 0, 1, 6, 18, 44, 91, 169,
+  288, 462, 704, 1031, 1460, 2011, 2705, 3565,
+  4614, 5881, 7391, 9176, 11266, 13693, 16494, 19704,
+  23360, 27504, 32176, 37419, 43278, 49800, 57032, 65025,
+];
+
+/// Maps the square root of a linear value to an output channel, with the
+/// output gamma of 2.2 and the model's overall darkening applied.
+///
+/// The channel mix can add up to `305 * 255`, past full scale, and the model
+/// applies the gamma before clamping, so this goes past 255 up to
+/// `isqrt(305 * 255)`.
+const LCD_GAMMA: [u8; 279] = [
+  0, 2, 3, 4, 5, 7, 8, 9, 10, 11, 12, 13, 14, 16, 17, 18,
+  19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32, 33, 34,
+  35, 36, 37, 38, 39, 40, 41, 42, 43, 44, 45, 46, 47, 48, 49, 50,
+  51, 52, 53, 54, 55, 56, 57, 58, 59, 59, 60, 61, 62, 63, 64, 65,
+  66, 67, 68, 69, 70, 71, 72, 73, 74, 74, 75, 76, 77, 78, 79, 80,
+  81, 82, 83, 84, 85, 86, 86, 87, 88, 89, 90, 91, 92, 93, 94, 95,
+  96, 96, 97, 98, 99, 100, 101, 102, 103, 104, 105, 105, 106, 107, 108, 109,
+  110, 111, 112, 113, 113, 114, 115, 116, 117, 118, 119, 120, 121, 121, 122, 123,
+  124, 125, 126, 127, 128, 129, 129, 130, 131, 132, 133, 134, 135, 136, 136, 137,
+  138, 139, 140, 141, 142, 142, 143, 144, 145, 146, 147, 148, 149, 149, 150, 151,
+  152, 153, 154, 155, 155, 156, 157, 158, 159, 160, 161, 161, 162, 163, 164, 165,
+  166, 167, 167, 168, 169, 170, 171, 172, 173, 173, 174, 175, 176, 177, 178, 179,
+  179, 180, 181, 182, 183, 184, 185, 185, 186, 187, 188, 189, 190, 190, 191, 192,
+  193, 194, 195, 195, 196, 197, 198, 199, 200, 201, 201, 202, 203, 204, 205, 206,
+  206, 207, 208, 209, 210, 211, 211, 212, 213, 214, 215, 216, 216, 217, 218, 219,
+  220, 221, 221, 222, 223, 224, 225, 226, 226, 227, 228, 229, 230, 231, 231, 232,
+  233, 234, 235, 236, 236, 237, 238, 239, 240, 240, 241, 242, 243, 244, 245, 245,
+  246, 247, 248, 249, 250, 250, 251,
+];
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_rgb888_round_trip() {
+    for raw in 0..0x8000 {
+      let color = Color(raw);
+      let (r, g, b) = color.to_rgb888();
+      assert_eq!(Color::from_rgb888(r, g, b), color);
+      assert_eq!(Color::from_hex(color.to_hex()), color);
+    }
+    assert_eq!(Color::WHITE.to_hex(), 0xFFFFFF);
+  }
+
+  #[test]
+  fn test_hsv() {
+    assert_eq!(Color::from_hsv(0, 255, 255), Color::from_rgb(31, 0, 0));
+    assert_eq!(Color::from_hsv(120, 255, 255), Color::from_rgb(0, 31, 0));
+    assert_eq!(Color::from_hsv(240, 255, 255), Color::from_rgb(0, 0, 31));
+    assert_eq!(Color::from_hsv(480, 0, 255), Color::WHITE);
+    assert_eq!(Color::from_rgb(0, 31, 31).to_hsv(), (180, 255, 255));
+  }
+
+  #[test]
+  fn test_hardware_blends() {
+    let a = Color::from_rgb(20, 10, 0);
+    let b = Color::from_rgb(20, 31, 8);
+    assert_eq!(Color::blend_alpha(a, 8, b, 8), Color::from_rgb(20, 20, 4));
+    assert_eq!(Color::blend_alpha(a, 16, b, 16), Color::from_rgb(31, 31, 8));
+    assert_eq!(a.brighten(8), Color::from_rgb(25, 20, 15));
+    assert_eq!(a.darken(8), Color::from_rgb(10, 5, 0));
+    assert_eq!(a.brighten(99), Color::WHITE);
+    assert_eq!(a.average(b), Color::from_rgb(20, 20, 4));
+    assert_eq!(a.lerp(b, 16), Color::from_rgb(20, 20, 4));
+  }
+
+  #[test]
+  fn test_lerp_ends_and_middle() {
+    let white = Color::from_rgb(31, 31, 31);
+    let red = Color::from_rgb(31, 0, 0);
+    assert_eq!(red.lerp(white, 0), red);
+    assert_eq!(red.lerp(white, 32), white);
+    assert_eq!(Color(0).lerp(white, 16), Color::from_rgb(15, 15, 15));
+    let (a, b) = (Color::from_rgb(4, 8, 12), Color::from_rgb(12, 8, 4));
+    assert_eq!(a.lerp(b, 16), Color::from_rgb(8, 8, 8));
+  }
+
+  #[test]
+  fn test_lcd_rgb888() {
+    assert_eq!(Color(0).to_lcd_rgb888(), (0, 0, 0));
+    assert_eq!(Color::WHITE.to_lcd_rgb888(), (251, 238, 242));
+    assert_eq!(Color::from_rgb(30, 30, 30).to_lcd_rgb888(), (237, 224, 228));
+    assert_eq!(Color::from_rgb(31, 0, 0).to_lcd_rgb888(), (232, 53, 110));
+  }
+}
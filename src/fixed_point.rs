@@ -1,7 +1,6 @@
 #![allow(non_camel_case_types)]
-// Note(Lokathor): NOT CURRENTLY USED.
-//
-// If we want this in the future we can convert it to const generics.
+// Note(Lokathor): If we want this in the future we can convert it to const
+// generics.
 
 //! Module for fixed point math types and operations.
 
@@ -9,7 +8,7 @@ use core::{
   marker::PhantomData,
   ops::{Add, Div, Mul, Neg, Shl, Shr, Sub},
 };
-use typenum::{consts::False, marker_traits::Unsigned, type_operators::IsEqual, U8};
+use typenum::{consts::False, marker_traits::Unsigned, type_operators::IsEqual, U12, U8};
 
 /// Fixed point `T` value with `F` fractional bits.
 #[derive(Debug, Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
@@ -21,7 +20,7 @@ pub struct Fx<T, F: Unsigned> {
 
 impl<T, F: Unsigned> Fx<T, F> {
   /// Uses the provided value directly.
-  pub fn from_raw(r: T) -> Self {
+  pub const fn from_raw(r: T) -> Self {
     Fx { num: r, phantom: PhantomData }
   }
 
@@ -190,6 +189,52 @@ fixed_point_unsigned_division! {u32}
 /// Alias for an `i16` fixed point value with 8 fractional bits.
 pub type fx8_8 = Fx<i16, U8>;
 
+/// Alias for an `i32` fixed point value with 8 fractional bits.
+pub type fx24_8 = Fx<i32, U8>;
+
+/// Alias for an `i32` fixed point value with 12 fractional bits.
+pub type fx20_12 = Fx<i32, U12>;
+
+/// A quarter of a sine wave in 256 steps per turn, with 12 fractional bits.
+const QUARTER_SINE: [i16; 65] = [
+  0, 101, 201, 301, 401, 501, 601, 700, 799, 897, 995, 1092, 1189,
+  1285, 1380, 1474, 1567, 1660, 1751, 1842, 1931, 2019, 2106, 2191, 2276, 2359,
+  2440, 2520, 2598, 2675, 2751, 2824, 2896, 2967, 3035, 3102, 3166, 3229, 3290,
+  3349, 3406, 3461, 3513, 3564, 3612, 3659, 3703, 3745, 3784, 3822, 3857, 3889,
+  3920, 3948, 3973, 3996, 4017, 4036, 4052, 4065, 4076, 4085, 4091, 4095, 4096,
+];
+
+/// Looks up the sine of a step in the 256 step table, using symmetry.
+fn sine_step(step: u32) -> i32 {
+  let step = step & 0xFF;
+  let quarter_index = (step & 0x3F) as usize;
+  let value = match step >> 6 {
+    0 => QUARTER_SINE[quarter_index],
+    1 => QUARTER_SINE[64 - quarter_index],
+    2 => -QUARTER_SINE[quarter_index],
+    _ => -QUARTER_SINE[64 - quarter_index],
+  };
+  value as i32
+}
+
+/// The sine of a "binary angle", where `0x1_0000` would be a full turn.
+///
+/// This is the same angle format as the BIOS affine functions use. The result
+/// comes from a lookup table with linear interpolation, so it doesn't need
+/// any floating point or division.
+pub fn sin(angle: u16) -> fx20_12 {
+  let step = (angle >> 8) as u32;
+  let frac = (angle & 0xFF) as i32;
+  let low = sine_step(step);
+  let high = sine_step(step + 1);
+  Fx::from_raw(low + (((high - low) * frac) >> 8))
+}
+
+/// The cosine of a "binary angle", see [`sin`].
+pub fn cos(angle: u16) -> fx20_12 {
+  sin(angle.wrapping_add(0x4000))
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -245,6 +290,16 @@ mod tests {
     assert!(two * three == twelve * half);
   }
 
+  #[test]
+  fn test_sin_cos() {
+    assert_eq!(sin(0).into_raw(), 0);
+    assert_eq!(sin(0x4000).into_raw(), 4096);
+    assert_eq!(sin(0xC000).into_raw(), -4096);
+    assert_eq!(cos(0).into_raw(), 4096);
+    assert_eq!(cos(0x8000).into_raw(), -4096);
+    assert_eq!(sin(0x2000).into_raw(), 2896);
+  }
+
   #[test]
   fn test_div() {
     use typenum::U4;
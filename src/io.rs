@@ -11,10 +11,14 @@ use super::*;
 pub mod background;
 pub mod color_blend;
 pub mod display;
+pub mod display_config;
 pub mod dma;
+pub mod hblank;
 pub mod irq;
 pub mod keypad;
+pub mod mode7;
 pub mod sio;
 pub mod sound;
 pub mod timers;
+pub mod transition;
 pub mod window;
@@ -1,6 +1,10 @@
 //! Module for Background controls
 
 use super::*;
+use crate::{
+  bios::BgAffineSetParams,
+  fixed_point::{fx24_8, fx8_8},
+};
 
 /// BG0 Control. Read/Write. Display Mode 0/1 only.
 pub const BG0CNT: VolAddress<BackgroundControlSetting, Safe, Safe> =
@@ -89,21 +93,220 @@ pub const BG3HOFS: VolAddress<u16, (), Safe> = unsafe { VolAddress::new(0x400_00
 /// BG3 Y-Offset. Write only. Text mode only. 9 bits.
 pub const BG3VOFS: VolAddress<u16, (), Safe> = unsafe { VolAddress::new(0x400_001E) };
 
-// TODO: fixed point format
-pub const BG2PA: VolAddress<u16, (), Safe> = unsafe { VolAddress::new(0x400_0020) };
-pub const BG2PB: VolAddress<u16, (), Safe> = unsafe { VolAddress::new(0x400_0022) };
-pub const BG2PC: VolAddress<u16, (), Safe> = unsafe { VolAddress::new(0x400_0024) };
-pub const BG2PD: VolAddress<u16, (), Safe> = unsafe { VolAddress::new(0x400_0026) };
-pub const BG2X_L: VolAddress<u16, (), Safe> = unsafe { VolAddress::new(0x400_0028) };
-pub const BG2X_H: VolAddress<u16, (), Safe> = unsafe { VolAddress::new(0x400_002A) };
-pub const BG2Y_L: VolAddress<u16, (), Safe> = unsafe { VolAddress::new(0x400_002C) };
-pub const BG2Y_H: VolAddress<u16, (), Safe> = unsafe { VolAddress::new(0x400_002E) };
-
-pub const BG3PA: VolAddress<u16, (), Safe> = unsafe { VolAddress::new(0x400_0030) };
-pub const BG3PB: VolAddress<u16, (), Safe> = unsafe { VolAddress::new(0x400_0032) };
-pub const BG3PC: VolAddress<u16, (), Safe> = unsafe { VolAddress::new(0x400_0034) };
-pub const BG3PD: VolAddress<u16, (), Safe> = unsafe { VolAddress::new(0x400_0036) };
-pub const BG3X_L: VolAddress<u16, (), Safe> = unsafe { VolAddress::new(0x400_0038) };
-pub const BG3X_H: VolAddress<u16, (), Safe> = unsafe { VolAddress::new(0x400_003A) };
-pub const BG3Y_L: VolAddress<u16, (), Safe> = unsafe { VolAddress::new(0x400_003C) };
-pub const BG3Y_H: VolAddress<u16, (), Safe> = unsafe { VolAddress::new(0x400_003E) };
+/// A handle to one of the four background layers.
+///
+/// The scroll registers are write only, so the handle keeps a RAM copy of the
+/// control setting and the scroll offsets. Changes are only made to the copy,
+/// call [`flush`](Background::flush) (usually during VBlank) to write them to
+/// the hardware.
+///
+/// `N` must be 0 through 3.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Background<const N: usize> {
+  control: BackgroundControlSetting,
+  h_offset: u16,
+  v_offset: u16,
+}
+
+/// The handle for BG0.
+pub type Bg0 = Background<0>;
+/// The handle for BG1.
+pub type Bg1 = Background<1>;
+/// The handle for BG2.
+pub type Bg2 = Background<2>;
+/// The handle for BG3.
+pub type Bg3 = Background<3>;
+
+impl<const N: usize> Background<N> {
+  const VALID_INDEX: () = assert!(N < 4, "background index must be 0 through 3");
+
+  const CNT: VolAddress<BackgroundControlSetting, Safe, Safe> =
+    unsafe { VolAddress::new(0x400_0008 + N * 2) };
+  const HOFS: VolAddress<u16, (), Safe> = unsafe { VolAddress::new(0x400_0010 + N * 4) };
+  const VOFS: VolAddress<u16, (), Safe> = unsafe { VolAddress::new(0x400_0012 + N * 4) };
+
+  /// Makes a handle with the control setting given and no scroll.
+  pub const fn new(control: BackgroundControlSetting) -> Self {
+    #[allow(clippy::let_unit_value)]
+    let _ = Self::VALID_INDEX;
+    Self { control, h_offset: 0, v_offset: 0 }
+  }
+
+  /// The control setting that will be written at the next flush.
+  pub const fn control(&self) -> BackgroundControlSetting {
+    self.control
+  }
+
+  /// Replaces the control setting.
+  pub fn set_control(&mut self, control: BackgroundControlSetting) {
+    self.control = control;
+  }
+
+  /// The current `(x, y)` scroll offset.
+  ///
+  /// Only the low 9 bits are used by the hardware, the rest are kept so that
+  /// the value reads back exactly as it was set.
+  pub const fn scroll(&self) -> (u16, u16) {
+    (self.h_offset, self.v_offset)
+  }
+
+  /// Sets the `(x, y)` scroll offset.
+  pub fn set_scroll(&mut self, x: u16, y: u16) {
+    self.h_offset = x;
+    self.v_offset = y;
+  }
+
+  /// Moves the scroll offset by the amount given, wrapping on overflow.
+  pub fn scroll_by(&mut self, dx: i16, dy: i16) {
+    self.h_offset = self.h_offset.wrapping_add(dx as u16);
+    self.v_offset = self.v_offset.wrapping_add(dy as u16);
+  }
+
+  /// Writes the control setting and scroll offsets to the hardware.
+  ///
+  /// The scroll registers only apply in text mode, they're ignored when the
+  /// layer is used as an affine background.
+  pub fn flush(&self) {
+    Self::CNT.write(self.control);
+    Self::HOFS.write(self.h_offset);
+    Self::VOFS.write(self.v_offset);
+  }
+}
+
+impl<const N: usize> Default for Background<N> {
+  fn default() -> Self {
+    Self::new(Default::default())
+  }
+}
+
+/// All four background layers together, for flushing at once.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Backgrounds {
+  pub bg0: Bg0,
+  pub bg1: Bg1,
+  pub bg2: Bg2,
+  pub bg3: Bg3,
+}
+impl Backgrounds {
+  /// Makes a set of handles with default control settings and no scroll.
+  pub const fn new() -> Self {
+    Self {
+      bg0: Bg0::new(BackgroundControlSetting::new()),
+      bg1: Bg1::new(BackgroundControlSetting::new()),
+      bg2: Bg2::new(BackgroundControlSetting::new()),
+      bg3: Bg3::new(BackgroundControlSetting::new()),
+    }
+  }
+
+  /// Writes every layer's control setting and scroll offsets to the hardware.
+  ///
+  /// Call this during VBlank to avoid tearing.
+  pub fn flush(&self) {
+    self.bg0.flush();
+    self.bg1.flush();
+    self.bg2.flush();
+    self.bg3.flush();
+  }
+}
+
+/// BG2 Affine Parameter A (dx). Write only. Affine mode only. 8.8 fixed point.
+pub const BG2PA: VolAddress<fx8_8, (), Safe> = unsafe { VolAddress::new(0x400_0020) };
+/// BG2 Affine Parameter B (dmx). Write only. Affine mode only. 8.8 fixed point.
+pub const BG2PB: VolAddress<fx8_8, (), Safe> = unsafe { VolAddress::new(0x400_0022) };
+/// BG2 Affine Parameter C (dy). Write only. Affine mode only. 8.8 fixed point.
+pub const BG2PC: VolAddress<fx8_8, (), Safe> = unsafe { VolAddress::new(0x400_0024) };
+/// BG2 Affine Parameter D (dmy). Write only. Affine mode only. 8.8 fixed point.
+pub const BG2PD: VolAddress<fx8_8, (), Safe> = unsafe { VolAddress::new(0x400_0026) };
+/// BG2 Reference Point X. Write only. Affine mode only. 20.8 fixed point.
+pub const BG2X: VolAddress<fx24_8, (), Safe> = unsafe { VolAddress::new(0x400_0028) };
+/// BG2 Reference Point Y. Write only. Affine mode only. 20.8 fixed point.
+pub const BG2Y: VolAddress<fx24_8, (), Safe> = unsafe { VolAddress::new(0x400_002C) };
+
+/// BG3 Affine Parameter A (dx). Write only. Affine mode only. 8.8 fixed point.
+pub const BG3PA: VolAddress<fx8_8, (), Safe> = unsafe { VolAddress::new(0x400_0030) };
+/// BG3 Affine Parameter B (dmx). Write only. Affine mode only. 8.8 fixed point.
+pub const BG3PB: VolAddress<fx8_8, (), Safe> = unsafe { VolAddress::new(0x400_0032) };
+/// BG3 Affine Parameter C (dy). Write only. Affine mode only. 8.8 fixed point.
+pub const BG3PC: VolAddress<fx8_8, (), Safe> = unsafe { VolAddress::new(0x400_0034) };
+/// BG3 Affine Parameter D (dmy). Write only. Affine mode only. 8.8 fixed point.
+pub const BG3PD: VolAddress<fx8_8, (), Safe> = unsafe { VolAddress::new(0x400_0036) };
+/// BG3 Reference Point X. Write only. Affine mode only. 20.8 fixed point.
+pub const BG3X: VolAddress<fx24_8, (), Safe> = unsafe { VolAddress::new(0x400_0038) };
+/// BG3 Reference Point Y. Write only. Affine mode only. 20.8 fixed point.
+pub const BG3Y: VolAddress<fx24_8, (), Safe> = unsafe { VolAddress::new(0x400_003C) };
+
+/// The full set of affine parameters for an affine background.
+///
+/// The matrix maps screen space into texture space: moving one pixel right on
+/// the screen moves `(pa, pc)` in the texture, and moving one pixel down moves
+/// `(pb, pd)`. The reference point is the texture position displayed at the
+/// top-left pixel of the screen.
+///
+/// The field layout exactly matches both the IO registers and the output of
+/// [`bg_affine_set`](bios::bg_affine_set), so the BIOS can write this type
+/// directly.
+///
+/// Only the low 28 bits of `x` and `y` are used by the hardware (20.8 fixed
+/// point, sign extended).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct AffineBgParams {
+  pub pa: fx8_8,
+  pub pb: fx8_8,
+  pub pc: fx8_8,
+  pub pd: fx8_8,
+  pub x: fx24_8,
+  pub y: fx24_8,
+}
+impl AffineBgParams {
+  /// The identity matrix with the reference point at the origin.
+  ///
+  /// This displays the background unscaled and unrotated, which is also what
+  /// the registers hold after a reset.
+  pub const IDENTITY: Self = Self {
+    pa: fx8_8::from_raw(1 << 8),
+    pb: fx8_8::from_raw(0),
+    pc: fx8_8::from_raw(0),
+    pd: fx8_8::from_raw(1 << 8),
+    x: fx24_8::from_raw(0),
+    y: fx24_8::from_raw(0),
+  };
+
+  /// Uses `bg_affine_set` to compute the parameters for the scale and
+  /// rotation given.
+  pub fn from_bios(src: &BgAffineSetParams) -> Self {
+    let mut out = Self::IDENTITY;
+    bios::bg_affine_set(src, &mut out as *mut Self as usize, 1);
+    out
+  }
+}
+
+/// Assigns all of BG2's affine registers.
+pub fn set_bg2_affine_params(params: AffineBgParams) {
+  BG2PA.write(params.pa);
+  BG2PB.write(params.pb);
+  BG2PC.write(params.pc);
+  BG2PD.write(params.pd);
+  BG2X.write(params.x);
+  BG2Y.write(params.y);
+}
+
+/// Assigns all of BG3's affine registers.
+pub fn set_bg3_affine_params(params: AffineBgParams) {
+  BG3PA.write(params.pa);
+  BG3PB.write(params.pb);
+  BG3PC.write(params.pc);
+  BG3PD.write(params.pd);
+  BG3X.write(params.x);
+  BG3Y.write(params.y);
+}
+
+/// Has `bg_affine_set` write its output straight into BG2's affine registers.
+pub fn bg2_affine_set(src: &BgAffineSetParams) {
+  bios::bg_affine_set(src, BG2PA.as_usize(), 1);
+}
+
+/// Has `bg_affine_set` write its output straight into BG3's affine registers.
+pub fn bg3_affine_set(src: &BgAffineSetParams) {
+  bios::bg_affine_set(src, BG3PA.as_usize(), 1);
+}
@@ -1,6 +1,7 @@
 //! Module that holds stuff for the color blending ability.
 
 use super::*;
+use crate::{io::display::LayerSet, sync::Static};
 
 /// Color Special Effects Selection (R/W)
 pub const BLDCNT: VolAddress<ColorEffectSetting, Safe, Safe> =
@@ -74,3 +75,244 @@ impl BrightnessSetting {
     evy_coefficient: 0-4,
   }
 }
+
+/// The most that any blend coefficient can be (16/16, full strength).
+pub const MAX_COEFFICIENT: u16 = 16;
+
+/// The last value written to `BLDY` through this module, since it can't be
+/// read back.
+static BRIGHTNESS_SHADOW: Static<u16> = Static::new(0);
+
+impl ColorEffectSetting {
+  /// Makes a setting with an effect and both target sets.
+  pub const fn from_targets(effect: ColorSpecialEffect, first: LayerSet, second: LayerSet) -> Self {
+    Self(first.bits() | (effect as u16) << 6 | second.bits() << 8)
+  }
+
+  /// The 1st target layers: the top layer for alpha blending, or the layers
+  /// changed by a brightness effect.
+  pub const fn first_targets(self) -> LayerSet {
+    LayerSet::from_bits(self.0)
+  }
+
+  /// The 2nd target layers: the bottom layer for alpha blending.
+  pub const fn second_targets(self) -> LayerSet {
+    LayerSet::from_bits(self.0 >> 8)
+  }
+}
+
+impl AlphaBlendingSetting {
+  /// Makes a setting from both coefficients, each clamped to 16.
+  pub const fn from_coefficients(eva: u16, evb: u16) -> Self {
+    let eva = if eva > MAX_COEFFICIENT { MAX_COEFFICIENT } else { eva };
+    let evb = if evb > MAX_COEFFICIENT { MAX_COEFFICIENT } else { evb };
+    Self(eva | evb << 8)
+  }
+}
+
+/// Blends `top` layers over `bottom` layers.
+///
+/// Each pixel of a `top` layer that has a `bottom` layer pixel directly under
+/// it becomes `top * eva / 16 + bottom * evb / 16`. The coefficients are
+/// clamped to 16.
+///
+/// Semi-transparent objects always blend with the `bottom` layers, even if
+/// `top` doesn't include the objects.
+pub fn alpha_blend(top: LayerSet, bottom: LayerSet, eva: u16, evb: u16) {
+  BLDCNT.write(ColorEffectSetting::from_targets(ColorSpecialEffect::AlphaBlending, top, bottom));
+  BLDALPHA.write(AlphaBlendingSetting::from_coefficients(eva, evb));
+}
+
+/// Fades every layer (and the backdrop) towards black.
+///
+/// The level goes from 0 (no change) to 16 (all black), and is clamped.
+pub fn fade_to_black(level: u16) {
+  fade_layers(ColorSpecialEffect::BrightnessDecrease, LayerSet::ALL, level);
+}
+
+/// Fades every layer (and the backdrop) towards white.
+///
+/// The level goes from 0 (no change) to 16 (all white), and is clamped.
+pub fn fade_to_white(level: u16) {
+  fade_layers(ColorSpecialEffect::BrightnessIncrease, LayerSet::ALL, level);
+}
+
+/// Fades some layers towards black or white.
+///
+/// `effect` should be `BrightnessIncrease` or `BrightnessDecrease`. The level
+/// goes from 0 to 16, and is clamped.
+pub fn fade_layers(effect: ColorSpecialEffect, layers: LayerSet, level: u16) {
+  BLDCNT.write(ColorEffectSetting::from_targets(effect, layers, LayerSet::NONE));
+  set_brightness(level);
+}
+
+/// Sets the brightness level, without changing the targets or the effect.
+///
+/// The level is clamped to 16.
+pub fn set_brightness(level: u16) {
+  let level = level.min(MAX_COEFFICIENT);
+  BRIGHTNESS_SHADOW.write(level);
+  BLDY.write(BrightnessSetting::new().with_evy_coefficient(level as u32));
+}
+
+/// The last brightness level set with this module.
+///
+/// `BLDY` is write only, so this is a copy kept when it's written. Writing
+/// `BLDY` directly won't update it.
+pub fn brightness() -> u16 {
+  BRIGHTNESS_SHADOW.read()
+}
+
+/// Turns off all color special effects.
+pub fn disable_effects() {
+  BLDCNT.write(ColorEffectSetting::new());
+}
+
+/// A brightness fade that runs over a number of frames.
+///
+/// ```no_run
+/// let mut fade = BrightnessFade::to_black(30);
+/// while !fade.is_done() {
+///   bios::vblank_interrupt_wait();
+///   fade.step();
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BrightnessFade {
+  effect: ColorSpecialEffect,
+  layers: LayerSet,
+  from: u16,
+  to: u16,
+  frames: u16,
+  elapsed: u16,
+}
+
+impl BrightnessFade {
+  /// Fades some layers from one level to another over a number of frames.
+  ///
+  /// `effect` should be `BrightnessIncrease` or `BrightnessDecrease`. Levels
+  /// are clamped to 16.
+  pub const fn new(
+    effect: ColorSpecialEffect, layers: LayerSet, from: u16, to: u16, frames: u16,
+  ) -> Self {
+    let from = if from > MAX_COEFFICIENT { MAX_COEFFICIENT } else { from };
+    let to = if to > MAX_COEFFICIENT { MAX_COEFFICIENT } else { to };
+    Self { effect, layers, from, to, frames, elapsed: 0 }
+  }
+
+  /// Fades everything out to black.
+  pub const fn to_black(frames: u16) -> Self {
+    Self::new(ColorSpecialEffect::BrightnessDecrease, LayerSet::ALL, 0, MAX_COEFFICIENT, frames)
+  }
+
+  /// Fades everything in from black.
+  pub const fn from_black(frames: u16) -> Self {
+    Self::new(ColorSpecialEffect::BrightnessDecrease, LayerSet::ALL, MAX_COEFFICIENT, 0, frames)
+  }
+
+  /// Fades everything out to white.
+  pub const fn to_white(frames: u16) -> Self {
+    Self::new(ColorSpecialEffect::BrightnessIncrease, LayerSet::ALL, 0, MAX_COEFFICIENT, frames)
+  }
+
+  /// Fades everything in from white.
+  pub const fn from_white(frames: u16) -> Self {
+    Self::new(ColorSpecialEffect::BrightnessIncrease, LayerSet::ALL, MAX_COEFFICIENT, 0, frames)
+  }
+
+  /// The level for the current frame.
+  pub fn level(&self) -> u16 {
+    lerp_level(self.from, self.to, self.elapsed, self.frames)
+  }
+
+  /// If the fade has reached its final level.
+  pub fn is_done(&self) -> bool {
+    self.elapsed >= self.frames
+  }
+
+  /// Writes the current level and moves on a frame. Call once per frame,
+  /// during VBlank.
+  ///
+  /// The first step also writes `BLDCNT`, and steps after the fade is done
+  /// just write the final level again.
+  pub fn step(&mut self) {
+    if self.elapsed == 0 {
+      BLDCNT.write(ColorEffectSetting::from_targets(self.effect, self.layers, LayerSet::NONE));
+    }
+    set_brightness(self.level());
+    if self.elapsed < self.frames {
+      self.elapsed += 1;
+    }
+  }
+}
+
+/// A cross fade between two sets of layers that runs over a number of frames.
+///
+/// The `top` layers go from `from` to `to` (out of 16), and the `bottom`
+/// layers make up the rest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AlphaFade {
+  top: LayerSet,
+  bottom: LayerSet,
+  from: u16,
+  to: u16,
+  frames: u16,
+  elapsed: u16,
+}
+
+impl AlphaFade {
+  /// Makes a cross fade. The coefficients are clamped to 16.
+  pub const fn new(top: LayerSet, bottom: LayerSet, from: u16, to: u16, frames: u16) -> Self {
+    let from = if from > MAX_COEFFICIENT { MAX_COEFFICIENT } else { from };
+    let to = if to > MAX_COEFFICIENT { MAX_COEFFICIENT } else { to };
+    Self { top, bottom, from, to, frames, elapsed: 0 }
+  }
+
+  /// The `top` layer coefficient for the current frame.
+  pub fn eva(&self) -> u16 {
+    lerp_level(self.from, self.to, self.elapsed, self.frames)
+  }
+
+  /// If the fade has reached its final level.
+  pub fn is_done(&self) -> bool {
+    self.elapsed >= self.frames
+  }
+
+  /// Writes the current coefficients and moves on a frame. Call once per
+  /// frame, during VBlank.
+  pub fn step(&mut self) {
+    let eva = self.eva();
+    alpha_blend(self.top, self.bottom, eva, MAX_COEFFICIENT - eva);
+    if self.elapsed < self.frames {
+      self.elapsed += 1;
+    }
+  }
+}
+
+/// Moves from `from` to `to` in proportion to `elapsed / frames`.
+fn lerp_level(from: u16, to: u16, elapsed: u16, frames: u16) -> u16 {
+  if elapsed >= frames {
+    to
+  } else {
+    let delta = to as i32 - from as i32;
+    (from as i32 + crate::bios::div(delta * elapsed as i32, frames as i32)) as u16
+  }
+}
+
+#[test]
+fn test_targets_and_coefficients() {
+  let bg1_backdrop = LayerSet::BG1 | LayerSet::BACKDROP;
+  let effect = ColorSpecialEffect::AlphaBlending;
+  let setting = ColorEffectSetting::from_targets(effect, bg1_backdrop, LayerSet::ALL);
+  assert_eq!(setting.0, 0b0011_1111_0110_0010);
+  assert!(setting.backdrop_1st_target_pixel() && setting.backdrop_2nd_target_pixel());
+  assert_eq!(setting.first_targets(), bg1_backdrop);
+  assert_eq!(setting.second_targets(), LayerSet::ALL);
+  assert_eq!(LayerSet::from_bits(0xFFFF), LayerSet::ALL);
+  assert_eq!(AlphaBlendingSetting::from_coefficients(20, 3).0, 16 | 3 << 8);
+  let fade = BrightnessFade::new(ColorSpecialEffect::BrightnessDecrease, LayerSet::ALL, 99, 0, 4);
+  assert_eq!(fade.level(), MAX_COEFFICIENT);
+  assert_eq!(lerp_level(16, 0, 1, 4), 12);
+  assert_eq!(lerp_level(0, 16, 3, 4), 12);
+  assert_eq!(lerp_level(0, 16, 9, 4), 16);
+}
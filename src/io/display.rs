@@ -159,3 +159,88 @@ impl MosaicSetting {
     obj_vertical_inc: 12-15,
   }
 }
+
+newtype! {
+  /// A set of display layers, for the window and blending settings.
+  ///
+  /// * 0-3: bg0 through bg3
+  /// * 4: Objects
+  /// * 5: The backdrop (only used for blending, windows ignore it)
+  ///
+  /// Sets can be combined with `|`.
+  ///
+  /// ```no_run
+  /// const HUD_LAYERS: LayerSet = LayerSet::BG0.union(LayerSet::OBJ);
+  /// ```
+  #[derive(Hash)]
+  LayerSet, u16
+}
+
+impl LayerSet {
+  phantom_fields! {
+    self.0: u16,
+    bg0: 0,
+    bg1: 1,
+    bg2: 2,
+    bg3: 3,
+    obj: 4,
+    backdrop: 5,
+  }
+
+  /// No layers.
+  pub const NONE: Self = Self(0);
+  /// Just bg0.
+  pub const BG0: Self = Self(1 << 0);
+  /// Just bg1.
+  pub const BG1: Self = Self(1 << 1);
+  /// Just bg2.
+  pub const BG2: Self = Self(1 << 2);
+  /// Just bg3.
+  pub const BG3: Self = Self(1 << 3);
+  /// Just the objects.
+  pub const OBJ: Self = Self(1 << 4);
+  /// Just the backdrop.
+  pub const BACKDROP: Self = Self(1 << 5);
+  /// All four backgrounds.
+  pub const ALL_BGS: Self = Self(0b1111);
+  /// All four backgrounds, the objects, and the backdrop.
+  pub const ALL: Self = Self(0b11_1111);
+
+  /// Makes a set from raw bits, ignoring any bits above 5.
+  pub const fn from_bits(bits: u16) -> Self {
+    Self(bits & 0b11_1111)
+  }
+
+  /// The layers in either set.
+  pub const fn union(self, other: Self) -> Self {
+    Self(self.0 | other.0)
+  }
+
+  /// The layers in both sets.
+  pub const fn intersection(self, other: Self) -> Self {
+    Self(self.0 & other.0)
+  }
+
+  /// If every layer in `other` is also in this set.
+  pub const fn contains(self, other: Self) -> bool {
+    self.0 & other.0 == other.0
+  }
+
+  /// The raw bits of the set.
+  pub const fn bits(self) -> u16 {
+    self.0
+  }
+}
+
+impl core::ops::BitOr for LayerSet {
+  type Output = Self;
+  fn bitor(self, other: Self) -> Self {
+    self.union(other)
+  }
+}
+
+impl core::ops::BitOrAssign for LayerSet {
+  fn bitor_assign(&mut self, other: Self) {
+    *self = self.union(other);
+  }
+}
@@ -0,0 +1,317 @@
+//! Module for a display configuration that's checked against the mode.
+//!
+//! `DisplayControlSetting` will take any combination of bits, even ones that
+//! make no sense, like turning on BG0 in Mode 3 or picking frame 1 in Mode 0.
+//! A `DisplayConfig` carries its video mode in its type, and only offers the
+//! layers and options that the mode has:
+//!
+//! | Mode | BG0  | BG1  | BG2    | BG3    | Frame 1 |
+//! |------|------|------|--------|--------|---------|
+//! | 0    | text | text | text   | text   | no      |
+//! | 1    | text | text | affine | -      | no      |
+//! | 2    | -    | -    | affine | affine | no      |
+//! | 3    | -    | -    | bitmap | -      | no      |
+//! | 4    | -    | -    | bitmap | -      | yes     |
+//! | 5    | -    | -    | bitmap | -      | yes     |
+//!
+//! The layer handles work the same way: a `TextBg` can only be made for a
+//! text layer of the mode and only has scroll offsets, while an `AffineBg`
+//! can only be made for an affine layer and only has affine parameters.
+//!
+//! Everything is `const`, and `apply` is a single write to `DISPCNT`.
+//!
+//! ```no_run
+//! const CONFIG: DisplayConfig<Mode1> =
+//!   DisplayConfig::new().with_text_bg::<0>(true).with_affine_bg::<2>(true).with_obj(true);
+//! CONFIG.apply();
+//! ```
+//!
+//! ```compile_fail
+//! # use gba::io::display_config::*;
+//! // BG3 isn't a text layer in Mode 1.
+//! let config = DisplayConfig::<Mode1>::new().with_text_bg::<3>(true);
+//! ```
+
+use super::*;
+use crate::io::{
+  background::{
+    set_bg2_affine_params, set_bg3_affine_params, AffineBgParams, Background,
+    BackgroundControlSetting, BG2CNT, BG3CNT,
+  },
+  display::{DisplayControlSetting, DisplayMode, DISPCNT},
+};
+use core::marker::PhantomData;
+
+mod sealed {
+  pub trait Sealed {}
+}
+
+/// A video mode, for use as the type parameter of `DisplayConfig`.
+pub trait VideoMode: sealed::Sealed {
+  /// The mode's value for `DisplayControlSetting`.
+  const MODE: DisplayMode;
+}
+
+/// Video modes where layer `N` is a text background.
+pub trait TextLayer<const N: usize>: VideoMode {}
+
+/// Video modes where layer `N` is an affine background.
+pub trait AffineLayer<const N: usize>: VideoMode {}
+
+/// Video modes where BG2 is a bitmap.
+pub trait BitmapMode: VideoMode {}
+
+/// Bitmap modes with two pages to flip between.
+pub trait PageFlipMode: BitmapMode {}
+
+macro_rules! video_modes {
+  ($($(#[$attr:meta])* $name:ident,)*) => {
+    $(
+      $(#[$attr])*
+      #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+      pub struct $name;
+      impl sealed::Sealed for $name {}
+      impl VideoMode for $name {
+        const MODE: DisplayMode = DisplayMode::$name;
+      }
+    )*
+  };
+}
+
+video_modes! {
+  /// Four text backgrounds.
+  Mode0,
+  /// Two text backgrounds and one affine background.
+  Mode1,
+  /// Two affine backgrounds.
+  Mode2,
+  /// One full screen 16bpp bitmap.
+  Mode3,
+  /// Two full screen 8bpp bitmap pages.
+  Mode4,
+  /// Two 160x128 16bpp bitmap pages.
+  Mode5,
+}
+
+impl TextLayer<0> for Mode0 {}
+impl TextLayer<1> for Mode0 {}
+impl TextLayer<2> for Mode0 {}
+impl TextLayer<3> for Mode0 {}
+impl TextLayer<0> for Mode1 {}
+impl TextLayer<1> for Mode1 {}
+impl AffineLayer<2> for Mode1 {}
+impl AffineLayer<2> for Mode2 {}
+impl AffineLayer<3> for Mode2 {}
+impl BitmapMode for Mode3 {}
+impl BitmapMode for Mode4 {}
+impl BitmapMode for Mode5 {}
+impl PageFlipMode for Mode4 {}
+impl PageFlipMode for Mode5 {}
+
+/// A display control setting that only allows what the mode `M` supports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DisplayConfig<M: VideoMode> {
+  setting: DisplayControlSetting,
+  mode: PhantomData<M>,
+}
+
+impl<M: VideoMode> DisplayConfig<M> {
+  /// Makes a config for the mode with every layer off.
+  pub const fn new() -> Self {
+    Self { setting: DisplayControlSetting::new().with_mode(M::MODE), mode: PhantomData }
+  }
+
+  const fn with_setting(self, setting: DisplayControlSetting) -> Self {
+    Self { setting, mode: PhantomData }
+  }
+
+  /// The setting that `apply` writes.
+  pub const fn setting(&self) -> DisplayControlSetting {
+    self.setting
+  }
+
+  /// Writes the setting to `DISPCNT`.
+  pub fn apply(&self) {
+    DISPCNT.write(self.setting);
+  }
+
+  /// Shows or hides the object layer.
+  pub const fn with_obj(self, on: bool) -> Self {
+    self.with_setting(self.setting.with_obj(on))
+  }
+
+  /// Sets 1D (`true`) or 2D (`false`) object tile mapping.
+  pub const fn with_oam_memory_1d(self, on: bool) -> Self {
+    self.with_setting(self.setting.with_oam_memory_1d(on))
+  }
+
+  /// Allows OAM access during HBlank, at the cost of fewer object pixels per
+  /// line.
+  pub const fn with_hblank_interval_free(self, on: bool) -> Self {
+    self.with_setting(self.setting.with_hblank_interval_free(on))
+  }
+
+  /// Blanks the screen, which gives full speed VRAM, OAM and PALRAM access.
+  pub const fn with_force_vblank(self, on: bool) -> Self {
+    self.with_setting(self.setting.with_force_vblank(on))
+  }
+
+  /// Turns window 0 on or off.
+  pub const fn with_win0(self, on: bool) -> Self {
+    self.with_setting(self.setting.with_win0(on))
+  }
+
+  /// Turns window 1 on or off.
+  pub const fn with_win1(self, on: bool) -> Self {
+    self.with_setting(self.setting.with_win1(on))
+  }
+
+  /// Turns the object window on or off.
+  pub const fn with_obj_window(self, on: bool) -> Self {
+    self.with_setting(self.setting.with_obj_window(on))
+  }
+
+  const fn with_bg(self, bg: usize, on: bool) -> Self {
+    self.with_setting(match bg {
+      0 => self.setting.with_bg0(on),
+      1 => self.setting.with_bg1(on),
+      2 => self.setting.with_bg2(on),
+      _ => self.setting.with_bg3(on),
+    })
+  }
+
+  /// Shows or hides a text layer.
+  pub const fn with_text_bg<const N: usize>(self, on: bool) -> Self
+  where
+    M: TextLayer<N>,
+  {
+    self.with_bg(N, on)
+  }
+
+  /// Shows or hides an affine layer.
+  pub const fn with_affine_bg<const N: usize>(self, on: bool) -> Self
+  where
+    M: AffineLayer<N>,
+  {
+    self.with_bg(N, on)
+  }
+
+  /// Makes a handle for a text layer.
+  pub const fn text_bg<const N: usize>(&self, control: BackgroundControlSetting) -> TextBg<N>
+  where
+    M: TextLayer<N>,
+  {
+    TextBg { background: Background::new(control) }
+  }
+
+  /// Makes a handle for an affine layer, with the identity matrix.
+  pub const fn affine_bg<const N: usize>(&self, control: BackgroundControlSetting) -> AffineBg<N>
+  where
+    M: AffineLayer<N>,
+  {
+    AffineBg { control, params: AffineBgParams::IDENTITY }
+  }
+}
+
+impl<M: BitmapMode> DisplayConfig<M> {
+  /// Shows or hides the bitmap (BG2).
+  pub const fn with_bitmap(self, on: bool) -> Self {
+    self.with_bg(2, on)
+  }
+}
+
+impl<M: PageFlipMode> DisplayConfig<M> {
+  /// Picks which page of the bitmap is shown.
+  pub const fn with_frame1(self, on: bool) -> Self {
+    self.with_setting(self.setting.with_frame1(on))
+  }
+}
+
+impl<M: VideoMode> Default for DisplayConfig<M> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// A handle to a layer that's a text background in the current mode.
+///
+/// As with `Background`, changes are made to a RAM copy and written by
+/// `flush`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextBg<const N: usize> {
+  background: Background<N>,
+}
+
+impl<const N: usize> TextBg<N> {
+  /// The control setting that will be written at the next flush.
+  pub const fn control(&self) -> BackgroundControlSetting {
+    self.background.control()
+  }
+
+  /// Replaces the control setting.
+  pub fn set_control(&mut self, control: BackgroundControlSetting) {
+    self.background.set_control(control);
+  }
+
+  /// The current `(x, y)` scroll offset.
+  pub const fn scroll(&self) -> (u16, u16) {
+    self.background.scroll()
+  }
+
+  /// Sets the `(x, y)` scroll offset.
+  pub fn set_scroll(&mut self, x: u16, y: u16) {
+    self.background.set_scroll(x, y);
+  }
+
+  /// Moves the scroll offset by the amount given, wrapping on overflow.
+  pub fn scroll_by(&mut self, dx: i16, dy: i16) {
+    self.background.scroll_by(dx, dy);
+  }
+
+  /// Writes the control setting and scroll offsets to the hardware.
+  pub fn flush(&self) {
+    self.background.flush();
+  }
+}
+
+/// A handle to a layer that's an affine background in the current mode.
+///
+/// Changes are made to a RAM copy and written by `flush`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AffineBg<const N: usize> {
+  control: BackgroundControlSetting,
+  params: AffineBgParams,
+}
+
+impl<const N: usize> AffineBg<N> {
+  /// The control setting that will be written at the next flush.
+  pub const fn control(&self) -> BackgroundControlSetting {
+    self.control
+  }
+
+  /// Replaces the control setting.
+  pub fn set_control(&mut self, control: BackgroundControlSetting) {
+    self.control = control;
+  }
+
+  /// The affine parameters that will be written at the next flush.
+  pub const fn params(&self) -> AffineBgParams {
+    self.params
+  }
+
+  /// Replaces the affine parameters.
+  pub fn set_params(&mut self, params: AffineBgParams) {
+    self.params = params;
+  }
+
+  /// Writes the control setting and affine parameters to the hardware.
+  pub fn flush(&self) {
+    if N == 2 {
+      BG2CNT.write(self.control);
+      set_bg2_affine_params(self.params);
+    } else {
+      BG3CNT.write(self.control);
+      set_bg3_affine_params(self.params);
+    }
+  }
+}
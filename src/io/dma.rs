@@ -435,4 +435,35 @@ impl DMA3 {
       );
     }
   }
+
+  /// Copies `count` slots from `src` to `dest`.
+  ///
+  /// # Safety
+  ///
+  /// Both pointers must be aligned, all positions specified for reading must
+  /// be valid for reading, and all positions specified for writing must be
+  /// valid for writing.
+  #[inline(always)]
+  pub unsafe fn copy32(src: *const u32, dest: *mut u32, count: u16) {
+    const COPY_CONTROL: DMAControlSetting =
+      DMAControlSetting::new().with_use_32bit(true).with_enabled(true);
+    // TODO: destination checking against SRAM
+    crate::sync::memory_read_hint(src);
+    Self::DMA3SAD.write(src);
+    Self::DMA3DAD.write(dest);
+    Self::DMA3CNT_L.write(count);
+    Self::DMA3CNT_H.write(COPY_CONTROL);
+    crate::sync::memory_write_hint(dest);
+
+    // Note(Lokathor): See `fill32` for why these are here.
+    #[cfg(target_arch = "arm")]
+    {
+      asm!("
+          NOP
+          NOP
+      ",
+        options(nomem, nostack)
+      );
+    }
+  }
 }
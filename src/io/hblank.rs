@@ -0,0 +1,241 @@
+//! Module for per-scanline register effects driven by HBlank DMA.
+//!
+//! An `HBlankEffect` holds a table with one value per scanline. DMA0 is set
+//! to repeat at each HBlank and copy the next table entry into a register, so
+//! each line of the frame is drawn with its own value. That's how you get
+//! wavy water (per-line scroll), parallax bands (a different scroll for each
+//! band of lines), gradient backdrops (per-line backdrop color), and so on.
+//!
+//! HBlank DMA only fires for the visible lines, and the DMA for a line's HBlank
+//! sets the value used for the _next_ line. So each frame, during VBlank, the
+//! effect writes entry 0 directly and restarts the DMA at entry 1. The HBlank
+//! of the last line copies entry 0 again, which stays in the register over
+//! VBlank.
+//!
+//! The tables are double buffered: fill in `back_table` during the frame,
+//! then `present` it during VBlank.
+//!
+//! DMA0 reads the tables straight out of the effect, so the effect has to be
+//! pinned before it can run, with `core::pin::pin!` or `Pin::static_mut`.
+//!
+//! ```no_run
+//! let mut water = pin!(HBlankEffect::for_register(BG0HOFS, 0));
+//! let mut phase = 0_u16;
+//! loop {
+//!   hblank::fill_wave(water.as_mut().back_table(), 0, 4, phase, 0x200);
+//!   phase = phase.wrapping_add(0x180);
+//!   bios::vblank_interrupt_wait();
+//!   water.as_mut().present();
+//! }
+//! ```
+//!
+//! Only one effect can run at once, because they all use DMA0.
+
+use super::*;
+use crate::{
+  fixed_point,
+  io::{
+    background::{AffineBgParams, BG2PA, BG3PA},
+    dma::{DMAControlSetting, DMADestAddressControl, DMAStartTiming, DMA0},
+  },
+  palram::PALRAM_BG,
+};
+use core::{
+  marker::PhantomPinned,
+  mem::{align_of, size_of},
+  pin::Pin,
+};
+
+/// The number of visible scanlines, which is the number of table entries.
+pub const SCANLINE_COUNT: usize = 160;
+
+/// A per-scanline table of register values, copied in by HBlank DMA.
+///
+/// While the effect is running, DMA0 reads straight out of this value, so
+/// the methods that start the DMA take it pinned. Pinning keeps it in place
+/// until it's dropped, and dropping it stops the DMA.
+///
+/// DMA0 can only read from IWRAM and EWRAM, so don't put this in ROM.
+pub struct HBlankEffect<T: Copy> {
+  tables: [Table<T>; 2],
+  front: usize,
+  dest: usize,
+  control: DMAControlSetting,
+  count: u16,
+  _pinned: PhantomPinned,
+}
+
+/// One table, with an extra entry after the last line for the DMA of the last
+/// line's HBlank to read.
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct Table<T> {
+  lines: [T; SCANLINE_COUNT],
+  end: T,
+}
+
+impl<T: Copy> HBlankEffect<T> {
+  /// Makes an effect that writes to the address given, with both tables
+  /// filled with `initial`.
+  ///
+  /// Nothing happens until you pin it and call `restart` or `present`.
+  ///
+  /// # Safety
+  ///
+  /// `dest` must be the address of a register (or group of registers) that's
+  /// safe to write a `T` to.
+  ///
+  /// ## Panics
+  ///
+  /// If `T` isn't a whole number of halfwords, or isn't at least halfword
+  /// aligned.
+  pub unsafe fn new(dest: usize, initial: T) -> Self {
+    let (size, align) = (size_of::<T>(), align_of::<T>());
+    assert!(size != 0 && size % 2 == 0 && align >= 2);
+    let use_32bit = size % 4 == 0 && align >= 4;
+    let count = (if use_32bit { size / 4 } else { size / 2 }) as u16;
+    let control = DMAControlSetting::new()
+      .with_dest_address_control(if count == 1 {
+        DMADestAddressControl::Fixed
+      } else {
+        DMADestAddressControl::IncrementReload
+      })
+      .with_dma_repeat(true)
+      .with_use_32bit(use_32bit)
+      .with_start_time(DMAStartTiming::HBlank)
+      .with_enabled(true);
+    let table = Table { lines: [initial; SCANLINE_COUNT], end: initial };
+    Self { tables: [table; 2], front: 0, dest, control, count, _pinned: PhantomPinned }
+  }
+
+  /// Makes an effect that writes to a register.
+  pub fn for_register<R>(register: VolAddress<T, R, Safe>, initial: T) -> Self {
+    unsafe { Self::new(register.as_usize(), initial) }
+  }
+
+  /// The table to fill in for the next frame.
+  pub fn back_table(self: Pin<&mut Self>) -> &mut [T; SCANLINE_COUNT] {
+    // Safety: the tables are written in place, never moved out.
+    let this = unsafe { self.get_unchecked_mut() };
+    &mut this.tables[1 - this.front].lines
+  }
+
+  /// The table that's currently being shown.
+  pub fn front_table(&self) -> &[T; SCANLINE_COUNT] {
+    &self.tables[self.front].lines
+  }
+
+  /// Swaps the tables and restarts the DMA, showing the back table.
+  ///
+  /// Call this during VBlank. The new back table holds whatever was shown
+  /// two frames ago.
+  pub fn present(mut self: Pin<&mut Self>) {
+    // Safety: only the index changes.
+    let this = unsafe { self.as_mut().get_unchecked_mut() };
+    this.front = 1 - this.front;
+    self.restart();
+  }
+
+  /// Restarts the DMA with the front table.
+  ///
+  /// Call this during VBlank each frame that you don't `present`.
+  pub fn restart(self: Pin<&mut Self>) {
+    // Safety: the table is written in place, and being pinned it stays where
+    // the DMA reads it until the effect is dropped, which stops the DMA.
+    let this = unsafe { self.get_unchecked_mut() };
+    let table = &mut this.tables[this.front];
+    table.end = table.lines[0];
+    unsafe {
+      DMA0::set_control(DMAControlSetting::new());
+      (this.dest as *mut T).write_volatile(table.lines[0]);
+      DMA0::set_source(&table.lines[1] as *const T as *const u32);
+      DMA0::set_dest(this.dest as *mut u32);
+      DMA0::set_count(this.count);
+      DMA0::set_control(this.control);
+    }
+  }
+
+  /// Stops the DMA, leaving the register with the last value written.
+  pub fn stop(&self) {
+    unsafe { DMA0::set_control(DMAControlSetting::new()) };
+  }
+}
+
+impl HBlankEffect<AffineBgParams> {
+  /// Makes an effect that sets all of BG2's affine parameters each line.
+  pub fn bg2_affine() -> Self {
+    unsafe { Self::new(BG2PA.as_usize(), AffineBgParams::IDENTITY) }
+  }
+
+  /// Makes an effect that sets all of BG3's affine parameters each line.
+  pub fn bg3_affine() -> Self {
+    unsafe { Self::new(BG3PA.as_usize(), AffineBgParams::IDENTITY) }
+  }
+}
+
+impl HBlankEffect<Color> {
+  /// Makes an effect that sets the backdrop color each line.
+  pub fn backdrop(initial: Color) -> Self {
+    Self::for_register(PALRAM_BG.index(0), initial)
+  }
+}
+
+impl<T: Copy> Drop for HBlankEffect<T> {
+  fn drop(&mut self) {
+    self.stop();
+  }
+}
+
+/// Fills a table with a sine wave around `base`, for wavy scroll effects.
+///
+/// Line `y` gets `base + amplitude * sin(phase + y * step)`, with angles in
+/// `fixed_point::sin` units (`0x10000` is a full turn). Move `phase` each
+/// frame to animate the wave.
+pub fn fill_wave(
+  table: &mut [u16; SCANLINE_COUNT], base: u16, amplitude: i16, phase: u16, step: u16,
+) {
+  let mut angle = phase;
+  for entry in table.iter_mut() {
+    let offset = (amplitude as i32 * fixed_point::sin(angle).into_raw()) >> 12;
+    *entry = base.wrapping_add(offset as u16);
+    angle = angle.wrapping_add(step);
+  }
+}
+
+/// Fills a table with bands of values, for parallax.
+///
+/// Each band is `(first_line, value)`, and runs until the next band's first
+/// line. Bands must be in order of first line. Lines before the first band
+/// are left as they were.
+pub fn fill_bands<T: Copy>(table: &mut [T; SCANLINE_COUNT], bands: &[(usize, T)]) {
+  for (i, &(first_line, value)) in bands.iter().enumerate() {
+    let end = bands.get(i + 1).map(|&(next, _)| next).unwrap_or(SCANLINE_COUNT);
+    let start = first_line.min(SCANLINE_COUNT);
+    for entry in table[start..end.clamp(start, SCANLINE_COUNT)].iter_mut() {
+      *entry = value;
+    }
+  }
+}
+
+/// Fills a table with a vertical gradient from `top` to `bottom`.
+pub fn fill_gradient(table: &mut [Color; SCANLINE_COUNT], top: Color, bottom: Color) {
+  for (y, entry) in table.iter_mut().enumerate() {
+    *entry = top.lerp(bottom, (y * 32 / (SCANLINE_COUNT - 1)) as u8);
+  }
+}
+
+#[test]
+fn test_fill_tables() {
+  let mut table = [7_u16; SCANLINE_COUNT];
+  fill_wave(&mut table, 0, 4, 0, 0x4000);
+  assert_eq!(table[..5], [0, 4, 0, 0xFFFC, 0]);
+  table = [7; SCANLINE_COUNT];
+  fill_bands(&mut table, &[(10, 1), (100, 2), (200, 3)]);
+  assert!(table[..10].iter().all(|&entry| entry == 7));
+  assert!(table[10..100].iter().all(|&entry| entry == 1));
+  assert!(table[100..].iter().all(|&entry| entry == 2));
+  let mut colors = [Color::BLACK; SCANLINE_COUNT];
+  fill_gradient(&mut colors, Color::WHITE, Color::BLACK);
+  assert_eq!(colors[0], Color::WHITE);
+  assert_eq!(colors[SCANLINE_COUNT - 1], Color::BLACK);
+}
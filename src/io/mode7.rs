@@ -0,0 +1,253 @@
+//! Module for "Mode 7" style perspective floors on affine backgrounds.
+//!
+//! An affine background can only be rotated and scaled as a whole, but if the
+//! affine parameters change every scanline (see `io::hblank`) each line can be
+//! scaled differently. Scaling lines further up the screen down more gives a
+//! floor that stretches off to the horizon.
+//!
+//! A `Mode7Camera` computes those per-line parameters, and projects points
+//! on the floor back to the screen so that objects can be placed (and scaled)
+//! to match. This works with BG2 in Mode 1, or BG2 and BG3 in Mode 2.
+//!
+//! World positions are in background pixels (as `fx24_8`), with `x` going
+//! right and `z` going down the background's map. Angles are in
+//! `fixed_point::sin` units, where `0x10000` is a full turn.
+//!
+//! ```no_run
+//! let mut floor = pin!(HBlankEffect::bg2_affine());
+//! let mut camera = Mode7Camera::new();
+//! loop {
+//!   camera.yaw = camera.yaw.wrapping_add(0x80);
+//!   camera.fill_table(floor.as_mut().back_table(), Mode7Camera::SKY);
+//!   bios::vblank_interrupt_wait();
+//!   floor.as_mut().present();
+//! }
+//! ```
+
+use super::*;
+use crate::{
+  bios,
+  fixed_point::{self, fx24_8, fx8_8},
+  io::{background::AffineBgParams, hblank::SCANLINE_COUNT},
+};
+
+/// The column at the center of the screen.
+const CENTER_COL: i32 = 120;
+
+/// A point on the screen that a world position projects to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mode7Projection {
+  /// The screen column.
+  pub x: i32,
+  /// The screen row.
+  pub y: i32,
+  /// How many world pixels one screen pixel covers at that depth.
+  ///
+  /// This is what to use for an object's affine scale, so that it shrinks
+  /// with distance the same way as the floor.
+  pub scale: fx8_8,
+}
+
+/// A camera looking at a perspective floor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mode7Camera {
+  /// The camera's position across the floor.
+  pub x: fx24_8,
+  /// How far above the floor the camera is.
+  pub height: fx24_8,
+  /// The camera's position along the floor.
+  pub z: fx24_8,
+  /// Which way the camera faces. At 0 it faces towards `-z` (up the map).
+  pub yaw: u16,
+  /// How far the camera tilts down from level.
+  pub pitch: u16,
+  /// The screen row that the camera's view direction passes through.
+  ///
+  /// With no pitch this is where the horizon is, tilting the camera down
+  /// moves the horizon up from here.
+  pub horizon: i32,
+  /// The distance from the eye to the screen, in pixels. Use `set_fov` to
+  /// set this from an angle.
+  pub focal_length: i32,
+}
+
+impl Mode7Camera {
+  /// Affine parameters for lines above the horizon that show nothing, as
+  /// long as the background doesn't have wraparound on.
+  pub const SKY: AffineBgParams = AffineBgParams {
+    pa: fx8_8::from_raw(0),
+    pb: fx8_8::from_raw(0),
+    pc: fx8_8::from_raw(0),
+    pd: fx8_8::from_raw(0),
+    x: fx24_8::from_raw(-1 << 8),
+    y: fx24_8::from_raw(-1 << 8),
+  };
+
+  /// Makes a camera at the origin, 32 pixels up, with the horizon at row 40
+  /// and a field of view of about 90 degrees.
+  pub const fn new() -> Self {
+    Self {
+      x: fx24_8::from_raw(0),
+      height: fx24_8::from_raw(32 << 8),
+      z: fx24_8::from_raw(0),
+      yaw: 0,
+      pitch: 0,
+      horizon: 40,
+      focal_length: 120,
+    }
+  }
+
+  /// Sets the focal length from the horizontal field of view.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` (and changes nothing) if the angle isn't between 0 and a
+  /// half turn (`0x8000`), exclusive.
+  pub fn set_fov(&mut self, angle: u16) -> Option<()> {
+    if angle == 0 || angle >= 0x8000 {
+      return None;
+    }
+    let (sin, cos) = sin_cos(angle / 2);
+    self.focal_length = bios::div(CENTER_COL * cos as i32, sin as i32);
+    Some(())
+  }
+
+  /// Moves the camera relative to the way it's facing.
+  ///
+  /// Positive `forward` moves the way the camera faces, positive `right`
+  /// strafes to the right.
+  pub fn move_local(&mut self, forward: fx24_8, right: fx24_8) {
+    let (sin, cos) = sin_cos(self.yaw);
+    let (forward, right) = (forward.into_raw() as i64, right.into_raw() as i64);
+    self.x = fx24_8::from_raw(self.x.into_raw() + ((forward * sin + right * cos) >> 12) as i32);
+    self.z = fx24_8::from_raw(self.z.into_raw() + ((right * sin - forward * cos) >> 12) as i32);
+  }
+
+  /// The affine parameters for one scanline, or `None` if the line is at or
+  /// above the horizon.
+  pub fn scanline_params(&self, line: usize) -> Option<AffineBgParams> {
+    self.line_params(line, &self.view())
+  }
+
+  /// Fills an HBlank table with the parameters for every scanline, using
+  /// `sky` for lines at or above the horizon.
+  pub fn fill_table(&self, table: &mut [AffineBgParams; SCANLINE_COUNT], sky: AffineBgParams) {
+    let view = self.view();
+    for (line, entry) in table.iter_mut().enumerate() {
+      *entry = self.line_params(line, &view).unwrap_or(sky);
+    }
+  }
+
+  /// The first screen row that shows floor.
+  pub fn horizon_line(&self) -> i32 {
+    let (pitch_sin, pitch_cos) = sin_cos(self.pitch);
+    if pitch_cos == 0 {
+      return 0;
+    }
+    // The first row where the ray points down: `dy * cos + f * sin > 0`.
+    let edge = -bios::div(self.focal_length * pitch_sin as i32, pitch_cos as i32);
+    self.horizon + edge + 1
+  }
+
+  /// Projects a world position (with `y` as the height above the floor) to
+  /// the screen.
+  ///
+  /// Gives `None` if the point is behind the camera. The result may still be
+  /// off screen.
+  pub fn project(&self, x: fx24_8, y: fx24_8, z: fx24_8) -> Option<Mode7Projection> {
+    let view = self.view();
+    let dx = (x.into_raw() - self.x.into_raw()) as i64;
+    let dz = (z.into_raw() - self.z.into_raw()) as i64;
+    let down = (self.height.into_raw() - y.into_raw()) as i64;
+    // World to camera space, all in .8 pixels.
+    let lateral = (dx * view.yaw_cos + dz * view.yaw_sin) >> 12;
+    let forward = (dx * view.yaw_sin - dz * view.yaw_cos) >> 12;
+    let depth = (forward * view.pitch_cos + down * view.pitch_sin) >> 12;
+    let screen_down = (down * view.pitch_cos - forward * view.pitch_sin) >> 12;
+    if depth < (1 << 8) {
+      return None;
+    }
+    let (depth, focal) = (depth as i32, self.focal_length);
+    Some(Mode7Projection {
+      x: CENTER_COL + bios::div(lateral as i32 * focal, depth),
+      y: self.horizon + bios::div(screen_down as i32 * focal, depth),
+      scale: fx8_8::from_raw(clamp_i16(bios::div(depth, focal))),
+    })
+  }
+
+  fn view(&self) -> View {
+    let (yaw_sin, yaw_cos) = sin_cos(self.yaw);
+    let (pitch_sin, pitch_cos) = sin_cos(self.pitch);
+    View { yaw_sin, yaw_cos, pitch_sin, pitch_cos }
+  }
+
+  fn line_params(&self, line: usize, view: &View) -> Option<AffineBgParams> {
+    let dy = line as i64 - self.horizon as i64;
+    let focal = self.focal_length as i64;
+    // The ray through this line, rotated by the pitch, in .12 pixels.
+    let down = dy * view.pitch_cos + focal * view.pitch_sin;
+    let forward = focal * view.pitch_cos - dy * view.pitch_sin;
+    let down_8 = (down >> 4) as i32;
+    if down_8 <= 0 {
+      return None;
+    }
+    // World pixels per screen pixel on this line (.12), and the distance
+    // along the floor to the center of the line (.8). The height is widened
+    // first, a .20 height overflows 32 bits past 2048 pixels.
+    let lambda = ((self.height.into_raw() as i64) << 12) / down_8 as i64;
+    let distance = (lambda * forward) >> 16;
+    let pa = (lambda * view.yaw_cos) >> 16;
+    let pc = (lambda * view.yaw_sin) >> 16;
+    // Step back from the center of the line to its left edge.
+    let left = CENTER_COL as i64 * lambda;
+    let x =
+      self.x.into_raw() as i64 + ((distance * view.yaw_sin) >> 12) - ((left * view.yaw_cos) >> 16);
+    let y =
+      self.z.into_raw() as i64 - ((distance * view.yaw_cos) >> 12) - ((left * view.yaw_sin) >> 16);
+    Some(AffineBgParams {
+      pa: fx8_8::from_raw(clamp_i16(pa as i32)),
+      pb: fx8_8::from_raw(0),
+      pc: fx8_8::from_raw(clamp_i16(pc as i32)),
+      pd: fx8_8::from_raw(0),
+      x: fx24_8::from_raw(x as i32),
+      y: fx24_8::from_raw(y as i32),
+    })
+  }
+}
+
+impl Default for Mode7Camera {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// The trig values for a view, as .12 fixed point.
+struct View {
+  yaw_sin: i64,
+  yaw_cos: i64,
+  pitch_sin: i64,
+  pitch_cos: i64,
+}
+
+fn sin_cos(angle: u16) -> (i64, i64) {
+  (fixed_point::sin(angle).into_raw() as i64, fixed_point::cos(angle).into_raw() as i64)
+}
+
+fn clamp_i16(value: i32) -> i16 {
+  value.clamp(i16::MIN as i32, i16::MAX as i32) as i16
+}
+
+#[test]
+fn test_line_params() {
+  let mut camera = Mode7Camera::new();
+  assert_eq!(camera.scanline_params(40), None);
+  // Line 100 is 60 rows below the horizon, so it's 120 * 32 / 60 = 64 pixels
+  // ahead and each screen pixel covers 32 / 60 of a world pixel.
+  let params = camera.scanline_params(100).unwrap();
+  assert_eq!(params.pa, fx8_8::from_raw(136));
+  assert_eq!(params.pc, fx8_8::from_raw(0));
+  assert_eq!(params.x, fx24_8::from_raw(-16380));
+  assert_eq!(params.y, fx24_8::from_raw(-16380));
+  camera.height = fx24_8::from_raw(4096 << 8);
+  assert_eq!(camera.scanline_params(100).unwrap().pa, fx8_8::from_raw(17476));
+}
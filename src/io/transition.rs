@@ -0,0 +1,571 @@
+//! Module for screen transitions between scenes.
+//!
+//! Each transition runs for a number of frames and is driven one frame at a
+//! time: call `step` once per frame, during VBlank, and it writes that frame's
+//! registers. A transition of `frames` frames takes `frames + 1` steps, the
+//! first showing where it starts and the last where it ends. The step that
+//! shows the end also calls the completion callback, if there is one, which
+//! is a good place to load the next scene.
+//!
+//! * `Fade` fades layers to or from black or white, with `BLDCNT` and `BLDY`.
+//! * `MosaicDissolve` grows or shrinks the mosaic size, with `MOSAIC`.
+//! * `IrisWipe` opens or closes a circle, and `BlindsWipe` opens or closes
+//!   horizontal slats. Both set `WIN0H` for each line with an `HBlankEffect`,
+//!   showing the scene inside window 0 and nothing outside. Like the effect,
+//!   they have to be pinned to run.
+//! * `Slide` scrolls a background from one offset to another.
+//!
+//! ```no_run
+//! let mut wipe = pin!(IrisWipe::close(120, 80, LayerSet::ALL, 40).with_on_complete(next_scene));
+//! transition::run(&mut wipe);
+//! ```
+//!
+//! The window wipes use DMA0, so they can't run at the same time as another
+//! `HBlankEffect`.
+
+use super::*;
+use crate::{
+  bios,
+  color::isqrt,
+  io::{
+    background::Background,
+    color_blend::{fade_layers, ColorSpecialEffect, MAX_COEFFICIENT},
+    display::{LayerSet, MosaicSetting, MOSAIC},
+    hblank::{HBlankEffect, SCANLINE_COUNT},
+    window::{HorizontalWindowSetting, Outside, Window0, WindowConfig, WindowRect, WIN0H},
+  },
+};
+use core::pin::Pin;
+
+/// A transition that's shown one frame at a time.
+pub trait Transition {
+  /// Writes the registers for the current frame and moves on a frame. Call
+  /// once per frame, during VBlank.
+  ///
+  /// Gives `true` once the last frame has been shown. Steps after that show
+  /// the last frame again.
+  fn step(&mut self) -> bool;
+
+  /// If the last frame has been shown.
+  fn is_done(&self) -> bool;
+}
+
+/// Steps a transition once per VBlank until it's done.
+///
+/// The VBlank interrupt must be enabled, see `bios::vblank_interrupt_wait`.
+pub fn run<T: Transition + ?Sized>(transition: &mut T) {
+  loop {
+    bios::vblank_interrupt_wait();
+    if transition.step() {
+      break;
+    }
+  }
+}
+
+/// How far along a transition is, and what to call when it's done.
+#[derive(Debug, Clone, Copy)]
+struct Timeline {
+  frames: u16,
+  elapsed: u16,
+  done: bool,
+  on_complete: Option<fn()>,
+}
+
+impl Timeline {
+  const fn new(frames: u16) -> Self {
+    Self { frames, elapsed: 0, done: false, on_complete: None }
+  }
+
+  /// Moves from `from` to `to` in proportion to how far along this is.
+  ///
+  /// This is in 64 bits, since a full `u16` range over a long transition
+  /// overflows 32.
+  fn lerp(&self, from: i32, to: i32) -> i32 {
+    if self.elapsed >= self.frames {
+      to
+    } else {
+      let distance = to as i64 - from as i64;
+      (from as i64 + distance * self.elapsed as i64 / self.frames as i64) as i32
+    }
+  }
+
+  /// Moves on a frame, calling the callback if that finished it.
+  fn advance(&mut self) -> bool {
+    if self.elapsed < self.frames {
+      self.elapsed += 1;
+    } else if !self.done {
+      self.done = true;
+      if let Some(on_complete) = self.on_complete {
+        on_complete();
+      }
+    }
+    self.done
+  }
+}
+
+/// Fades layers towards black or white.
+#[derive(Debug, Clone, Copy)]
+pub struct Fade {
+  effect: ColorSpecialEffect,
+  layers: LayerSet,
+  from: u16,
+  to: u16,
+  timeline: Timeline,
+}
+
+impl Fade {
+  /// Fades some layers from one brightness level to another.
+  ///
+  /// `effect` should be `BrightnessIncrease` or `BrightnessDecrease`. Levels
+  /// are clamped to 16.
+  pub const fn new(
+    effect: ColorSpecialEffect, layers: LayerSet, from: u16, to: u16, frames: u16,
+  ) -> Self {
+    let from = if from > MAX_COEFFICIENT { MAX_COEFFICIENT } else { from };
+    let to = if to > MAX_COEFFICIENT { MAX_COEFFICIENT } else { to };
+    Self { effect, layers, from, to, timeline: Timeline::new(frames) }
+  }
+
+  /// Fades everything out to black.
+  pub const fn to_black(frames: u16) -> Self {
+    Self::new(ColorSpecialEffect::BrightnessDecrease, LayerSet::ALL, 0, MAX_COEFFICIENT, frames)
+  }
+
+  /// Fades everything in from black.
+  pub const fn from_black(frames: u16) -> Self {
+    Self::new(ColorSpecialEffect::BrightnessDecrease, LayerSet::ALL, MAX_COEFFICIENT, 0, frames)
+  }
+
+  /// Fades everything out to white.
+  pub const fn to_white(frames: u16) -> Self {
+    Self::new(ColorSpecialEffect::BrightnessIncrease, LayerSet::ALL, 0, MAX_COEFFICIENT, frames)
+  }
+
+  /// Fades everything in from white.
+  pub const fn from_white(frames: u16) -> Self {
+    Self::new(ColorSpecialEffect::BrightnessIncrease, LayerSet::ALL, MAX_COEFFICIENT, 0, frames)
+  }
+
+  /// Sets a function to call when the fade is done.
+  pub const fn with_on_complete(mut self, on_complete: fn()) -> Self {
+    self.timeline.on_complete = Some(on_complete);
+    self
+  }
+
+  /// The level for the current frame.
+  pub fn level(&self) -> u16 {
+    self.timeline.lerp(self.from as i32, self.to as i32) as u16
+  }
+}
+
+impl Transition for Fade {
+  fn step(&mut self) -> bool {
+    fade_layers(self.effect, self.layers, self.level());
+    self.timeline.advance()
+  }
+
+  fn is_done(&self) -> bool {
+    self.timeline.done
+  }
+}
+
+/// The largest mosaic size, where each block is 16 pixels across.
+pub const MAX_MOSAIC_SIZE: u16 = 15;
+
+/// Grows or shrinks the mosaic, so the scene breaks up into blocks or comes
+/// back together.
+///
+/// Only layers with their mosaic flag set are affected:
+/// `BackgroundControlSetting::with_mosaic` for backgrounds, and
+/// `OBJAttr0::with_mosaic` for objects.
+#[derive(Debug, Clone, Copy)]
+pub struct MosaicDissolve {
+  from: u16,
+  to: u16,
+  bg: bool,
+  obj: bool,
+  timeline: Timeline,
+}
+
+impl MosaicDissolve {
+  /// Goes from one mosaic size to another, for backgrounds and objects.
+  ///
+  /// Sizes are the `MosaicSetting` increases, and are clamped to 15.
+  pub const fn new(from: u16, to: u16, frames: u16) -> Self {
+    let from = if from > MAX_MOSAIC_SIZE { MAX_MOSAIC_SIZE } else { from };
+    let to = if to > MAX_MOSAIC_SIZE { MAX_MOSAIC_SIZE } else { to };
+    Self { from, to, bg: true, obj: true, timeline: Timeline::new(frames) }
+  }
+
+  /// Breaks the scene up into the largest blocks.
+  pub const fn to_blocks(frames: u16) -> Self {
+    Self::new(0, MAX_MOSAIC_SIZE, frames)
+  }
+
+  /// Brings the scene back from the largest blocks.
+  pub const fn from_blocks(frames: u16) -> Self {
+    Self::new(MAX_MOSAIC_SIZE, 0, frames)
+  }
+
+  /// Picks whether the background mosaic and the object mosaic change. The
+  /// one that doesn't is left at 0.
+  pub const fn with_layers(self, bg: bool, obj: bool) -> Self {
+    Self { bg, obj, ..self }
+  }
+
+  /// Sets a function to call when the dissolve is done.
+  pub const fn with_on_complete(mut self, on_complete: fn()) -> Self {
+    self.timeline.on_complete = Some(on_complete);
+    self
+  }
+
+  /// The mosaic setting for the current frame.
+  pub fn setting(&self) -> MosaicSetting {
+    let size = self.timeline.lerp(self.from as i32, self.to as i32) as u16;
+    let bg = if self.bg { size } else { 0 };
+    let obj = if self.obj { size } else { 0 };
+    MosaicSetting::new()
+      .with_bg_horizontal_inc(bg)
+      .with_bg_vertical_inc(bg)
+      .with_obj_horizontal_inc(obj)
+      .with_obj_vertical_inc(obj)
+  }
+}
+
+impl Transition for MosaicDissolve {
+  fn step(&mut self) -> bool {
+    MOSAIC.write(self.setting());
+    self.timeline.advance()
+  }
+
+  fn is_done(&self) -> bool {
+    self.timeline.done
+  }
+}
+
+/// A window 0 shape that changes each line, shared by the window wipes.
+struct WindowWipe {
+  effect: HBlankEffect<HorizontalWindowSetting>,
+  layers: LayerSet,
+  started: bool,
+}
+
+impl WindowWipe {
+  fn new(layers: LayerSet) -> Self {
+    Self { effect: HBlankEffect::for_register(WIN0H, EMPTY_SPAN), layers, started: false }
+  }
+
+  /// The effect, which is pinned along with the wipe.
+  fn effect(self: Pin<&mut Self>) -> Pin<&mut HBlankEffect<HorizontalWindowSetting>> {
+    unsafe { self.map_unchecked_mut(|wipe| &mut wipe.effect) }
+  }
+
+  /// Sets up the windows on the first frame, then shows the back table.
+  fn present(mut self: Pin<&mut Self>) {
+    if !self.started {
+      WindowConfig::new(Outside::new(LayerSet::NONE))
+        .with_win0(Window0::new(WindowRect::FULL_SCREEN, self.layers))
+        .apply();
+      // Safety: the flag isn't pinned.
+      unsafe { self.as_mut().get_unchecked_mut() }.started = true;
+    }
+    self.effect().present();
+  }
+
+  fn stop(&self) {
+    self.effect.stop();
+    Window0::disable();
+  }
+}
+
+/// A line that's entirely outside of the window.
+const EMPTY_SPAN: HorizontalWindowSetting = WindowRect::EMPTY.horizontal();
+
+/// A line that's entirely inside of the window.
+const FULL_SPAN: HorizontalWindowSetting = WindowRect::FULL_SCREEN.horizontal();
+
+/// Opens or closes a circle, showing the scene inside it and nothing (the
+/// backdrop) outside.
+///
+/// Window 0 stays on after the wipe is done, so that a closed iris stays
+/// closed. Call `stop` once it's not needed (dropping the wipe only stops the
+/// per-line updates).
+///
+/// `Transition` is implemented for the pinned wipe, `Pin<&mut IrisWipe>`.
+pub struct IrisWipe {
+  wipe: WindowWipe,
+  center: (i32, i32),
+  from: u16,
+  to: u16,
+  timeline: Timeline,
+}
+
+impl IrisWipe {
+  /// Goes from one radius to another, with the circle centered on `(x, y)`.
+  pub fn new(x: i32, y: i32, from: u16, to: u16, layers: LayerSet, frames: u16) -> Self {
+    let timeline = Timeline::new(frames);
+    Self { wipe: WindowWipe::new(layers), center: (x, y), from, to, timeline }
+  }
+
+  /// Opens from nothing until the whole screen shows.
+  pub fn open(x: i32, y: i32, layers: LayerSet, frames: u16) -> Self {
+    Self::new(x, y, 0, Self::covering_radius(x, y), layers, frames)
+  }
+
+  /// Closes from the whole screen down to nothing.
+  pub fn close(x: i32, y: i32, layers: LayerSet, frames: u16) -> Self {
+    Self::new(x, y, Self::covering_radius(x, y), 0, layers, frames)
+  }
+
+  /// The radius that covers the whole screen from `(x, y)`, at most
+  /// `u16::MAX`.
+  fn covering_radius(x: i32, y: i32) -> u16 {
+    let (x, y) = (x as i64, y as i64);
+    let dx = x.max(240 - x).unsigned_abs();
+    let dy = y.max(160 - y).unsigned_abs();
+    let squared = dx * dx + dy * dy;
+    if squared > u32::MAX as u64 {
+      u16::MAX
+    } else {
+      (isqrt(squared as u32) + 1).min(u16::MAX as u32) as u16
+    }
+  }
+
+  /// Sets a function to call when the wipe is done.
+  pub fn with_on_complete(mut self, on_complete: fn()) -> Self {
+    self.timeline.on_complete = Some(on_complete);
+    self
+  }
+
+  /// The radius for the current frame.
+  pub fn radius(&self) -> u16 {
+    self.timeline.lerp(self.from as i32, self.to as i32) as u16
+  }
+
+  /// Stops the per-line updates and turns window 0 off.
+  pub fn stop(&self) {
+    self.wipe.stop();
+  }
+
+  fn wipe(self: Pin<&mut Self>) -> Pin<&mut WindowWipe> {
+    unsafe { self.map_unchecked_mut(|iris| &mut iris.wipe) }
+  }
+}
+
+impl Transition for Pin<&mut IrisWipe> {
+  fn step(&mut self) -> bool {
+    let (x, y) = self.center;
+    let radius = self.radius();
+    let mut wipe = self.as_mut().wipe();
+    fill_iris(wipe.as_mut().effect().back_table(), x, y, radius);
+    wipe.present();
+    // Safety: the timeline isn't pinned.
+    unsafe { self.as_mut().get_unchecked_mut() }.timeline.advance()
+  }
+
+  fn is_done(&self) -> bool {
+    self.timeline.done
+  }
+}
+
+/// Opens or closes horizontal slats, like blinds, showing the scene through
+/// them and nothing (the backdrop) between them.
+///
+/// As with `IrisWipe`, window 0 stays on after the wipe is done until `stop`
+/// is called, and `Transition` is implemented for `Pin<&mut BlindsWipe>`.
+pub struct BlindsWipe {
+  wipe: WindowWipe,
+  slat_height: u8,
+  from: u8,
+  to: u8,
+  timeline: Timeline,
+}
+
+impl BlindsWipe {
+  /// Goes from one number of open rows per slat to another.
+  ///
+  /// A slat height of 0 is treated as 1, and the open rows are clamped to
+  /// the slat height.
+  pub fn new(slat_height: u8, from: u8, to: u8, layers: LayerSet, frames: u16) -> Self {
+    let slat_height = slat_height.max(1);
+    Self {
+      wipe: WindowWipe::new(layers),
+      slat_height,
+      from: from.min(slat_height),
+      to: to.min(slat_height),
+      timeline: Timeline::new(frames),
+    }
+  }
+
+  /// Opens the slats from shut until the whole screen shows.
+  pub fn open(slat_height: u8, layers: LayerSet, frames: u16) -> Self {
+    Self::new(slat_height, 0, slat_height, layers, frames)
+  }
+
+  /// Closes the slats from the whole screen until nothing shows.
+  pub fn close(slat_height: u8, layers: LayerSet, frames: u16) -> Self {
+    Self::new(slat_height, slat_height, 0, layers, frames)
+  }
+
+  /// Sets a function to call when the wipe is done.
+  pub fn with_on_complete(mut self, on_complete: fn()) -> Self {
+    self.timeline.on_complete = Some(on_complete);
+    self
+  }
+
+  /// The number of rows of each slat shown on the current frame.
+  pub fn open_rows(&self) -> u8 {
+    self.timeline.lerp(self.from as i32, self.to as i32) as u8
+  }
+
+  /// Stops the per-line updates and turns window 0 off.
+  pub fn stop(&self) {
+    self.wipe.stop();
+  }
+
+  fn wipe(self: Pin<&mut Self>) -> Pin<&mut WindowWipe> {
+    unsafe { self.map_unchecked_mut(|blinds| &mut blinds.wipe) }
+  }
+}
+
+impl Transition for Pin<&mut BlindsWipe> {
+  fn step(&mut self) -> bool {
+    let (slat_height, open_rows) = (self.slat_height, self.open_rows());
+    let mut wipe = self.as_mut().wipe();
+    fill_blinds(wipe.as_mut().effect().back_table(), slat_height, open_rows);
+    wipe.present();
+    // Safety: the timeline isn't pinned.
+    unsafe { self.as_mut().get_unchecked_mut() }.timeline.advance()
+  }
+
+  fn is_done(&self) -> bool {
+    self.timeline.done
+  }
+}
+
+/// Fills a `WIN0H` table so the window is a circle of `radius` around
+/// `(x, y)`, clipped to the screen.
+pub fn fill_iris(
+  table: &mut [HorizontalWindowSetting; SCANLINE_COUNT], x: i32, y: i32, radius: u16,
+) {
+  // A `u16` radius squared doesn't fit in an `i32`.
+  let radius_squared = radius as i64 * radius as i64;
+  for (line, entry) in table.iter_mut().enumerate() {
+    let dy = line as i64 - y as i64;
+    let remaining = radius_squared - dy * dy;
+    *entry = if remaining <= 0 {
+      EMPTY_SPAN
+    } else {
+      let half_width = isqrt(remaining as u32) as i64;
+      let left = (x as i64 - half_width).clamp(0, 240);
+      let right = (x as i64 + half_width).clamp(0, 240);
+      if left >= right {
+        EMPTY_SPAN
+      } else {
+        HorizontalWindowSetting::new().with_col_start(left as u16).with_col_end(right as u16)
+      }
+    };
+  }
+}
+
+/// Fills a `WIN0H` table so the window is the top `open_rows` lines of each
+/// slat of `slat_height` lines.
+///
+/// ## Panics
+///
+/// If `slat_height` is 0.
+pub fn fill_blinds(
+  table: &mut [HorizontalWindowSetting; SCANLINE_COUNT], slat_height: u8, open_rows: u8,
+) {
+  for (line, entry) in table.iter_mut().enumerate() {
+    let row = (line % slat_height as usize) as u8;
+    *entry = if row < open_rows { FULL_SPAN } else { EMPTY_SPAN };
+  }
+}
+
+/// Scrolls a text background from one offset to another, to slide a scene
+/// onto or off of the screen.
+#[derive(Debug, Clone, Copy)]
+pub struct Slide<const N: usize> {
+  background: Background<N>,
+  from: (i16, i16),
+  to: (i16, i16),
+  timeline: Timeline,
+}
+
+impl<const N: usize> Slide<N> {
+  /// Moves the background's scroll offset from `from` to `to`, as `(x, y)`.
+  ///
+  /// The offsets are signed so that a slide can cross 0, such as from
+  /// `(-240, 0)` to `(0, 0)` to slide in from the right.
+  pub const fn new(
+    background: Background<N>, from: (i16, i16), to: (i16, i16), frames: u16,
+  ) -> Self {
+    Self { background, from, to, timeline: Timeline::new(frames) }
+  }
+
+  /// Sets a function to call when the slide is done.
+  pub const fn with_on_complete(mut self, on_complete: fn()) -> Self {
+    self.timeline.on_complete = Some(on_complete);
+    self
+  }
+
+  /// The scroll offset for the current frame.
+  pub fn offset(&self) -> (i16, i16) {
+    let x = self.timeline.lerp(self.from.0 as i32, self.to.0 as i32);
+    let y = self.timeline.lerp(self.from.1 as i32, self.to.1 as i32);
+    (x as i16, y as i16)
+  }
+
+  /// The background handle, with the offset of the last step.
+  pub const fn background(&self) -> Background<N> {
+    self.background
+  }
+}
+
+impl<const N: usize> Transition for Slide<N> {
+  fn step(&mut self) -> bool {
+    let (x, y) = self.offset();
+    self.background.set_scroll(x as u16, y as u16);
+    self.background.flush();
+    self.timeline.advance()
+  }
+
+  fn is_done(&self) -> bool {
+    self.timeline.done
+  }
+}
+
+#[test]
+fn test_window_tables() {
+  let mut table = [EMPTY_SPAN; SCANLINE_COUNT];
+  fill_iris(&mut table, 120, 80, 10);
+  assert_eq!(table[70], EMPTY_SPAN);
+  assert_eq!(table[80], HorizontalWindowSetting::new().with_col_start(110).with_col_end(130));
+  assert_eq!(table[89].col_start(), 120 - 4);
+  fill_iris(&mut table, 5, 80, 10);
+  assert_eq!(table[80].col_start(), 0);
+  fill_iris(&mut table, 120, 80, u16::MAX);
+  assert_eq!(table[0], FULL_SPAN);
+  assert_eq!(table[159], FULL_SPAN);
+  assert_eq!(IrisWipe::covering_radius(120, 80), 145);
+  assert_eq!(IrisWipe::covering_radius(-50_000, 0), 50_241);
+  assert_eq!(IrisWipe::covering_radius(-70_000, 0), u16::MAX);
+  assert_eq!(IrisWipe::covering_radius(i32::MIN, i32::MAX), u16::MAX);
+  fill_blinds(&mut table, 8, 3);
+  assert_eq!(table[2], FULL_SPAN);
+  assert_eq!(table[3], EMPTY_SPAN);
+  assert_eq!(table[10], FULL_SPAN);
+  let mut timeline = Timeline::new(4);
+  assert_eq!(timeline.lerp(0, 16), 0);
+  assert!(!timeline.advance());
+  assert_eq!(timeline.lerp(0, 16), 4);
+  for _ in 0..3 {
+    assert!(!timeline.advance());
+  }
+  assert_eq!(timeline.lerp(0, 16), 16);
+  assert!(timeline.advance());
+  let mut long = Timeline::new(u16::MAX);
+  long.elapsed = u16::MAX - 1;
+  assert_eq!(long.lerp(i16::MIN as i32, i16::MAX as i32), i16::MAX as i32 - 1);
+}
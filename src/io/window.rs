@@ -1,6 +1,7 @@
 //! Module that holds stuff for the Window ability.
 
 use super::*;
+use crate::io::display::{DisplayControlSetting, LayerSet, DISPCNT};
 
 /// Window 0 Horizontal Dimensions (W)
 pub const WIN0H: VolAddress<HorizontalWindowSetting, Safe, Safe> =
@@ -73,6 +74,16 @@ impl InsideWindowSetting {
     win1_obj: 12,
     win1_color_special: 13,
   }
+
+  /// The layers shown inside window 0.
+  pub const fn win0_layers(self) -> LayerSet {
+    LayerSet::from_bits(self.0 & 0x1F)
+  }
+
+  /// The layers shown inside window 1.
+  pub const fn win1_layers(self) -> LayerSet {
+    LayerSet::from_bits((self.0 >> 8) & 0x1F)
+  }
 }
 
 ///  Control of Outside of Windows & Inside of OBJ Window (R/W)
@@ -100,4 +111,344 @@ impl OutsideWindowSetting {
     obj_win_obj: 12,
     obj_win_color_special: 13,
   }
+
+  /// The layers shown outside of every window.
+  pub const fn outside_layers(self) -> LayerSet {
+    LayerSet::from_bits(self.0 & 0x1F)
+  }
+
+  /// The layers shown inside the object window.
+  pub const fn obj_win_layers(self) -> LayerSet {
+    LayerSet::from_bits((self.0 >> 8) & 0x1F)
+  }
+}
+
+/// A window rectangle, in screen pixels.
+///
+/// The `right` and `bottom` edges are exclusive, so a full screen window is
+/// `0, 0, 240, 160`. Values past the screen edge (more than 240 or 160) act
+/// like the screen edge.
+///
+/// If `left` is more than `right` the hardware wraps the window around the
+/// screen's edges, covering `left..240` and `0..right`, and the same goes for
+/// `top` and `bottom`. Use `clipped` for a rectangle that might go partly off
+/// screen, so it doesn't wrap by accident.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WindowRect {
+  /// The first column inside the window.
+  pub left: u8,
+  /// The first row inside the window.
+  pub top: u8,
+  /// The first column past the window.
+  pub right: u8,
+  /// The first row past the window.
+  pub bottom: u8,
+}
+
+impl WindowRect {
+  /// Covers the whole screen.
+  pub const FULL_SCREEN: Self = Self { left: 0, top: 0, right: 240, bottom: 160 };
+
+  /// Covers nothing.
+  pub const EMPTY: Self = Self { left: 0, top: 0, right: 0, bottom: 0 };
+
+  /// Makes a rectangle from the register values.
+  pub const fn new(left: u8, top: u8, right: u8, bottom: u8) -> Self {
+    Self { left, top, right, bottom }
+  }
+
+  /// Makes a rectangle from a position and size, clipped to the screen.
+  ///
+  /// Rectangles that are entirely off screen give `EMPTY`.
+  pub fn clipped(x: i32, y: i32, width: u32, height: u32) -> Self {
+    let clip = |start: i32, len: u32, max: i32| {
+      let end = start.saturating_add(len.min(i32::MAX as u32) as i32);
+      (start.max(0).min(max), end.max(0).min(max))
+    };
+    let (left, right) = clip(x, width, 240);
+    let (top, bottom) = clip(y, height, 160);
+    if left == right || top == bottom {
+      Self::EMPTY
+    } else {
+      Self { left: left as u8, top: top as u8, right: right as u8, bottom: bottom as u8 }
+    }
+  }
+
+  /// If the hardware counts a pixel as inside this rectangle, including the
+  /// wrap around when `left > right` or `top > bottom`.
+  pub fn contains(&self, x: u8, y: u8) -> bool {
+    fn in_span(v: u8, start: u8, end: u8, max: u8) -> bool {
+      let end = end.min(max);
+      if start <= end {
+        start <= v && v < end
+      } else {
+        v >= start || v < end
+      }
+    }
+    in_span(x, self.left, self.right, 240) && in_span(y, self.top, self.bottom, 160)
+  }
+
+  /// The value for `WIN0H` or `WIN1H`.
+  pub const fn horizontal(&self) -> HorizontalWindowSetting {
+    HorizontalWindowSetting((self.left as u16) << 8 | self.right as u16)
+  }
+
+  /// The value for `WIN0V` or `WIN1V`.
+  pub const fn vertical(&self) -> VerticalWindowSetting {
+    VerticalWindowSetting((self.top as u16) << 8 | self.bottom as u16)
+  }
+}
+
+/// The bits for one byte of `WININ` or `WINOUT`.
+///
+/// The backdrop bit is dropped, since that bit is the effects flag here.
+const fn window_bits(layers: LayerSet, effects: bool) -> u16 {
+  (layers.bits() & 0b1_1111) | (effects as u16) << 5
+}
+
+/// One of the two rectangular windows.
+///
+/// Only the `layers` are shown inside the window, and color special effects
+/// (blending) only happen inside it if `effects` is set. Where the two
+/// rectangular windows overlap, window 0 wins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Window<const N: usize> {
+  /// The area the window covers.
+  pub rect: WindowRect,
+  /// The layers shown inside the window.
+  pub layers: LayerSet,
+  /// If color special effects happen inside the window.
+  pub effects: bool,
+}
+
+/// Window 0, see `Window`.
+pub type Window0 = Window<0>;
+
+/// Window 1, see `Window`.
+pub type Window1 = Window<1>;
+
+impl<const N: usize> Window<N> {
+  const VALID_INDEX: () = assert!(N < 2, "window index must be 0 or 1");
+  const H: VolAddress<HorizontalWindowSetting, Safe, Safe> =
+    unsafe { VolAddress::new(0x400_0040 + N * 2) };
+  const V: VolAddress<VerticalWindowSetting, Safe, Safe> =
+    unsafe { VolAddress::new(0x400_0044 + N * 2) };
+
+  /// Makes a window over a rectangle, showing the layers given.
+  pub const fn new(rect: WindowRect, layers: LayerSet) -> Self {
+    #[allow(clippy::let_unit_value)]
+    let _ = Self::VALID_INDEX;
+    Self { rect, layers, effects: false }
+  }
+
+  /// Sets if color special effects happen inside the window.
+  pub const fn with_effects(self, effects: bool) -> Self {
+    Self { effects, ..self }
+  }
+
+  /// The bits for this window's half of `WININ`.
+  const fn inside_bits(&self) -> u16 {
+    window_bits(self.layers, self.effects) << (N * 8)
+  }
+
+  /// Writes the window's rectangle and layers, and turns it on in `DISPCNT`.
+  pub fn apply(&self) {
+    // The fields are public, so this might not have come from `new`.
+    #[allow(clippy::let_unit_value)]
+    let _ = Self::VALID_INDEX;
+    Self::H.write(self.rect.horizontal());
+    Self::V.write(self.rect.vertical());
+    let others = WININ.read().0 & !(0xFF << (N * 8));
+    WININ.write(InsideWindowSetting(others | self.inside_bits()));
+    let dispcnt = DISPCNT.read();
+    DISPCNT.write(if N == 0 { dispcnt.with_win0(true) } else { dispcnt.with_win1(true) });
+  }
+
+  /// Moves the window without changing anything else.
+  ///
+  /// This is cheap enough to call every frame (or every line from HBlank).
+  pub fn set_rect(&mut self, rect: WindowRect) {
+    #[allow(clippy::let_unit_value)]
+    let _ = Self::VALID_INDEX;
+    self.rect = rect;
+    Self::H.write(rect.horizontal());
+    Self::V.write(rect.vertical());
+  }
+
+  /// Turns this window off in `DISPCNT`.
+  pub fn disable() {
+    #[allow(clippy::let_unit_value)]
+    let _ = Self::VALID_INDEX;
+    let dispcnt = DISPCNT.read();
+    DISPCNT.write(if N == 0 { dispcnt.with_win0(false) } else { dispcnt.with_win1(false) });
+  }
+}
+
+impl<const N: usize> Default for Window<N> {
+  fn default() -> Self {
+    Self::new(Default::default(), Default::default())
+  }
+}
+
+/// The object window: the area covered by the non-transparent pixels of
+/// objects in `ObjectMode::OBJWindow`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ObjWindow {
+  /// The layers shown inside the window.
+  pub layers: LayerSet,
+  /// If color special effects happen inside the window.
+  pub effects: bool,
+}
+
+impl ObjWindow {
+  /// Makes an object window showing the layers given.
+  pub const fn new(layers: LayerSet) -> Self {
+    Self { layers, effects: false }
+  }
+
+  /// Sets if color special effects happen inside the window.
+  pub const fn with_effects(self, effects: bool) -> Self {
+    Self { effects, ..self }
+  }
+
+  /// Writes the window's layers, and turns it on in `DISPCNT`.
+  pub fn apply(&self) {
+    let outside = WINOUT.read().0 & 0xFF;
+    WINOUT.write(OutsideWindowSetting(outside | window_bits(self.layers, self.effects) << 8));
+    DISPCNT.write(DISPCNT.read().with_obj_window(true));
+  }
+
+  /// Turns the object window off in `DISPCNT`.
+  pub fn disable() {
+    DISPCNT.write(DISPCNT.read().with_obj_window(false));
+  }
+}
+
+/// The area outside of every enabled window.
+///
+/// This only matters when at least one window is on, otherwise every layer
+/// enabled in `DISPCNT` is shown everywhere.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Outside {
+  /// The layers shown outside of the windows.
+  pub layers: LayerSet,
+  /// If color special effects happen outside of the windows.
+  pub effects: bool,
+}
+
+impl Outside {
+  /// Makes an outside area showing the layers given.
+  pub const fn new(layers: LayerSet) -> Self {
+    Self { layers, effects: false }
+  }
+
+  /// Sets if color special effects happen outside of the windows.
+  pub const fn with_effects(self, effects: bool) -> Self {
+    Self { effects, ..self }
+  }
+
+  /// Writes the outside layers.
+  pub fn apply(&self) {
+    let obj_window = WINOUT.read().0 & 0xFF00;
+    WINOUT.write(OutsideWindowSetting(obj_window | window_bits(self.layers, self.effects)));
+  }
+}
+
+/// A complete window setup, written all at once.
+///
+/// ```no_run
+/// // A spotlight: only bg0 and objects inside, darkness outside.
+/// let windows = WindowConfig::new(Outside::new(LayerSet::NONE))
+///   .with_win0(Window0::new(WindowRect::clipped(80, 40, 80, 80), LayerSet::BG0 | LayerSet::OBJ));
+/// windows.apply();
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WindowConfig {
+  /// Window 0, if it's on.
+  pub win0: Option<Window0>,
+  /// Window 1, if it's on.
+  pub win1: Option<Window1>,
+  /// The object window, if it's on.
+  pub obj_window: Option<ObjWindow>,
+  /// The area outside of the windows.
+  pub outside: Outside,
+}
+
+impl WindowConfig {
+  /// Makes a setup with every window off.
+  pub const fn new(outside: Outside) -> Self {
+    Self { win0: None, win1: None, obj_window: None, outside }
+  }
+
+  /// Turns on window 0.
+  pub const fn with_win0(self, win0: Window0) -> Self {
+    Self { win0: Some(win0), ..self }
+  }
+
+  /// Turns on window 1.
+  pub const fn with_win1(self, win1: Window1) -> Self {
+    Self { win1: Some(win1), ..self }
+  }
+
+  /// Turns on the object window.
+  pub const fn with_obj_window(self, obj_window: ObjWindow) -> Self {
+    Self { obj_window: Some(obj_window), ..self }
+  }
+
+  /// The `WININ` value for this setup.
+  pub fn inside_setting(&self) -> InsideWindowSetting {
+    let win0 = self.win0.map(|w| w.inside_bits()).unwrap_or(0);
+    let win1 = self.win1.map(|w| w.inside_bits()).unwrap_or(0);
+    InsideWindowSetting(win0 | win1)
+  }
+
+  /// The `WINOUT` value for this setup.
+  pub fn outside_setting(&self) -> OutsideWindowSetting {
+    let outside = window_bits(self.outside.layers, self.outside.effects);
+    let obj = self.obj_window.map(|w| window_bits(w.layers, w.effects)).unwrap_or(0);
+    OutsideWindowSetting(outside | obj << 8)
+  }
+
+  /// Sets the window bits of a display setting to match this setup.
+  pub fn display_control(&self, setting: DisplayControlSetting) -> DisplayControlSetting {
+    setting
+      .with_win0(self.win0.is_some())
+      .with_win1(self.win1.is_some())
+      .with_obj_window(self.obj_window.is_some())
+  }
+
+  /// Writes every window register, and the window bits of `DISPCNT`.
+  pub fn apply(&self) {
+    if let Some(win0) = self.win0 {
+      WIN0H.write(win0.rect.horizontal());
+      WIN0V.write(win0.rect.vertical());
+    }
+    if let Some(win1) = self.win1 {
+      WIN1H.write(win1.rect.horizontal());
+      WIN1V.write(win1.rect.vertical());
+    }
+    WININ.write(self.inside_setting());
+    WINOUT.write(self.outside_setting());
+    DISPCNT.write(self.display_control(DISPCNT.read()));
+  }
+}
+
+#[test]
+fn test_window_rect() {
+  assert_eq!(WindowRect::clipped(-10, 150, 50, 50), WindowRect::new(0, 150, 40, 160));
+  assert_eq!(WindowRect::clipped(230, 0, 20, 10), WindowRect::new(230, 0, 240, 10));
+  assert_eq!(WindowRect::clipped(240, 0, 10, 10), WindowRect::EMPTY);
+  assert_eq!(WindowRect::clipped(-20, 0, 20, 10), WindowRect::EMPTY);
+  assert_eq!(WindowRect::clipped(10, 10, 0, 5), WindowRect::EMPTY);
+  // A right edge of 240 (or past it) reaches the last column.
+  assert!(WindowRect::FULL_SCREEN.contains(239, 159));
+  assert!(WindowRect::new(0, 0, 255, 255).contains(239, 159));
+  assert!(!WindowRect::EMPTY.contains(0, 0));
+  assert!(!WindowRect::new(5, 5, 5, 5).contains(5, 5));
+  // With `left > right` the window wraps around the sides of the screen.
+  let wrapped = WindowRect::new(200, 0, 40, 160);
+  assert!(wrapped.contains(220, 5) && wrapped.contains(10, 5));
+  assert!(!wrapped.contains(100, 5) && !wrapped.contains(40, 5));
+  let wrapped = WindowRect::new(200, 0, 0, 160);
+  assert!(wrapped.contains(239, 0) && !wrapped.contains(0, 0));
 }
@@ -1,4 +1,4 @@
-#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
 #![feature(asm)]
 #![feature(global_asm)]
 #![feature(isa_attribute)]
@@ -22,6 +22,9 @@
 //!
 //! **Do not** use this crate in programs that aren't running on the GBA. If you
 //! do, it's a giant bag of Undefined Behavior.
+//!
+//! The one exception is the `render` module (with the `std` feature), which
+//! only works with plain memory and is meant for running tests on a PC.
 
 pub(crate) use gba_proc_macro::phantom_fields;
 
@@ -29,6 +32,8 @@ use voladdress::*;
 
 pub mod macros;
 
+pub mod fixed_point;
+
 pub mod bios;
 
 pub mod iwram;
@@ -51,6 +56,11 @@ pub mod sync;
 
 pub mod debug;
 
+mod color;
+
+#[cfg(feature = "std")]
+pub mod render;
+
 extern "C" {
   /// This marks the end of the `.data` and `.bss` sections in IWRAM.
   ///
@@ -1,6 +1,13 @@
 //! Types and declarations for the Object Attribute Memory (`OAM`).
 
 use super::*;
+use crate::{
+  bios::{ObjAffineSetOffset, ObjAffineSetParams},
+  fixed_point::fx8_8,
+};
+
+pub mod metasprite;
+pub mod multiplex;
 
 newtype! {
   /// 0th part of an object's attributes.
@@ -128,7 +135,11 @@ impl OBJAttr2 {
   }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The three attribute values of a single object.
+///
+/// The layout matches the first three `u16` values of each OAM slot.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[repr(C)]
 pub struct ObjectAttributes {
   pub attr0: OBJAttr0,
   pub attr1: OBJAttr1,
@@ -159,14 +170,86 @@ pub fn read_obj_attributes(slot: usize) -> Option<ObjectAttributes> {
   })
 }
 
+/// The size of an object in pixels, as `(width, height)`.
+pub const fn obj_dimensions(shape: ObjectShape, size: ObjectSize) -> (u16, u16) {
+  match (shape, size) {
+    (ObjectShape::Square, ObjectSize::Zero) => (8, 8),
+    (ObjectShape::Square, ObjectSize::One) => (16, 16),
+    (ObjectShape::Square, ObjectSize::Two) => (32, 32),
+    (ObjectShape::Square, ObjectSize::Three) => (64, 64),
+    (ObjectShape::Horizontal, ObjectSize::Zero) => (16, 8),
+    (ObjectShape::Horizontal, ObjectSize::One) => (32, 8),
+    (ObjectShape::Horizontal, ObjectSize::Two) => (32, 16),
+    (ObjectShape::Horizontal, ObjectSize::Three) => (64, 32),
+    (ObjectShape::Vertical, ObjectSize::Zero) => (8, 16),
+    (ObjectShape::Vertical, ObjectSize::One) => (8, 32),
+    (ObjectShape::Vertical, ObjectSize::Two) => (16, 32),
+    (ObjectShape::Vertical, ObjectSize::Three) => (32, 64),
+  }
+}
+
+/// An affine matrix for objects, each value is 8.8 fixed point.
+///
+/// As with affine backgrounds, the matrix maps screen space into texture
+/// space, so it's the _inverse_ of the transform that you see.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
 pub struct AffineParameters {
   pub pa: i16,
   pub pb: i16,
   pub pc: i16,
   pub pd: i16,
 }
-// TODO: find the correct fixed-point type here.
+impl AffineParameters {
+  /// The identity matrix, no rotation or scaling.
+  pub const IDENTITY: Self = Self { pa: 1 << 8, pb: 0, pc: 0, pd: 1 << 8 };
+
+  /// Makes a matrix that rotates counter-clockwise by `angle` and then scales
+  /// by `scale_x` and `scale_y`.
+  ///
+  /// * `angle` is a "binary angle", `0x1_0000` would be a full turn.
+  /// * The scales are how large the object appears, so `2.0` doubles the
+  ///   size. This is the reverse of the BIOS scale values.
+  ///
+  /// ## Panics
+  ///
+  /// If either scale is 0.
+  pub fn from_rotation_scale(angle: u16, scale_x: fx8_8, scale_y: fx8_8) -> Self {
+    let (sin, cos) = (fixed_point::sin(angle).into_raw(), fixed_point::cos(angle).into_raw());
+    // 1/scale with 16 fractional bits.
+    let inv_x = bios::div(1 << 24, scale_x.into_raw() as i32);
+    let inv_y = bios::div(1 << 24, scale_y.into_raw() as i32);
+    // .12 times .16 is .28, shift down to .8 and saturate very small scales.
+    let mul = |trig: i32, inv: i32| {
+      ((trig as i64 * inv as i64) >> 20).clamp(i16::MIN as i64, i16::MAX as i64) as i16
+    };
+    Self { pa: mul(cos, inv_x), pb: mul(-sin, inv_x), pc: mul(sin, inv_y), pd: mul(cos, inv_y) }
+  }
+
+  /// Uses `obj_affine_set` to compute the matrix for the values given.
+  pub fn from_bios(src: &ObjAffineSetParams) -> Self {
+    let mut out = Self::IDENTITY;
+    bios::obj_affine_set(src, &mut out as *mut Self as usize, 1, ObjAffineSetOffset::Continuous);
+    out
+  }
+
+  /// Checks if an object of the size given would be clipped when drawn with
+  /// this matrix using `ObjectRender::Affine`.
+  ///
+  /// When this is true, use `ObjectRender::DoubleAreaAffine` instead.
+  /// Objects transformed to more than twice their size are clipped either way.
+  pub fn needs_double_area(&self, width: u16, height: u16) -> bool {
+    let (pa, pb, pc, pd) = (self.pa as i64, self.pb as i64, self.pc as i64, self.pd as i64);
+    let (half_w, half_h) = (width as i64 / 2, height as i64 / 2);
+    // The on-screen extent is the matrix inverse applied to the corners, we
+    // compare against `det * half_size` to avoid dividing by `det`. A
+    // saturated matrix has `det` near 2^30, so this needs 64 bits.
+    let det = (pa * pd - pb * pc).abs();
+    let extent_x = (pd.abs() * half_w + pb.abs() * half_h) << 8;
+    let extent_y = (pc.abs() * half_w + pa.abs() * half_h) << 8;
+    extent_x > det * half_w || extent_y > det * half_h
+  }
+}
 
 /// The object attributes, but there are gaps in the array, so we must not
 /// expose this directly.
@@ -194,3 +277,334 @@ pub fn read_affine_parameters(slot: usize) -> Option<AffineParameters> {
     AffineParameters { pa, pb, pc, pd }
   })
 }
+
+/// The number of object slots in OAM.
+pub const OBJ_SLOT_COUNT: usize = 128;
+
+/// The number of affine parameter groups in OAM.
+pub const AFFINE_SLOT_COUNT: usize = 32;
+
+/// Object attributes that don't display anything.
+pub const HIDDEN_OBJ_ATTRIBUTES: ObjectAttributes = ObjectAttributes {
+  attr0: OBJAttr0::new().with_obj_rendering(ObjectRender::Disabled),
+  attr1: OBJAttr1::new(),
+  attr2: OBJAttr2::new(),
+};
+
+/// OAM viewed as words, for bulk copies.
+const OAM_WORDS: VolBlock<u32, Safe, Safe, 256> = unsafe { VolBlock::new(0x700_0000) };
+
+/// One OAM slot: the attributes plus the interleaved affine parameter value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+struct OamSlot {
+  attributes: ObjectAttributes,
+  affine: i16,
+}
+
+/// A handle to an object slot allocated from an [`ObjectShadow`].
+///
+/// This isn't `Copy`, so that a slot can't be freed twice.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ObjectHandle(u8);
+impl ObjectHandle {
+  /// The OAM slot index of this object.
+  ///
+  /// Lower slots are drawn over higher slots of the same priority.
+  pub fn slot(&self) -> usize {
+    self.0 as usize
+  }
+}
+
+/// A handle to an affine matrix slot allocated from an [`ObjectShadow`].
+///
+/// Objects can share a matrix, see [`ObjectShadow::share_affine`].
+#[derive(Debug, PartialEq, Eq)]
+pub struct AffineHandle(u8);
+impl AffineHandle {
+  /// The affine parameter group index of this matrix.
+  pub fn slot(&self) -> usize {
+    self.0 as usize
+  }
+}
+
+/// A RAM copy of all of OAM, with an allocator for the object slots.
+///
+/// Edit the copy freely during the frame and then `commit` it during VBlank,
+/// so that objects never tear. The copy is exactly the size and layout of OAM
+/// (1k), and since the `.bss` section and the stack are both in IWRAM it's
+/// fast to copy from wherever you keep it.
+///
+/// Unallocated slots are always hidden with `ObjectRender::Disabled`.
+///
+/// The affine parameters are interleaved with the object attributes in OAM,
+/// so they're stored here too. They can be managed with the reference counted
+/// `alloc_affine` / `share_affine` / `release_affine` pool, or assigned by
+/// index directly, but you shouldn't mix the two styles.
+#[derive(Clone)]
+#[repr(C, align(4))]
+pub struct ObjectShadow {
+  slots: [OamSlot; OBJ_SLOT_COUNT],
+  used: [u32; OBJ_SLOT_COUNT / 32],
+  affine_refs: [u8; AFFINE_SLOT_COUNT],
+}
+impl ObjectShadow {
+  /// Makes a shadow with every slot free (and hidden) and every affine
+  /// parameter zeroed.
+  pub const fn new() -> Self {
+    Self {
+      slots: [OamSlot { attributes: HIDDEN_OBJ_ATTRIBUTES, affine: 0 }; OBJ_SLOT_COUNT],
+      used: [0; OBJ_SLOT_COUNT / 32],
+      affine_refs: [0; AFFINE_SLOT_COUNT],
+    }
+  }
+
+  /// Allocates the lowest free slot.
+  ///
+  /// The object stays hidden until you give it attributes.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` if all slots are in use.
+  pub fn alloc(&mut self) -> Option<ObjectHandle> {
+    for (word_index, word) in self.used.iter_mut().enumerate() {
+      if *word != u32::MAX {
+        let bit = (!*word).trailing_zeros() as usize;
+        *word |= 1 << bit;
+        let slot = word_index * 32 + bit;
+        self.slots[slot].attributes = HIDDEN_OBJ_ATTRIBUTES;
+        return Some(ObjectHandle(slot as u8));
+      }
+    }
+    None
+  }
+
+  /// Frees a slot, hiding the object.
+  pub fn free(&mut self, handle: ObjectHandle) {
+    let slot = handle.slot();
+    self.used[slot / 32] &= !(1 << (slot % 32));
+    self.slots[slot].attributes = HIDDEN_OBJ_ATTRIBUTES;
+  }
+
+  /// Frees every object and affine slot, hiding all objects.
+  ///
+  /// Any handles still around become meaningless, so drop them too.
+  pub fn free_all(&mut self) {
+    self.used = [0; OBJ_SLOT_COUNT / 32];
+    self.affine_refs = [0; AFFINE_SLOT_COUNT];
+    for slot in self.slots.iter_mut() {
+      slot.attributes = HIDDEN_OBJ_ATTRIBUTES;
+    }
+  }
+
+  /// The number of slots that are allocated.
+  pub fn used_count(&self) -> usize {
+    self.used.iter().map(|word| word.count_ones() as usize).sum()
+  }
+
+  /// Gets the attributes of an allocated object.
+  pub fn attributes(&self, handle: &ObjectHandle) -> ObjectAttributes {
+    self.slots[handle.slot()].attributes
+  }
+
+  /// Gets mutable access to the attributes of an allocated object.
+  pub fn attributes_mut(&mut self, handle: &ObjectHandle) -> &mut ObjectAttributes {
+    &mut self.slots[handle.slot()].attributes
+  }
+
+  /// Assigns the attributes of an allocated object.
+  pub fn set_attributes(&mut self, handle: &ObjectHandle, attributes: ObjectAttributes) {
+    self.slots[handle.slot()].attributes = attributes;
+  }
+
+  /// Gets the affine parameters of the group specified.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` if out of bounds
+  pub fn affine_parameters(&self, slot: usize) -> Option<AffineParameters> {
+    if slot < AFFINE_SLOT_COUNT {
+      let base = slot * 4;
+      Some(AffineParameters {
+        pa: self.slots[base].affine,
+        pb: self.slots[base + 1].affine,
+        pc: self.slots[base + 2].affine,
+        pd: self.slots[base + 3].affine,
+      })
+    } else {
+      None
+    }
+  }
+
+  /// Assigns the affine parameters of the group specified.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` if out of bounds
+  pub fn set_affine_parameters(&mut self, slot: usize, params: AffineParameters) -> Option<()> {
+    if slot < AFFINE_SLOT_COUNT {
+      let base = slot * 4;
+      self.slots[base].affine = params.pa;
+      self.slots[base + 1].affine = params.pb;
+      self.slots[base + 2].affine = params.pc;
+      self.slots[base + 3].affine = params.pd;
+      Some(())
+    } else {
+      None
+    }
+  }
+
+  /// Allocates an affine matrix slot holding the parameters given.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` if all 32 slots are in use.
+  pub fn alloc_affine(&mut self, params: AffineParameters) -> Option<AffineHandle> {
+    let slot = self.affine_refs.iter().position(|&refs| refs == 0)?;
+    self.affine_refs[slot] = 1;
+    self.set_affine_parameters(slot, params);
+    Some(AffineHandle(slot as u8))
+  }
+
+  /// Makes another handle to the same matrix, so that more objects can share
+  /// it. The slot stays allocated until every handle is released.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` if the slot isn't allocated (such as after `free_all`), or
+  /// already has 255 handles.
+  pub fn share_affine(&mut self, handle: &AffineHandle) -> Option<AffineHandle> {
+    let refs = &mut self.affine_refs[handle.slot()];
+    if *refs == 0 {
+      return None;
+    }
+    *refs = refs.checked_add(1)?;
+    Some(AffineHandle(handle.0))
+  }
+
+  /// Releases a handle, freeing the slot if it was the last one.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` if the slot isn't allocated (such as after `free_all`).
+  pub fn release_affine(&mut self, handle: AffineHandle) -> Option<()> {
+    let refs = &mut self.affine_refs[handle.slot()];
+    *refs = refs.checked_sub(1)?;
+    Some(())
+  }
+
+  /// Assigns the parameters of an allocated matrix.
+  ///
+  /// This affects every object sharing the matrix.
+  pub fn set_affine(&mut self, handle: &AffineHandle, params: AffineParameters) {
+    self.set_affine_parameters(handle.slot(), params);
+  }
+
+  /// Has `obj_affine_set` write its output straight into an allocated
+  /// matrix.
+  pub fn bios_set_affine(&mut self, handle: &AffineHandle, src: &ObjAffineSetParams) {
+    let dest = &mut self.slots[handle.slot() * 4].affine as *mut i16 as usize;
+    bios::obj_affine_set(src, dest, 1, ObjAffineSetOffset::OAM);
+  }
+
+  /// Makes an object use an allocated matrix.
+  ///
+  /// The object is switched to `ObjectRender::DoubleAreaAffine` if the matrix
+  /// would otherwise clip it, or `ObjectRender::Affine` if not. Note that the
+  /// double area mode moves the object's center by half its size, so you'll
+  /// need to adjust the position to match.
+  pub fn attach_affine(&mut self, obj: &ObjectHandle, matrix: &AffineHandle) {
+    let params = self.affine_parameters(matrix.slot()).unwrap();
+    let attributes = &mut self.slots[obj.slot()].attributes;
+    let (width, height) =
+      obj_dimensions(attributes.attr0.obj_shape(), attributes.attr1.obj_size());
+    let render = if params.needs_double_area(width, height) {
+      ObjectRender::DoubleAreaAffine
+    } else {
+      ObjectRender::Affine
+    };
+    attributes.attr0 = attributes.attr0.with_obj_rendering(render);
+    attributes.attr1 = attributes.attr1.with_affine_index(matrix.0 as u16);
+  }
+
+  /// Copies the whole shadow into OAM with the CPU.
+  ///
+  /// Call this during VBlank (OAM can't be written while it's being drawn
+  /// from, unless `hblank_interval_free` is set).
+  pub fn commit(&self) {
+    let words = self.slots.as_ptr() as *const u32;
+    for (i, va) in OAM_WORDS.iter().enumerate() {
+      va.write(unsafe { words.add(i).read() });
+    }
+  }
+
+  /// Copies the whole shadow into OAM using DMA3.
+  ///
+  /// Call this during VBlank, as with `commit`.
+  pub fn dma_commit(&self) {
+    use crate::io::dma::DMA3;
+    unsafe {
+      DMA3::copy32(
+        self.slots.as_ptr() as *const u32,
+        OAM_WORDS.index(0).as_usize() as *mut u32,
+        OAM_WORDS.len() as u16,
+      )
+    };
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_object_slots() {
+    let mut shadow = ObjectShadow::new();
+    let mut kept = None;
+    for i in 0..OBJ_SLOT_COUNT {
+      let handle = shadow.alloc().unwrap();
+      assert_eq!(handle.slot(), i);
+      if i == 70 {
+        kept = Some(handle);
+      }
+    }
+    assert_eq!(shadow.used_count(), OBJ_SLOT_COUNT);
+    assert!(shadow.alloc().is_none());
+    shadow.free(kept.unwrap());
+    assert_eq!(shadow.used_count(), OBJ_SLOT_COUNT - 1);
+    assert_eq!(shadow.alloc().map(|handle| handle.slot()), Some(70));
+    shadow.free_all();
+    assert_eq!(shadow.used_count(), 0);
+    assert_eq!(shadow.alloc().map(|handle| handle.slot()), Some(0));
+  }
+
+  #[test]
+  fn test_needs_double_area() {
+    assert!(!AffineParameters::IDENTITY.needs_double_area(8, 8));
+    let one = fx8_8::from_raw(1 << 8);
+    let turned = AffineParameters::from_rotation_scale(0x2000, one, one);
+    assert_eq!(turned, AffineParameters { pa: 181, pb: -181, pc: 181, pd: 181 });
+    assert!(turned.needs_double_area(32, 32));
+    let doubled = fx8_8::from_raw(2 << 8);
+    assert!(AffineParameters::from_rotation_scale(0, doubled, doubled).needs_double_area(8, 8));
+    // A tiny scale saturates the matrix, which used to overflow.
+    let tiny = fx8_8::from_raw(1);
+    let shrunk = AffineParameters::from_rotation_scale(0, tiny, tiny);
+    assert_eq!(shrunk.pa, i16::MAX);
+    assert!(!shrunk.needs_double_area(64, 64));
+  }
+
+  #[test]
+  fn test_affine_refcounts() {
+    let mut shadow = ObjectShadow::new();
+    let first = shadow.alloc_affine(AffineParameters::IDENTITY).unwrap();
+    let second = shadow.share_affine(&first).unwrap();
+    assert_eq!(shadow.release_affine(first), Some(()));
+    assert_eq!(shadow.release_affine(second), Some(()));
+    let third = shadow.alloc_affine(AffineParameters::IDENTITY).unwrap();
+    assert_eq!(third.slot(), 0);
+    shadow.free_all();
+    assert!(shadow.share_affine(&third).is_none());
+    assert!(shadow.release_affine(third).is_none());
+  }
+}
@@ -0,0 +1,300 @@
+//! Module for metasprites: several objects that move (and flip) as one.
+//!
+//! A `Metasprite` is a list of `SpritePart` values, each one object placed
+//! relative to the metasprite's origin. Drawing a metasprite takes a template
+//! `ObjectAttributes` for the shared settings (priority, palbank, mode, color
+//! depth, and the first tile id) and gives one set of attributes per part.
+//!
+//! Flipping the template flips the whole metasprite around its origin: each
+//! part's flip is toggled and its offset is mirrored, so a part at `x` with
+//! width `w` moves to `-x - w`. Put the origin where the character should
+//! pivot (often the middle of its feet).
+//!
+//! An `Animation` is a list of frames, each a metasprite plus a tile offset
+//! shown for some number of frames, and an `AnimationPlayer` steps through
+//! one. All of these types can be `const` data in ROM:
+//!
+//! ```no_run
+//! const IDLE: Metasprite = Metasprite::new(&[
+//!   SpritePart::new(-16, -32, ObjectShape::Square, ObjectSize::Two, 0),
+//!   SpritePart::new(-16, 0, ObjectShape::Horizontal, ObjectSize::Zero, 16),
+//! ]);
+//! const WALK: Animation = Animation::new(
+//!   &[AnimationFrame::new(&IDLE, 0, 8), AnimationFrame::new(&IDLE, 24, 8)],
+//!   true,
+//! );
+//! ```
+
+use super::*;
+
+/// One object of a metasprite.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpritePart {
+  /// The column of the part's left edge, relative to the origin.
+  pub x: i16,
+  /// The row of the part's top edge, relative to the origin.
+  pub y: i16,
+  /// The part's shape.
+  pub shape: ObjectShape,
+  /// The part's size.
+  pub size: ObjectSize,
+  /// Added to the template's tile id.
+  pub tile_offset: u16,
+  /// If the part is mirrored left to right (before any flip of the whole
+  /// metasprite).
+  pub hflip: bool,
+  /// If the part is mirrored top to bottom (before any flip of the whole
+  /// metasprite).
+  pub vflip: bool,
+}
+
+impl SpritePart {
+  /// Makes an unflipped part.
+  pub const fn new(
+    x: i16, y: i16, shape: ObjectShape, size: ObjectSize, tile_offset: u16,
+  ) -> Self {
+    Self { x, y, shape, size, tile_offset, hflip: false, vflip: false }
+  }
+
+  /// Sets the part's own flips.
+  pub const fn with_flips(self, hflip: bool, vflip: bool) -> Self {
+    Self { hflip, vflip, ..self }
+  }
+
+  /// The size of the part in pixels, as `(width, height)`.
+  pub const fn dimensions(&self) -> (u16, u16) {
+    obj_dimensions(self.shape, self.size)
+  }
+
+  /// The attributes for this part with the metasprite's origin at `(x, y)`.
+  ///
+  /// Parts that are entirely off screen are hidden, since the object
+  /// coordinates would otherwise wrap them around to the other side.
+  pub fn attributes(&self, x: i32, y: i32, template: ObjectAttributes) -> ObjectAttributes {
+    let (hflip, vflip) = (template.attr1.hflip(), template.attr1.vflip());
+    let (width, height) = self.dimensions();
+    let (width, height) = (width as i32, height as i32);
+    let left = x + if hflip { -(self.x as i32) - width } else { self.x as i32 };
+    let top = y + if vflip { -(self.y as i32) - height } else { self.y as i32 };
+    if left + width <= 0 || left >= 240 || top + height <= 0 || top >= 160 {
+      return HIDDEN_OBJ_ATTRIBUTES;
+    }
+    let tile_id = template.attr2.tile_id().wrapping_add(self.tile_offset) & 0x3FF;
+    ObjectAttributes {
+      attr0: template.attr0.with_obj_shape(self.shape).with_row_coordinate((top & 0xFF) as u16),
+      attr1: template
+        .attr1
+        .with_obj_size(self.size)
+        .with_col_coordinate((left & 0x1FF) as u16)
+        .with_hflip(hflip != self.hflip)
+        .with_vflip(vflip != self.vflip),
+      attr2: template.attr2.with_tile_id(tile_id),
+    }
+  }
+}
+
+/// A group of objects drawn together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Metasprite<'a> {
+  /// The parts, in OAM order (earlier parts are drawn over later ones).
+  pub parts: &'a [SpritePart],
+}
+
+impl<'a> Metasprite<'a> {
+  /// Makes a metasprite from its parts.
+  pub const fn new(parts: &'a [SpritePart]) -> Self {
+    Self { parts }
+  }
+
+  /// The attributes for every part, with the origin at `(x, y)`.
+  ///
+  /// The template should use `ObjectRender::Normal`. Its `tile_id` is the
+  /// base that part tile offsets are added to, and its flips flip the whole
+  /// metasprite.
+  pub fn attributes(
+    &self, x: i32, y: i32, template: ObjectAttributes,
+  ) -> impl Iterator<Item = ObjectAttributes> + 'a {
+    self.parts.iter().map(move |part| part.attributes(x, y, template))
+  }
+
+  /// Writes the metasprite into allocated objects, one per part.
+  ///
+  /// Parts beyond the number of handles aren't drawn, and handles beyond the
+  /// number of parts are hidden, so a set of handles sized for the largest
+  /// frame of an animation can be reused for every frame.
+  pub fn write(
+    &self, shadow: &mut ObjectShadow, handles: &[ObjectHandle], x: i32, y: i32,
+    template: ObjectAttributes,
+  ) {
+    let mut parts = self.attributes(x, y, template);
+    for handle in handles {
+      shadow.set_attributes(handle, parts.next().unwrap_or(HIDDEN_OBJ_ATTRIBUTES));
+    }
+  }
+}
+
+/// One frame of an animation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnimationFrame<'a> {
+  /// The metasprite to show.
+  pub metasprite: &'a Metasprite<'a>,
+  /// Added to the template's tile id, on top of each part's tile offset.
+  pub tile_offset: u16,
+  /// How many frames to show this for. A duration of 0 holds the frame until
+  /// the player is restarted.
+  pub duration: u16,
+}
+
+impl<'a> AnimationFrame<'a> {
+  /// Makes a frame.
+  pub const fn new(metasprite: &'a Metasprite<'a>, tile_offset: u16, duration: u16) -> Self {
+    Self { metasprite, tile_offset, duration }
+  }
+}
+
+/// A sequence of frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Animation<'a> {
+  /// The frames, in order.
+  pub frames: &'a [AnimationFrame<'a>],
+  /// If the animation goes back to the first frame after the last. If not, it
+  /// stays on the last frame.
+  pub looping: bool,
+}
+
+impl<'a> Animation<'a> {
+  /// Makes an animation.
+  pub const fn new(frames: &'a [AnimationFrame<'a>], looping: bool) -> Self {
+    Self { frames, looping }
+  }
+}
+
+/// Plays an animation, one `step` per frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnimationPlayer<'a> {
+  animation: &'a Animation<'a>,
+  frame: usize,
+  elapsed: u16,
+}
+
+impl<'a> AnimationPlayer<'a> {
+  /// Makes a player at the start of an animation.
+  pub const fn new(animation: &'a Animation<'a>) -> Self {
+    Self { animation, frame: 0, elapsed: 0 }
+  }
+
+  /// Switches to another animation, starting it from the beginning.
+  ///
+  /// Switching to an animation equal to the one that's already playing does
+  /// nothing, so this can be called every frame with the animation that
+  /// should be showing. Animations are compared by value, so this works with
+  /// `const` animations, where each use can have its own address.
+  pub fn play(&mut self, animation: &'a Animation<'a>) {
+    if self.animation != animation {
+      *self = Self::new(animation);
+    }
+  }
+
+  /// Starts the current animation over.
+  pub fn restart(&mut self) {
+    self.frame = 0;
+    self.elapsed = 0;
+  }
+
+  /// The index of the current frame.
+  pub fn frame_index(&self) -> usize {
+    self.frame
+  }
+
+  /// The current frame.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` if the animation has no frames.
+  pub fn current(&self) -> Option<&'a AnimationFrame<'a>> {
+    self.animation.frames.get(self.frame)
+  }
+
+  /// If a non-looping animation has finished its last frame.
+  pub fn is_finished(&self) -> bool {
+    match self.current() {
+      Some(frame) => {
+        !self.animation.looping
+          && self.frame + 1 == self.animation.frames.len()
+          && frame.duration != 0
+          && self.elapsed >= frame.duration
+      }
+      None => true,
+    }
+  }
+
+  /// Advances by one frame, and gives `true` if that changed which frame
+  /// is shown.
+  pub fn step(&mut self) -> bool {
+    let duration = match self.current() {
+      Some(frame) if frame.duration != 0 => frame.duration,
+      _ => return false,
+    };
+    if self.elapsed < duration {
+      self.elapsed += 1;
+    }
+    if self.elapsed < duration {
+      return false;
+    }
+    if self.frame + 1 < self.animation.frames.len() {
+      self.frame += 1;
+    } else if self.animation.looping && self.frame != 0 {
+      self.frame = 0;
+    } else {
+      return false;
+    }
+    self.elapsed = 0;
+    true
+  }
+
+  /// Writes the current frame into allocated objects, see
+  /// `Metasprite::write`.
+  pub fn write(
+    &self, shadow: &mut ObjectShadow, handles: &[ObjectHandle], x: i32, y: i32,
+    template: ObjectAttributes,
+  ) {
+    if let Some(frame) = self.current() {
+      let tile_id = template.attr2.tile_id().wrapping_add(frame.tile_offset) & 0x3FF;
+      let template = ObjectAttributes { attr2: template.attr2.with_tile_id(tile_id), ..template };
+      frame.metasprite.write(shadow, handles, x, y, template);
+    }
+  }
+}
+
+#[test]
+fn test_part_flip_mirrors_offset() {
+  let part = SpritePart::new(-8, -32, ObjectShape::Horizontal, ObjectSize::One, 4);
+  let template = ObjectAttributes { attr2: OBJAttr2::new().with_tile_id(10), ..Default::default() };
+  let plain = part.attributes(100, 100, template);
+  assert_eq!(plain.attr1.col_coordinate(), 92);
+  assert_eq!(plain.attr0.row_coordinate(), 68);
+  assert_eq!(plain.attr2.tile_id(), 14);
+  assert!(!plain.attr1.hflip());
+  let flipped = ObjectAttributes { attr1: OBJAttr1::new().with_hflip(true), ..template };
+  let mirrored = part.attributes(100, 100, flipped);
+  assert_eq!(mirrored.attr1.col_coordinate(), 100 + 8 - 32);
+  assert!(mirrored.attr1.hflip());
+  assert_eq!(part.attributes(-40, 100, template), HIDDEN_OBJ_ATTRIBUTES);
+}
+
+#[test]
+fn test_play_same_const_keeps_going() {
+  const IDLE: Metasprite = Metasprite::new(&[]);
+  const WALK: Animation = Animation::new(
+    &[AnimationFrame::new(&IDLE, 0, 1), AnimationFrame::new(&IDLE, 8, 1)],
+    true,
+  );
+  const JUMP: Animation = Animation::new(&[AnimationFrame::new(&IDLE, 16, 0)], false);
+  let mut player = AnimationPlayer::new(&WALK);
+  assert!(player.step());
+  player.play(&WALK);
+  assert_eq!(player.frame_index(), 1);
+  player.play(&JUMP);
+  assert_eq!(player.frame_index(), 0);
+  assert_eq!(player.current().map(|frame| frame.tile_offset), Some(16));
+}
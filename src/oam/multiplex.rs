@@ -0,0 +1,275 @@
+//! Module for showing more than 128 objects by reusing OAM slots mid-frame.
+//!
+//! The screen is split into bands of `band_height` lines. Each object goes in
+//! the band that holds its top line, and the bands take turns using the two
+//! halves of OAM (64 slots each): even bands use slots 0 to 63, odd bands use
+//! slots 64 to 127. While one band is being drawn, a VCount interrupt writes
+//! the band after it into the other half, over the band before it (which is
+//! finished by then).
+//!
+//! For that to work an object must finish before its half of OAM is reused,
+//! so keep objects no taller than `band_height - MULTIPLEX_LEAD_LINES`. Taller
+//! objects can have their bottom cut off. A band with more than 64 objects
+//! only shows the first 64, and a warning goes out through the `debug`
+//! module.
+//!
+//! The game builds each frame's objects in a `SpriteList` that it owns, and
+//! hands it to the `SpriteMultiplexer` during VBlank. The multiplexer is
+//! shared with the interrupt handler, but the game only locks it for
+//! `present`, so the handler never finds it locked while the screen is being
+//! drawn.
+//!
+//! ```no_run
+//! static SPRITES: Mutex<SpriteMultiplexer<256>> = Mutex::new(SpriteMultiplexer::new(32));
+//!
+//! extern "C" fn irq_handler(flags: IrqFlags) {
+//!   if flags.vcounter() {
+//!     if let Some(mut sprites) = SPRITES.try_lock() {
+//!       sprites.on_vcount();
+//!     }
+//!   }
+//! }
+//!
+//! let mut list = SpriteList::<256>::new();
+//! loop {
+//!   list.clear();
+//!   // push the objects with their screen positions
+//!   bios::vblank_interrupt_wait();
+//!   SPRITES.lock().present(&list);
+//! }
+//! ```
+//!
+//! Along with the handler, the VCount interrupt has to be enabled in `IE`.
+//! The multiplexer sets `DISPSTAT`'s VCount setting and IRQ enable itself.
+//!
+//! If a VCount interrupt is missed (or its handler can't lock the
+//! multiplexer), the VCount setting isn't moved on, so the bands after it
+//! don't show for the rest of that frame. The next `present` starts over from
+//! the top.
+
+use super::*;
+use crate::io::display::DISPSTAT;
+
+/// How many lines before a band starts its objects are written.
+pub const MULTIPLEX_LEAD_LINES: u16 = 2;
+
+/// The number of OAM slots each band can use.
+pub const BAND_CAPACITY: usize = OBJ_SLOT_COUNT / 2;
+
+/// The most bands a frame can have (with the smallest band height).
+const MAX_BANDS: usize = 160 / MIN_BAND_HEIGHT as usize;
+
+/// The smallest band height allowed.
+const MIN_BAND_HEIGHT: u16 = 16;
+
+/// A list of up to `N` objects for a `SpriteMultiplexer` to show.
+#[derive(Clone)]
+pub struct SpriteList<const N: usize> {
+  objects: [ObjectAttributes; N],
+  count: usize,
+}
+
+impl<const N: usize> SpriteList<N> {
+  /// Makes an empty list.
+  pub const fn new() -> Self {
+    Self { objects: [HIDDEN_OBJ_ATTRIBUTES; N], count: 0 }
+  }
+
+  /// Removes every object.
+  pub fn clear(&mut self) {
+    self.count = 0;
+  }
+
+  /// Adds an object, using its screen position.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` if the list already holds `N` objects.
+  pub fn push(&mut self, attributes: ObjectAttributes) -> Option<()> {
+    *self.objects.get_mut(self.count)? = attributes;
+    self.count += 1;
+    Some(())
+  }
+
+  /// The number of objects.
+  pub fn len(&self) -> usize {
+    self.count
+  }
+
+  /// If the list is empty.
+  pub fn is_empty(&self) -> bool {
+    self.count == 0
+  }
+
+  /// The objects, in the order they were pushed.
+  pub fn objects(&self) -> &[ObjectAttributes] {
+    &self.objects[..self.count]
+  }
+}
+
+impl<const N: usize> Default for SpriteList<N> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// Shows up to `N` objects, 64 per band.
+///
+/// This holds a sorted copy of the last list presented, so keep it in a
+/// `static` (behind a `Mutex`) rather than on the stack.
+#[derive(Clone)]
+pub struct SpriteMultiplexer<const N: usize> {
+  active: [ObjectAttributes; N],
+  active_count: usize,
+  band_height: u16,
+  band_starts: [u16; MAX_BANDS],
+  band_count: usize,
+  next_band: usize,
+}
+
+impl<const N: usize> SpriteMultiplexer<N> {
+  /// Makes an empty multiplexer.
+  ///
+  /// The band height is clamped to between 16 and 160 lines.
+  pub const fn new(band_height: u16) -> Self {
+    let band_height = if band_height < MIN_BAND_HEIGHT {
+      MIN_BAND_HEIGHT
+    } else if band_height > 160 {
+      160
+    } else {
+      band_height
+    };
+    Self {
+      active: [HIDDEN_OBJ_ATTRIBUTES; N],
+      active_count: 0,
+      band_height,
+      band_starts: [0; MAX_BANDS],
+      band_count: 0,
+      next_band: 0,
+    }
+  }
+
+  /// Shows a list, starting from the top of the screen.
+  ///
+  /// Call this during VBlank. It copies the list sorted by top line, writes
+  /// the first two bands into OAM, and sets up the VCount interrupt for the
+  /// third. The list isn't changed, so objects that don't move can stay in it.
+  pub fn present(&mut self, list: &SpriteList<N>) {
+    self.activate(list);
+    self.next_band = 0;
+    self.load_next_band();
+    self.load_next_band();
+    self.schedule();
+  }
+
+  /// Writes the next band into OAM. Call this from the interrupt handler when
+  /// a VCount interrupt happens.
+  pub fn on_vcount(&mut self) {
+    self.load_next_band();
+    self.schedule();
+  }
+
+  /// Copies a list into the active list, sorted by top line, and splits it
+  /// into bands.
+  fn activate(&mut self, list: &SpriteList<N>) {
+    self.active[..list.len()].copy_from_slice(list.objects());
+    self.active_count = list.len();
+    self.active[..self.active_count].sort_unstable_by_key(top_line);
+    self.plan_bands();
+  }
+
+  /// Finds where each band starts in the sorted list, and warns about bands
+  /// that are over capacity.
+  fn plan_bands(&mut self) {
+    let visible_bands = ((159 / self.band_height) + 1) as usize;
+    let mut sprite = 0;
+    for band in 0..visible_bands {
+      self.band_starts[band] = sprite as u16;
+      let band_end = (band as i32 + 1) * self.band_height as i32;
+      let first = sprite;
+      while sprite < self.active_count && top_line(&self.active[sprite]) < band_end {
+        sprite += 1;
+      }
+      if sprite - first > BAND_CAPACITY {
+        crate::warn!(
+          "Sprite multiplexer band {} has {} objects, only {} are shown",
+          band,
+          sprite - first,
+          BAND_CAPACITY
+        );
+      }
+    }
+    self.band_count = visible_bands;
+  }
+
+  /// The objects of a band, limited to what fits.
+  fn band(&self, band: usize) -> &[ObjectAttributes] {
+    let start = self.band_starts[band] as usize;
+    let end = if band + 1 < self.band_count {
+      self.band_starts[band + 1] as usize
+    } else {
+      self.active_count
+    };
+    &self.active[start..end.min(start + BAND_CAPACITY)]
+  }
+
+  /// Writes the next band into its half of OAM, hiding the unused slots.
+  fn load_next_band(&mut self) {
+    let band = self.next_band;
+    if band >= self.band_count {
+      return;
+    }
+    let first_slot = (band % 2) * BAND_CAPACITY;
+    let objects = self.band(band);
+    for i in 0..BAND_CAPACITY {
+      let attributes = objects.get(i).copied().unwrap_or(HIDDEN_OBJ_ATTRIBUTES);
+      write_obj_attributes(first_slot + i, attributes);
+    }
+    self.next_band += 1;
+  }
+
+  /// Sets the VCount interrupt for the next band, or turns it off if there
+  /// are no more bands this frame.
+  fn schedule(&self) {
+    let status = DISPSTAT.read();
+    if self.next_band < self.band_count {
+      let line = (self.next_band as u16 * self.band_height).saturating_sub(MULTIPLEX_LEAD_LINES);
+      DISPSTAT.write(status.with_vcount_setting(line).with_vcounter_irq_enable(true));
+    } else {
+      DISPSTAT.write(status.with_vcounter_irq_enable(false));
+    }
+  }
+}
+
+/// The screen row of an object's top line, with rows 160 and up counting as
+/// above the screen (the row coordinate wraps at 256).
+fn top_line(attributes: &ObjectAttributes) -> i32 {
+  let row = attributes.attr0.row_coordinate() as i32;
+  if row >= 160 {
+    row - 256
+  } else {
+    row
+  }
+}
+
+#[test]
+fn test_band_planning() {
+  let at_row = |row: u16| ObjectAttributes {
+    attr0: OBJAttr0::new().with_row_coordinate(row),
+    ..Default::default()
+  };
+  let mut list = SpriteList::<5>::new();
+  for &row in &[100, 250, 40, 31, 0] {
+    list.push(at_row(row)).unwrap();
+  }
+  assert_eq!(list.push(at_row(0)), None);
+  let mut sprites = SpriteMultiplexer::<5>::new(32);
+  sprites.activate(&list);
+  assert_eq!(sprites.band_count, 5);
+  assert_eq!(sprites.band(0).len(), 3);
+  assert_eq!(sprites.band(0)[0].attr0.row_coordinate(), 250);
+  assert_eq!(sprites.band(1).len(), 1);
+  assert_eq!(sprites.band(2).len(), 0);
+  assert_eq!(sprites.band(3)[0].attr0.row_coordinate(), 100);
+  assert_eq!(sprites.band(4).len(), 0);
+}
@@ -25,6 +25,8 @@
 
 use super::*;
 
+pub mod effects;
+
 // TODO: PalIndex newtypes?
 
 /// The `PALRAM` for background colors, 256 slot view.
@@ -0,0 +1,283 @@
+//! Module for palette effects: fades, color cycling and palbank flashes.
+//!
+//! A `PaletteEffects` keeps a source copy of both palettes (what the palettes
+//! "really" are) and an output copy (what's shown this frame). Each frame you
+//! call `step` to advance the effects and rebuild the output, then `commit`
+//! during VBlank to copy the output into `PALRAM`.
+//!
+//! Palette indexes here cover both palettes at once: 0 to 255 are the
+//! background palette and 256 to 511 are the object palette. In the same way,
+//! palbanks 0 to 15 are background palbanks and 16 to 31 are object palbanks.
+//!
+//! Blend levels go from 0 (all the first color) to 32 (all the second color),
+//! as with `Color::lerp`.
+
+use super::*;
+use crate::bios;
+
+/// The total number of colors in both palettes.
+pub const PALETTE_COLOR_COUNT: usize = 512;
+
+/// The index of the first object palette color.
+pub const OBJ_PALETTE_START: usize = 256;
+
+/// The number of color cycles that can be active at once.
+pub const MAX_CYCLES: usize = 8;
+
+/// The blend level that gives all of the second color.
+pub const MAX_BLEND_LEVEL: u8 = 32;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Fade {
+  target: Color,
+  from: u8,
+  to: u8,
+  frames: u16,
+  elapsed: u16,
+}
+impl Fade {
+  fn level(&self) -> u8 {
+    if self.elapsed >= self.frames {
+      self.to
+    } else {
+      let delta = self.to as i32 - self.from as i32;
+      (self.from as i32 + bios::div(delta * self.elapsed as i32, self.frames as i32)) as u8
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Cycle {
+  start: u16,
+  len: u16,
+  frames_per_step: u16,
+  timer: u16,
+  offset: u16,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Flash {
+  color: Color,
+  level: u8,
+  frames: u16,
+}
+
+/// Palette fades, color cycles and palbank flashes.
+///
+/// This is about 2.5k, so keep it in a `static` (behind a `Mutex`) rather
+/// than on the stack.
+#[derive(Clone)]
+#[repr(C, align(4))]
+pub struct PaletteEffects {
+  output: [Color; PALETTE_COLOR_COUNT],
+  source: [Color; PALETTE_COLOR_COUNT],
+  fade: Fade,
+  cycles: [Cycle; MAX_CYCLES],
+  flashes: [Flash; 32],
+}
+
+impl PaletteEffects {
+  /// Makes a set of effects with an all black source palette and no effects
+  /// running.
+  pub const fn new() -> Self {
+    const NO_CYCLE: Cycle = Cycle { start: 0, len: 0, frames_per_step: 0, timer: 0, offset: 0 };
+    const NO_FLASH: Flash = Flash { color: Color(0), level: 0, frames: 0 };
+    Self {
+      output: [Color(0); PALETTE_COLOR_COUNT],
+      source: [Color(0); PALETTE_COLOR_COUNT],
+      fade: Fade { target: Color(0), from: 0, to: 0, frames: 0, elapsed: 0 },
+      cycles: [NO_CYCLE; MAX_CYCLES],
+      flashes: [NO_FLASH; 32],
+    }
+  }
+
+  /// Copies the current contents of `PALRAM` into the source palette.
+  pub fn capture(&mut self) {
+    for (i, va) in PALRAM_BG.iter().chain(PALRAM_OBJ.iter()).enumerate() {
+      self.source[i] = va.read();
+    }
+  }
+
+  /// The source color at an index.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` if the index is out of bounds.
+  pub fn source(&self, index: usize) -> Option<Color> {
+    self.source.get(index).copied()
+  }
+
+  /// Sets a source color.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` if the index is out of bounds.
+  pub fn set_source(&mut self, index: usize, color: Color) -> Option<()> {
+    *self.source.get_mut(index)? = color;
+    Some(())
+  }
+
+  /// Sets a run of source colors, starting at `index`.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` (and changes nothing) if the run goes out of bounds.
+  pub fn set_source_colors(&mut self, index: usize, colors: &[Color]) -> Option<()> {
+    self.source.get_mut(index..index.checked_add(colors.len())?)?.copy_from_slice(colors);
+    Some(())
+  }
+
+  /// Sets all 16 source colors of a palbank.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` if the palbank is 32 or more.
+  pub fn set_source_palbank(&mut self, palbank: usize, colors: &[Color; 16]) -> Option<()> {
+    if palbank >= 32 {
+      return None;
+    }
+    self.set_source_colors(palbank * 16, colors)
+  }
+
+  /// Fades from `from` to `to` (blend levels towards `target`) over some
+  /// number of frames.
+  ///
+  /// A fade of 0 frames jumps straight to `to`.
+  pub fn start_fade(&mut self, target: Color, from: u8, to: u8, frames: u16) {
+    let from = from.min(MAX_BLEND_LEVEL);
+    let to = to.min(MAX_BLEND_LEVEL);
+    self.fade = Fade { target, from, to, frames, elapsed: 0 };
+  }
+
+  /// Fades from the source palette out to a color.
+  pub fn fade_out(&mut self, target: Color, frames: u16) {
+    self.start_fade(target, 0, MAX_BLEND_LEVEL, frames)
+  }
+
+  /// Fades from a color in to the source palette.
+  pub fn fade_in(&mut self, target: Color, frames: u16) {
+    self.start_fade(target, MAX_BLEND_LEVEL, 0, frames)
+  }
+
+  /// Holds the fade at a fixed level.
+  pub fn set_fade_level(&mut self, target: Color, level: u8) {
+    self.start_fade(target, level, level, 0)
+  }
+
+  /// The current fade level.
+  pub fn fade_level(&self) -> u8 {
+    self.fade.level()
+  }
+
+  /// If the fade has reached its final level.
+  pub fn is_fade_done(&self) -> bool {
+    self.fade.elapsed >= self.fade.frames
+  }
+
+  /// Rotates the colors `start..start+len` by one place every
+  /// `frames_per_step` frames, and gives an id for `remove_cycle`.
+  ///
+  /// Color `start + i` shows the source color `start + (i + n) % len` after
+  /// `n` steps, so the colors appear to move towards `start`.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` if all cycle slots are in use, the range is out of bounds,
+  /// `len` is less than 2, or `frames_per_step` is 0.
+  pub fn add_cycle(&mut self, start: usize, len: usize, frames_per_step: u16) -> Option<usize> {
+    if len < 2 || frames_per_step == 0 || start.checked_add(len)? > PALETTE_COLOR_COUNT {
+      return None;
+    }
+    let id = self.cycles.iter().position(|cycle| cycle.len == 0)?;
+    self.cycles[id] =
+      Cycle { start: start as u16, len: len as u16, frames_per_step, timer: 0, offset: 0 };
+    Some(id)
+  }
+
+  /// Stops a color cycle, putting the colors back in their source order.
+  pub fn remove_cycle(&mut self, id: usize) {
+    if let Some(cycle) = self.cycles.get_mut(id) {
+      *cycle = Cycle::default();
+    }
+  }
+
+  /// Blends a palbank towards a color at `level` for some number of frames.
+  ///
+  /// This is for things like flashing a sprite white when it's hit. Starting
+  /// a new flash on a palbank replaces the old one.
+  pub fn flash_palbank(&mut self, palbank: usize, color: Color, level: u8, frames: u16) {
+    if let Some(flash) = self.flashes.get_mut(palbank) {
+      *flash = Flash { color, level: level.min(MAX_BLEND_LEVEL), frames };
+    }
+  }
+
+  /// Advances all effects by one frame and rebuilds the output palette.
+  pub fn step(&mut self) {
+    if self.fade.elapsed < self.fade.frames {
+      self.fade.elapsed += 1;
+    }
+    for cycle in self.cycles.iter_mut().filter(|cycle| cycle.len != 0) {
+      cycle.timer += 1;
+      if cycle.timer >= cycle.frames_per_step {
+        cycle.timer = 0;
+        cycle.offset = if cycle.offset + 1 >= cycle.len { 0 } else { cycle.offset + 1 };
+      }
+    }
+    self.rebuild();
+    for flash in self.flashes.iter_mut() {
+      flash.frames = flash.frames.saturating_sub(1);
+    }
+  }
+
+  /// Rebuilds the output palette without advancing any effects.
+  pub fn rebuild(&mut self) {
+    self.output = self.source;
+    for cycle in self.cycles.iter().filter(|cycle| cycle.len != 0) {
+      let (start, len) = (cycle.start as usize, cycle.len as usize);
+      for i in 0..len {
+        let mut from = i + cycle.offset as usize;
+        if from >= len {
+          from -= len;
+        }
+        self.output[start + i] = self.source[start + from];
+      }
+    }
+    for (palbank, flash) in self.flashes.iter().enumerate() {
+      if flash.frames > 0 && flash.level > 0 {
+        for color in self.output[palbank * 16..palbank * 16 + 16].iter_mut() {
+          *color = color.lerp(flash.color, flash.level);
+        }
+      }
+    }
+    let level = self.fade.level();
+    if level > 0 {
+      let target = self.fade.target;
+      for color in self.output.iter_mut() {
+        *color = color.lerp(target, level);
+      }
+    }
+  }
+
+  /// The color that `commit` will write at an index.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` if the index is out of bounds.
+  pub fn output(&self, index: usize) -> Option<Color> {
+    self.output.get(index).copied()
+  }
+
+  /// Copies the output palette into `PALRAM` using DMA3.
+  ///
+  /// Call this during VBlank to avoid changing colors mid-frame.
+  pub fn commit(&self) {
+    use crate::io::dma::DMA3;
+    unsafe {
+      DMA3::copy32(
+        self.output.as_ptr() as *const u32,
+        PALRAM_BG.index(0).as_usize() as *mut u32,
+        (PALETTE_COLOR_COUNT / 2) as u16,
+      )
+    };
+  }
+}
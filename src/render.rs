@@ -0,0 +1,613 @@
+//! Module for rendering snapshots of the video hardware on the host.
+//!
+//! This is only available with the `std` feature, and it's meant for tests
+//! that run on a PC: build (or load) a `Snapshot` of VRAM, OAM, PALRAM and
+//! the video registers, `render` it, and compare the `Frame` against a golden
+//! image.
+//!
+//! The renderer covers text and affine backgrounds, bitmap modes 3 to 5,
+//! regular and affine objects, all three windows, and the color special
+//! effects. It draws the whole frame from one set of register values, so
+//! HBlank effects and mosaic aren't shown.
+//!
+//! Blending uses the `Color` methods that copy the hardware arithmetic, so
+//! blended pixels come out the same as on the GBA.
+
+use super::*;
+use crate::{
+  io::{
+    background::{AffineBgParams, BGSize, BackgroundControlSetting},
+    color_blend::{
+      AlphaBlendingSetting, BrightnessSetting, ColorEffectSetting, ColorSpecialEffect,
+    },
+    display::{DisplayControlSetting, DisplayMode, LayerSet},
+    window::{
+      HorizontalWindowSetting, InsideWindowSetting, OutsideWindowSetting, VerticalWindowSetting,
+      WindowRect,
+    },
+  },
+  oam::{obj_dimensions, AffineParameters, ObjectAttributes, ObjectShape, ObjectSize},
+};
+
+/// The width of the screen in pixels.
+pub const SCREEN_WIDTH: usize = 240;
+
+/// The height of the screen in pixels.
+pub const SCREEN_HEIGHT: usize = 160;
+
+/// The size of VRAM in bytes.
+pub const VRAM_SIZE: usize = 96 * 1024;
+
+/// The size of OAM in bytes.
+pub const OAM_SIZE: usize = 1024;
+
+/// The number of colors in PALRAM (background then object palette).
+pub const PALRAM_COLORS: usize = 512;
+
+/// A copy of everything the display hardware reads to draw a frame.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+  /// All of VRAM.
+  pub vram: Vec<u8>,
+  /// All of OAM.
+  pub oam: Vec<u8>,
+  /// Both palettes, background first.
+  pub palram: Vec<Color>,
+  /// `DISPCNT`.
+  pub display: DisplayControlSetting,
+  /// `BG0CNT` through `BG3CNT`.
+  pub bg_control: [BackgroundControlSetting; 4],
+  /// The `HOFS` and `VOFS` values of each background.
+  pub bg_scroll: [(u16, u16); 4],
+  /// The BG2 affine registers (also used by the bitmap modes).
+  pub bg2_affine: AffineBgParams,
+  /// The BG3 affine registers.
+  pub bg3_affine: AffineBgParams,
+  /// `WIN0H` and `WIN1H`.
+  pub win_h: [HorizontalWindowSetting; 2],
+  /// `WIN0V` and `WIN1V`.
+  pub win_v: [VerticalWindowSetting; 2],
+  /// `WININ`.
+  pub win_in: InsideWindowSetting,
+  /// `WINOUT`.
+  pub win_out: OutsideWindowSetting,
+  /// `BLDCNT`.
+  pub blend: ColorEffectSetting,
+  /// `BLDALPHA`.
+  pub alpha: AlphaBlendingSetting,
+  /// `BLDY`.
+  pub brightness: BrightnessSetting,
+}
+
+impl Snapshot {
+  /// Makes a snapshot with all memory zeroed, as after a reset (except that
+  /// the affine backgrounds have the identity matrix).
+  pub fn new() -> Self {
+    Self {
+      vram: vec![0; VRAM_SIZE],
+      oam: vec![0; OAM_SIZE],
+      palram: vec![Color(0); PALRAM_COLORS],
+      display: DisplayControlSetting::new(),
+      bg_control: [BackgroundControlSetting::new(); 4],
+      bg_scroll: [(0, 0); 4],
+      bg2_affine: AffineBgParams::IDENTITY,
+      bg3_affine: AffineBgParams::IDENTITY,
+      win_h: [HorizontalWindowSetting::new(); 2],
+      win_v: [VerticalWindowSetting::new(); 2],
+      win_in: InsideWindowSetting::new(),
+      win_out: OutsideWindowSetting::new(),
+      blend: ColorEffectSetting::new(),
+      alpha: AlphaBlendingSetting::new(),
+      brightness: BrightnessSetting::new(),
+    }
+  }
+
+  /// Copies bytes into VRAM, starting at a byte offset.
+  ///
+  /// ## Panics
+  ///
+  /// If the data goes past the end of VRAM.
+  pub fn write_vram(&mut self, offset: usize, data: &[u8]) {
+    self.vram[offset..offset + data.len()].copy_from_slice(data);
+  }
+
+  /// Copies words into VRAM (such as the contents of a `Tile4bpp`), starting
+  /// at a byte offset.
+  ///
+  /// ## Panics
+  ///
+  /// If the data goes past the end of VRAM.
+  pub fn write_vram_words(&mut self, offset: usize, data: &[u32]) {
+    for (i, word) in data.iter().enumerate() {
+      self.write_vram(offset + i * 4, &word.to_le_bytes());
+    }
+  }
+
+  /// Sets the attributes of an object.
+  ///
+  /// ## Panics
+  ///
+  /// If the slot is 128 or more.
+  pub fn set_obj_attributes(&mut self, slot: usize, attributes: ObjectAttributes) {
+    // `ObjectAttributes` has the same layout as the first three halfwords.
+    let halfwords: [u16; 3] = unsafe { core::mem::transmute(attributes) };
+    for (i, halfword) in halfwords.iter().enumerate() {
+      self.oam[slot * 8 + i * 2..slot * 8 + i * 2 + 2].copy_from_slice(&halfword.to_le_bytes());
+    }
+  }
+
+  /// Sets an object affine matrix.
+  ///
+  /// ## Panics
+  ///
+  /// If the slot is 32 or more.
+  pub fn set_obj_affine(&mut self, slot: usize, params: AffineParameters) {
+    for (i, value) in [params.pa, params.pb, params.pc, params.pd].iter().enumerate() {
+      let offset = slot * 32 + i * 8 + 6;
+      self.oam[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+    }
+  }
+
+  /// Draws the frame.
+  pub fn render(&self) -> Frame {
+    let mut frame = Frame { pixels: vec![Color(0x7FFF); SCREEN_WIDTH * SCREEN_HEIGHT] };
+    if self.display.force_vblank() {
+      return frame;
+    }
+    for y in 0..SCREEN_HEIGHT {
+      let objects = self.obj_line(y);
+      for x in 0..SCREEN_WIDTH {
+        frame.pixels[y * SCREEN_WIDTH + x] = self.compose(x, y, &objects);
+      }
+    }
+    frame
+  }
+
+  fn vram_u8(&self, offset: usize) -> u8 {
+    self.vram.get(offset).copied().unwrap_or(0)
+  }
+
+  fn vram_u16(&self, offset: usize) -> u16 {
+    u16::from_le_bytes([self.vram_u8(offset), self.vram_u8(offset + 1)])
+  }
+
+  fn oam_u16(&self, offset: usize) -> u16 {
+    u16::from_le_bytes([self.oam[offset], self.oam[offset + 1]])
+  }
+
+  fn is_bitmap_mode(&self) -> bool {
+    matches!(self.display.mode(), DisplayMode::Mode3 | DisplayMode::Mode4 | DisplayMode::Mode5)
+  }
+
+  /// Works out the final color of one pixel.
+  fn compose(&self, x: usize, y: usize, objects: &ObjLine) -> Color {
+    let (visible, effects) = self.window_at(x, y, objects.window[x]);
+    let backdrop = Candidate { color: self.palram[0], key: u16::MAX, layer: 5, semi: false };
+    let (mut top, mut below) = (backdrop, backdrop);
+    let mut offer = |candidate: Candidate| {
+      if candidate.key < top.key {
+        below = top;
+        top = candidate;
+      } else if candidate.key < below.key {
+        below = candidate;
+      }
+    };
+    let enabled = [self.display.bg0(), self.display.bg1(), self.display.bg2(), self.display.bg3()];
+    for (bg, &on) in enabled.iter().enumerate() {
+      if on && visible.contains(LayerSet::from_bits(1 << bg)) {
+        if let Some(color) = self.bg_pixel(bg, x, y) {
+          let key = self.bg_control[bg].bg_priority() * 8 + 1 + bg as u16;
+          offer(Candidate { color, key, layer: bg as u16, semi: false });
+        }
+      }
+    }
+    if self.display.obj() && visible.contains(LayerSet::OBJ) {
+      if let Some(pixel) = objects.pixels[x] {
+        offer(Candidate {
+          color: pixel.color,
+          key: pixel.priority * 8,
+          layer: 4,
+          semi: pixel.semi,
+        });
+      }
+    }
+    if effects {
+      self.apply_effect(top, below)
+    } else {
+      top.color
+    }
+  }
+
+  /// Gives the visible layers and if effects are on for a pixel.
+  fn window_at(&self, x: usize, y: usize, in_obj_window: bool) -> (LayerSet, bool) {
+    let display = self.display;
+    if !(display.win0() || display.win1() || display.obj_window()) {
+      return (LayerSet::ALL, true);
+    }
+    let rect = |n: usize| {
+      let (h, v) = (self.win_h[n], self.win_v[n]);
+      WindowRect::new(
+        h.col_start() as u8,
+        v.row_start() as u8,
+        h.col_end() as u8,
+        v.row_end() as u8,
+      )
+    };
+    let (x, y) = (x as u8, y as u8);
+    if display.win0() && rect(0).contains(x, y) {
+      (self.win_in.win0_layers(), self.win_in.win0_color_special())
+    } else if display.win1() && rect(1).contains(x, y) {
+      (self.win_in.win1_layers(), self.win_in.win1_color_special())
+    } else if display.obj_window() && in_obj_window {
+      (self.win_out.obj_win_layers(), self.win_out.obj_win_color_special())
+    } else {
+      (self.win_out.outside_layers(), self.win_out.outside_color_special())
+    }
+  }
+
+  /// Applies the color special effect to the top pixel.
+  fn apply_effect(&self, top: Candidate, below: Candidate) -> Color {
+    let first = self.blend.first_targets();
+    let second = self.blend.second_targets();
+    let top_layer = LayerSet::from_bits(1 << top.layer);
+    let below_is_second = second.contains(LayerSet::from_bits(1 << below.layer));
+    let eva = self.alpha.eva_coefficient();
+    let evb = self.alpha.evb_coefficient();
+    let evy = self.brightness.evy_coefficient() as u16;
+    if top.semi && below_is_second {
+      return Color::blend_alpha(top.color, eva, below.color, evb);
+    }
+    if !first.contains(top_layer) {
+      return top.color;
+    }
+    match self.blend.color_special_effect() {
+      ColorSpecialEffect::AlphaBlending if below_is_second => {
+        Color::blend_alpha(top.color, eva, below.color, evb)
+      }
+      ColorSpecialEffect::BrightnessIncrease => top.color.brighten(evy),
+      ColorSpecialEffect::BrightnessDecrease => top.color.darken(evy),
+      _ => top.color,
+    }
+  }
+
+  /// The color of a background at a screen pixel, or `None` if the
+  /// background is transparent there (or not available in this mode).
+  fn bg_pixel(&self, bg: usize, x: usize, y: usize) -> Option<Color> {
+    match (self.display.mode(), bg) {
+      (DisplayMode::Mode0, _) | (DisplayMode::Mode1, 0) | (DisplayMode::Mode1, 1) => {
+        self.text_bg_pixel(bg, x, y)
+      }
+      (DisplayMode::Mode1, 2) | (DisplayMode::Mode2, 2) | (DisplayMode::Mode2, 3) => {
+        self.affine_bg_pixel(bg, x, y)
+      }
+      (DisplayMode::Mode3, 2) | (DisplayMode::Mode4, 2) | (DisplayMode::Mode5, 2) => {
+        self.bitmap_pixel(x, y)
+      }
+      _ => None,
+    }
+  }
+
+  fn text_bg_pixel(&self, bg: usize, x: usize, y: usize) -> Option<Color> {
+    let control = self.bg_control[bg];
+    let size = control.size();
+    let (width, height) = match size {
+      BGSize::Zero => (256, 256),
+      BGSize::One => (512, 256),
+      BGSize::Two => (256, 512),
+      BGSize::Three => (512, 512),
+    };
+    let (h_offset, v_offset) = self.bg_scroll[bg];
+    let px = (x + (h_offset & 0x1FF) as usize) % width;
+    let py = (y + (v_offset & 0x1FF) as usize) % height;
+    let (tile_x, tile_y) = (px / 8, py / 8);
+    let block = match size {
+      BGSize::Zero => 0,
+      BGSize::One => tile_x / 32,
+      BGSize::Two => tile_y / 32,
+      BGSize::Three => (tile_y / 32) * 2 + tile_x / 32,
+    };
+    let entry_offset = (control.screen_base_block() as usize + block) * 0x800
+      + ((tile_y % 32) * 32 + tile_x % 32) * 2;
+    let entry = self.vram_u16(entry_offset);
+    let tile_id = (entry & 0x3FF) as usize;
+    let col = if entry & (1 << 10) != 0 { 7 - px % 8 } else { px % 8 };
+    let row = if entry & (1 << 11) != 0 { 7 - py % 8 } else { py % 8 };
+    let char_base = control.char_base_block() as usize * 0x4000;
+    if control.is_8bpp() {
+      let offset = char_base + tile_id * 64 + row * 8 + col;
+      self.bg_tile_color(offset, |byte| byte as usize)
+    } else {
+      let palbank = (entry >> 12) as usize;
+      let offset = char_base + tile_id * 32 + row * 4 + col / 2;
+      self.bg_tile_color(offset, |byte| match nibble(byte, col) {
+        0 => 0,
+        index => palbank * 16 + index as usize,
+      })
+    }
+  }
+
+  /// Looks up a background tile pixel, where tiles past the background
+  /// region of VRAM always read as transparent.
+  fn bg_tile_color(&self, offset: usize, index: impl Fn(u8) -> usize) -> Option<Color> {
+    if offset >= 0x1_0000 {
+      return None;
+    }
+    match index(self.vram_u8(offset)) {
+      0 => None,
+      i => Some(self.palram[i]),
+    }
+  }
+
+  /// The texture position of a screen pixel through affine parameters.
+  fn affine_position(params: &AffineBgParams, x: usize, y: usize) -> (i32, i32) {
+    let (x, y) = (x as i32, y as i32);
+    let tx =
+      params.x.into_raw() + params.pa.into_raw() as i32 * x + params.pb.into_raw() as i32 * y;
+    let ty =
+      params.y.into_raw() + params.pc.into_raw() as i32 * x + params.pd.into_raw() as i32 * y;
+    (tx >> 8, ty >> 8)
+  }
+
+  fn affine_bg_pixel(&self, bg: usize, x: usize, y: usize) -> Option<Color> {
+    let control = self.bg_control[bg];
+    let params = if bg == 2 { &self.bg2_affine } else { &self.bg3_affine };
+    let size = 128 << control.size() as i32;
+    let (mut tx, mut ty) = Self::affine_position(params, x, y);
+    if control.affine_display_overflow_wrapping() {
+      tx = tx.rem_euclid(size);
+      ty = ty.rem_euclid(size);
+    } else if tx < 0 || ty < 0 || tx >= size || ty >= size {
+      return None;
+    }
+    let (tx, ty, size) = (tx as usize, ty as usize, size as usize);
+    let map_offset = control.screen_base_block() as usize * 0x800 + (ty / 8) * (size / 8) + tx / 8;
+    let tile_id = self.vram_u8(map_offset) as usize;
+    let offset = control.char_base_block() as usize * 0x4000 + tile_id * 64 + (ty % 8) * 8 + tx % 8;
+    self.bg_tile_color(offset, |byte| byte as usize)
+  }
+
+  fn bitmap_pixel(&self, x: usize, y: usize) -> Option<Color> {
+    let mode = self.display.mode();
+    let (width, height) = if mode == DisplayMode::Mode5 { (160, 128) } else { (240, 160) };
+    let (tx, ty) = Self::affine_position(&self.bg2_affine, x, y);
+    if tx < 0 || ty < 0 || tx >= width || ty >= height {
+      return None;
+    }
+    let index = (ty * width + tx) as usize;
+    let page = if self.display.frame1() { 0xA000 } else { 0 };
+    match mode {
+      DisplayMode::Mode3 => Some(Color(self.vram_u16(index * 2))),
+      DisplayMode::Mode4 => match self.vram_u8(page + index) {
+        0 => None,
+        i => Some(self.palram[i as usize]),
+      },
+      _ => Some(Color(self.vram_u16(page + index * 2))),
+    }
+  }
+
+  /// Draws every object that touches a scanline.
+  fn obj_line(&self, y: usize) -> ObjLine {
+    let mut line = ObjLine { pixels: [None; SCREEN_WIDTH], window: [false; SCREEN_WIDTH] };
+    if !self.display.obj() {
+      return line;
+    }
+    for slot in 0..128 {
+      let attr0 = self.oam_u16(slot * 8);
+      let attr1 = self.oam_u16(slot * 8 + 2);
+      let attr2 = self.oam_u16(slot * 8 + 4);
+      let rendering = (attr0 >> 8) & 0b11;
+      let mode = (attr0 >> 10) & 0b11;
+      if rendering == 2 || mode == 3 {
+        continue;
+      }
+      let shape = match attr0 >> 14 {
+        0 => ObjectShape::Square,
+        1 => ObjectShape::Horizontal,
+        2 => ObjectShape::Vertical,
+        _ => continue,
+      };
+      let size = match attr1 >> 14 {
+        0 => ObjectSize::Zero,
+        1 => ObjectSize::One,
+        2 => ObjectSize::Two,
+        _ => ObjectSize::Three,
+      };
+      let (width, height) = obj_dimensions(shape, size);
+      let (width, height) = (width as i32, height as i32);
+      let affine = rendering & 1 != 0;
+      let double = rendering == 3;
+      let (box_width, box_height) = if double { (width * 2, height * 2) } else { (width, height) };
+      let local_y = (y as i32 - (attr0 & 0xFF) as i32) & 0xFF;
+      if local_y >= box_height {
+        continue;
+      }
+      let matrix = if affine {
+        let base = ((attr1 >> 9) & 0x1F) as usize * 32;
+        let read = |i: usize| self.oam_u16(base + i * 8 + 6) as i16 as i32;
+        Some((read(0), read(1), read(2), read(3)))
+      } else {
+        None
+      };
+      for x in 0..SCREEN_WIDTH {
+        let local_x = (x as i32 - (attr1 & 0x1FF) as i32) & 0x1FF;
+        if local_x >= box_width {
+          continue;
+        }
+        let (tx, ty) = match matrix {
+          Some((pa, pb, pc, pd)) => {
+            let (dx, dy) = (local_x - box_width / 2, local_y - box_height / 2);
+            (((pa * dx + pb * dy) >> 8) + width / 2, ((pc * dx + pd * dy) >> 8) + height / 2)
+          }
+          None => (
+            if attr1 & (1 << 12) != 0 { width - 1 - local_x } else { local_x },
+            if attr1 & (1 << 13) != 0 { height - 1 - local_y } else { local_y },
+          ),
+        };
+        if tx < 0 || ty < 0 || tx >= width || ty >= height {
+          continue;
+        }
+        let color = match self.obj_texel(attr0, attr2, width, tx as usize, ty as usize) {
+          Some(color) => color,
+          None => continue,
+        };
+        if mode == 2 {
+          line.window[x] = true;
+          continue;
+        }
+        let priority = (attr2 >> 10) & 0b11;
+        if line.pixels[x].map(|old| priority < old.priority).unwrap_or(true) {
+          line.pixels[x] = Some(ObjPixel { color, priority, semi: mode == 1 });
+        }
+      }
+    }
+    line
+  }
+
+  /// The color of an object's texture at a position within the object.
+  fn obj_texel(&self, attr0: u16, attr2: u16, width: i32, tx: usize, ty: usize) -> Option<Color> {
+    let is_8bpp = attr0 & (1 << 13) != 0;
+    let units_per_tile = if is_8bpp { 2 } else { 1 };
+    let (tile_x, tile_y) = (tx / 8, ty / 8);
+    let base = (attr2 & 0x3FF) as usize;
+    let unit = if self.display.oam_memory_1d() {
+      base + (tile_y * (width as usize / 8) + tile_x) * units_per_tile
+    } else {
+      base + tile_y * 32 + tile_x * units_per_tile
+    } & 0x3FF;
+    if self.is_bitmap_mode() && unit < 512 {
+      return None;
+    }
+    let offset = 0x1_0000 + unit * 32;
+    let (col, row) = (tx % 8, ty % 8);
+    let index = if is_8bpp {
+      self.vram_u8(offset + row * 8 + col) as usize
+    } else {
+      match nibble(self.vram_u8(offset + row * 4 + col / 2), col) {
+        0 => 0,
+        index => (attr2 >> 12) as usize * 16 + index as usize,
+      }
+    };
+    match index {
+      0 => None,
+      i => Some(self.palram[256 + i]),
+    }
+  }
+}
+
+impl Default for Snapshot {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// The 4bpp palette index of a column from its byte of tile data.
+fn nibble(byte: u8, col: usize) -> u8 {
+  if col % 2 == 0 {
+    byte & 0xF
+  } else {
+    byte >> 4
+  }
+}
+
+/// A layer's pixel competing to be shown. Lower keys are on top.
+#[derive(Debug, Clone, Copy)]
+struct Candidate {
+  color: Color,
+  key: u16,
+  layer: u16,
+  semi: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ObjPixel {
+  color: Color,
+  priority: u16,
+  semi: bool,
+}
+
+struct ObjLine {
+  pixels: [Option<ObjPixel>; SCREEN_WIDTH],
+  window: [bool; SCREEN_WIDTH],
+}
+
+/// A rendered frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+  /// The pixels, row by row.
+  pub pixels: Vec<Color>,
+}
+
+impl Frame {
+  /// The color of a pixel.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` if the position is off screen.
+  pub fn pixel(&self, x: usize, y: usize) -> Option<Color> {
+    if x < SCREEN_WIDTH && y < SCREEN_HEIGHT {
+      Some(self.pixels[y * SCREEN_WIDTH + x])
+    } else {
+      None
+    }
+  }
+
+  /// The frame as 8 bit RGB bytes, row by row, for saving as an image.
+  pub fn to_rgb888(&self) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(self.pixels.len() * 3);
+    for color in self.pixels.iter() {
+      let (r, g, b) = color.to_rgb888();
+      bytes.extend_from_slice(&[r, g, b]);
+    }
+    bytes
+  }
+
+  /// The number of pixels that differ between two frames.
+  pub fn diff_count(&self, other: &Frame) -> usize {
+    self.pixels.iter().zip(other.pixels.iter()).filter(|(a, b)| a != b).count()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::oam::{OBJAttr0, OBJAttr1, OBJAttr2};
+
+  #[test]
+  fn test_text_bg_and_object() {
+    let mut snapshot = Snapshot::new();
+    let (red, blue, green) =
+      (Color::from_rgb(31, 0, 0), Color::from_rgb(0, 0, 31), Color::from_rgb(0, 31, 0));
+    snapshot.palram[0] = green;
+    snapshot.palram[1] = red;
+    snapshot.palram[256 + 1] = blue;
+    // Tile 1 of charblock 0 is solid color 1, and the first map entry uses it.
+    snapshot.write_vram_words(32, &[0x1111_1111; 8]);
+    snapshot.write_vram(31 * 0x800, &[1, 0]);
+    snapshot.bg_control[0] = BackgroundControlSetting::new().with_screen_base_block(31);
+    // A solid 8x8 object at (4, 4).
+    snapshot.write_vram_words(0x1_0000 + 32, &[0x1111_1111; 8]);
+    snapshot.set_obj_attributes(
+      0,
+      ObjectAttributes {
+        attr0: OBJAttr0::new().with_row_coordinate(4),
+        attr1: OBJAttr1::new().with_col_coordinate(4),
+        attr2: OBJAttr2::new().with_tile_id(1),
+      },
+    );
+    snapshot.display = DisplayControlSetting::new().with_bg0(true).with_obj(true);
+    let frame = snapshot.render();
+    assert_eq!(frame.pixel(0, 0), Some(red));
+    assert_eq!(frame.pixel(8, 0), Some(green));
+    assert_eq!(frame.pixel(4, 4), Some(blue));
+    assert_eq!(frame.pixel(11, 11), Some(blue));
+    assert_eq!(frame.pixel(12, 12), Some(green));
+
+    snapshot.blend = ColorEffectSetting::from_targets(
+      ColorSpecialEffect::BrightnessDecrease,
+      LayerSet::ALL,
+      LayerSet::NONE,
+    );
+    snapshot.brightness = BrightnessSetting::new().with_evy_coefficient(16);
+    assert_eq!(snapshot.render().diff_count(&Frame { pixels: vec![Color(0); 240 * 160] }), 0);
+  }
+}
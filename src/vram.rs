@@ -17,7 +17,15 @@ pub(crate) use super::*;
 
 pub mod affine;
 pub mod bitmap;
+pub mod console;
+pub mod draw;
+pub mod font;
+pub mod layout;
+pub mod object_tiles;
+pub mod raster;
+pub mod streaming;
 pub mod text;
+pub mod tile;
 
 use text::TextScreenblockEntry;
 
@@ -64,3 +72,12 @@ pub fn get_8bpp_character_block(slot: usize) -> VolBlock<Tile8bpp, Safe, Safe, 2
 pub fn get_screen_block(slot: usize) -> VolBlock<TextScreenblockEntry, Safe, Safe, 1024> {
   unsafe { VolBlock::new(SCREEN_BASE_BLOCKS.index(slot).as_usize()) }
 }
+
+/// If a `width` by `height` rectangle at `(x, y)` fits in a map of the size
+/// given.
+fn rect_fits(x: usize, y: usize, width: usize, height: usize, map: (usize, usize)) -> bool {
+  match (x.checked_add(width), y.checked_add(height)) {
+    (Some(right), Some(bottom)) => right <= map.0 && bottom <= map.1,
+    _ => false,
+  }
+}
@@ -1,6 +1,7 @@
 //! Module for affine things.
 
 use super::*;
+use crate::io::background::BGSize;
 
 newtype! {
   /// A screenblock entry for use in Affine mode.
@@ -30,3 +31,238 @@ newtype! {
   #[derive(Clone, Copy)]
   AffineScreenblock128x128, [AffineScreenblockEntry; 128*128], no frills
 }
+
+/// The number of bytes of VRAM that background maps can use.
+const BG_MAP_BYTES: usize = 0x1_0000;
+
+/// Gives the specified screenblock as a read only 16x16 affine map.
+///
+/// VRAM can't take byte writes, so use an `AffineMap` to write entries.
+///
+/// ## Panics
+///
+/// If the slot is 32 or more.
+pub fn get_affine_screen_block_16x16(
+  slot: usize,
+) -> VolBlock<AffineScreenblockEntry, Safe, (), 256> {
+  unsafe { VolBlock::new(affine_block_address(slot, 16 * 16)) }
+}
+
+/// Gives the specified screenblock as a read only 32x32 affine map.
+///
+/// VRAM can't take byte writes, so use an `AffineMap` to write entries.
+///
+/// ## Panics
+///
+/// If the slot is 32 or more.
+pub fn get_affine_screen_block_32x32(
+  slot: usize,
+) -> VolBlock<AffineScreenblockEntry, Safe, (), 1024> {
+  unsafe { VolBlock::new(affine_block_address(slot, 32 * 32)) }
+}
+
+/// Gives the specified screenblock (and the next one) as a read only 64x64
+/// affine map.
+///
+/// VRAM can't take byte writes, so use an `AffineMap` to write entries.
+///
+/// ## Panics
+///
+/// If the map would go past screenblock 31.
+pub fn get_affine_screen_block_64x64(
+  slot: usize,
+) -> VolBlock<AffineScreenblockEntry, Safe, (), 4096> {
+  unsafe { VolBlock::new(affine_block_address(slot, 64 * 64)) }
+}
+
+/// Gives the specified screenblock (and the next seven) as a read only
+/// 128x128 affine map.
+///
+/// VRAM can't take byte writes, so use an `AffineMap` to write entries.
+///
+/// ## Panics
+///
+/// If the map would go past screenblock 31.
+pub fn get_affine_screen_block_128x128(
+  slot: usize,
+) -> VolBlock<AffineScreenblockEntry, Safe, (), 16384> {
+  unsafe { VolBlock::new(affine_block_address(slot, 128 * 128)) }
+}
+
+fn affine_block_address(slot: usize, bytes: usize) -> usize {
+  assert!(slot < SCREEN_BASE_BLOCKS.len() && slot * 0x800 + bytes <= BG_MAP_BYTES);
+  SCREEN_BASE_BLOCKS.index(slot).as_usize()
+}
+
+/// An affine background's map.
+///
+/// The map is `N` by `N` tiles, one byte per entry, and must match one of the
+/// `BGSize` values, use the aliases below. Positions are `(x, y)` in tiles.
+///
+/// VRAM can't take byte writes (a byte written to background VRAM is copied
+/// into both halves of the halfword), so writes here read the halfword that
+/// holds the entry, change one byte, and write it back.
+///
+/// All accesses are bounds checked. Methods that write more than one entry
+/// check the whole area first, and write nothing if any of it is out of
+/// bounds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AffineMap<const N: usize> {
+  screen_base_block: usize,
+}
+
+/// A 16x16 tile affine map (`BGSize::Zero`).
+pub type AffineMap16x16 = AffineMap<16>;
+/// A 32x32 tile affine map (`BGSize::One`).
+pub type AffineMap32x32 = AffineMap<32>;
+/// A 64x64 tile affine map (`BGSize::Two`).
+pub type AffineMap64x64 = AffineMap<64>;
+/// A 128x128 tile affine map (`BGSize::Three`).
+pub type AffineMap128x128 = AffineMap<128>;
+
+impl<const N: usize> AffineMap<N> {
+  const VALID_SIZE: () =
+    assert!(N == 16 || N == 32 || N == 64 || N == 128, "affine maps must be 16 to 128 tiles");
+
+  /// The width and height of the map in tiles.
+  pub const SIZE: usize = N;
+
+  /// The `BGSize` to put in the background's control setting.
+  pub const BG_SIZE: BGSize = match N {
+    16 => BGSize::Zero,
+    32 => BGSize::One,
+    64 => BGSize::Two,
+    _ => BGSize::Three,
+  };
+
+  /// Makes a view of the map starting at a screenblock.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` if the map doesn't fit in background VRAM.
+  pub fn new(screen_base_block: usize) -> Option<Self> {
+    #[allow(clippy::let_unit_value)]
+    let _ = Self::VALID_SIZE;
+    if screen_base_block < SCREEN_BASE_BLOCKS.len()
+      && screen_base_block * 0x800 + N * N <= BG_MAP_BYTES
+    {
+      Some(Self { screen_base_block })
+    } else {
+      None
+    }
+  }
+
+  /// The first screenblock of the map.
+  pub const fn screen_base_block(&self) -> usize {
+    self.screen_base_block
+  }
+
+  /// The halfword holding the entry at an index.
+  fn halfword(&self, index: usize) -> VolAddress<u16, Safe, Safe> {
+    let base = SCREEN_BASE_BLOCKS.index(self.screen_base_block).as_usize();
+    unsafe { VolAddress::new(base + (index & !1)) }
+  }
+
+  fn write_index(&self, index: usize, entry: AffineScreenblockEntry) {
+    let halfword = self.halfword(index);
+    let shift = (index & 1) * 8;
+    halfword.write(halfword.read() & !(0xFF << shift) | (entry.0 as u16) << shift);
+  }
+
+  /// Reads the entry at a position.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` if the position is out of bounds.
+  pub fn read(&self, x: usize, y: usize) -> Option<AffineScreenblockEntry> {
+    if x < N && y < N {
+      let index = y * N + x;
+      Some(AffineScreenblockEntry((self.halfword(index).read() >> ((index & 1) * 8)) as u8))
+    } else {
+      None
+    }
+  }
+
+  /// Writes the entry at a position.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` if the position is out of bounds.
+  pub fn write(&self, x: usize, y: usize, entry: AffineScreenblockEntry) -> Option<()> {
+    if x < N && y < N {
+      self.write_index(y * N + x, entry);
+      Some(())
+    } else {
+      None
+    }
+  }
+
+  /// Sets every entry in the map.
+  pub fn fill(&self, entry: AffineScreenblockEntry) {
+    let both = (entry.0 as u16) << 8 | entry.0 as u16;
+    for index in (0..N * N).step_by(2) {
+      self.halfword(index).write(both);
+    }
+  }
+
+  /// Sets every entry in a rectangle.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` (and writes nothing) if the rectangle goes out of bounds.
+  pub fn fill_rect(
+    &self, x: usize, y: usize, width: usize, height: usize, entry: AffineScreenblockEntry,
+  ) -> Option<()> {
+    if !rect_fits(x, y, width, height, (N, N)) {
+      return None;
+    }
+    for row in y..y + height {
+      for col in x..x + width {
+        self.write_index(row * N + col, entry);
+      }
+    }
+    Some(())
+  }
+
+  /// Copies a row-major rectangle of entries `width` tiles wide into the map,
+  /// with its top left at `(x, y)`.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` (and writes nothing) if `width` is 0, the entries aren't a
+  /// whole number of rows, or the rectangle goes out of bounds.
+  pub fn copy_rect(
+    &self, x: usize, y: usize, width: usize, entries: &[AffineScreenblockEntry],
+  ) -> Option<()> {
+    if width == 0 || entries.len() % width != 0 {
+      return None;
+    }
+    if !rect_fits(x, y, width, entries.len() / width, (N, N)) {
+      return None;
+    }
+    for (i, row) in entries.chunks(width).enumerate() {
+      for (j, &entry) in row.iter().enumerate() {
+        self.write_index((y + i) * N + x + j, entry);
+      }
+    }
+    Some(())
+  }
+
+  /// Writes entries going right from `(x, y)`.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` (and writes nothing) if the row goes out of bounds.
+  pub fn write_row(&self, x: usize, y: usize, entries: &[AffineScreenblockEntry]) -> Option<()> {
+    self.copy_rect(x, y, entries.len().max(1), entries)
+  }
+
+  /// Writes entries going down from `(x, y)`.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` (and writes nothing) if the column goes out of bounds.
+  pub fn write_col(&self, x: usize, y: usize, entries: &[AffineScreenblockEntry]) -> Option<()> {
+    self.copy_rect(x, y, 1, entries)
+  }
+}
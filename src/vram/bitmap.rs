@@ -1,6 +1,8 @@
 //! Module for the Bitmap video modes.
 
 use super::*;
+use crate::io::display::DISPCNT;
+use core::marker::PhantomData;
 
 /// A bitmap video mode with full color and full resolution.
 ///
@@ -32,6 +34,7 @@ impl Mode3 {
   /// ## Failure
   ///
   /// Gives `None` if out of bounds
+  #[inline(always)]
   fn get(col: usize, row: usize) -> Option<VolAddress<Color, Safe, Safe>> {
     Self::VRAM.get(col + row * Self::WIDTH)
   }
@@ -50,6 +53,7 @@ impl Mode3 {
   /// ## Failure
   ///
   /// Gives `None` if out of bounds
+  #[inline(always)]
   pub fn write(col: usize, row: usize, color: Color) -> Option<()> {
     Self::get(col, row).map(|va| va.write(color))
   }
@@ -194,6 +198,7 @@ impl Mode4 {
   /// ## Failure
   ///
   /// Gives `None` if out of bounds
+  #[inline(always)]
   pub fn write(page: Page, col: usize, row: usize, pal8bpp: u8) -> Option<()> {
     // Note(Lokathor): Byte writes to VRAM aren't permitted, we have to jump
     // through some hoops.
@@ -360,6 +365,7 @@ impl Mode5 {
   /// ## Failure
   ///
   /// Gives `None` if out of bounds
+  #[inline(always)]
   pub fn write(page: Page, col: usize, row: usize, color: Color) -> Option<()> {
     match page {
       Page::Zero => Self::PAGE0_PIXELS,
@@ -455,3 +461,230 @@ impl Mode5 {
     }
   }
 }
+
+/// The bitmap modes that have two pages.
+pub trait PagedMode {
+  /// The pixel type: a palette index or a full color.
+  type Pixel: Copy;
+
+  /// The screen's width in this mode.
+  const WIDTH: usize;
+
+  /// The screen's height in this mode.
+  const HEIGHT: usize;
+
+  /// Reads the pixel specified, `None` if out of bounds.
+  fn read(page: Page, col: usize, row: usize) -> Option<Self::Pixel>;
+
+  /// Writes the pixel specified, `None` if out of bounds.
+  fn write(page: Page, col: usize, row: usize, pixel: Self::Pixel) -> Option<()>;
+
+  /// Clears the page to the pixel specified.
+  fn clear_to(page: Page, pixel: Self::Pixel);
+
+  /// Clears the page to the pixel specified using DMA3.
+  fn dma_clear_to(page: Page, pixel: Self::Pixel);
+
+  /// Draws a line between the two points given.
+  fn draw_line(page: Page, c1: isize, r1: isize, c2: isize, r2: isize, pixel: Self::Pixel);
+
+  /// Fills the columns `col_start..col_end` of a row.
+  ///
+  /// Out of bounds pixels are skipped. On the GBA this is ARM code in IWRAM,
+  /// for the triangle fills of the `raster` module.
+  #[cfg_attr(target_arch = "arm", link_section = ".text_iwram", instruction_set(arm::a32))]
+  fn fill_span(page: Page, row: usize, col_start: usize, col_end: usize, pixel: Self::Pixel) {
+    for col in col_start..col_end {
+      Self::write(page, col, row, pixel);
+    }
+  }
+}
+
+impl PagedMode for Mode4 {
+  type Pixel = u8;
+  const WIDTH: usize = Mode4::WIDTH;
+  const HEIGHT: usize = Mode4::HEIGHT;
+  fn read(page: Page, col: usize, row: usize) -> Option<u8> {
+    Mode4::read(page, col, row)
+  }
+  #[inline(always)]
+  fn write(page: Page, col: usize, row: usize, pixel: u8) -> Option<()> {
+    Mode4::write(page, col, row, pixel)
+  }
+  fn clear_to(page: Page, pixel: u8) {
+    Mode4::clear_to(page, pixel)
+  }
+  fn dma_clear_to(page: Page, pixel: u8) {
+    Mode4::dma_clear_to(page, pixel)
+  }
+  fn draw_line(page: Page, c1: isize, r1: isize, c2: isize, r2: isize, pixel: u8) {
+    Mode4::draw_line(page, c1, r1, c2, r2, pixel)
+  }
+  #[cfg_attr(target_arch = "arm", link_section = ".text_iwram", instruction_set(arm::a32))]
+  fn fill_span(page: Page, row: usize, col_start: usize, col_end: usize, pixel: u8) {
+    // Note: Byte writes to VRAM aren't permitted, so the ends of the span might
+    // need a read-modify-write, but everything between is whole `u16` pairs.
+    let col_end = col_end.min(Self::WIDTH);
+    if row >= Self::HEIGHT || col_start >= col_end {
+      return;
+    }
+    let mut col = col_start;
+    if col & 1 == 1 {
+      Self::write(page, col, row, pixel);
+      col += 1;
+    }
+    let pair = (pixel as u16) << 8 | pixel as u16;
+    let row_start: VolAddress<u16, Safe, Safe> = unsafe {
+      match page {
+        Page::Zero => Self::PAGE0_INDEXES,
+        Page::One => Self::PAGE1_INDEXES,
+      }
+      .index(row * Self::WIDTH)
+      .cast::<u16>()
+    };
+    while col + 1 < col_end {
+      unsafe { row_start.offset((col / 2) as isize) }.write(pair);
+      col += 2;
+    }
+    if col < col_end {
+      Self::write(page, col, row, pixel);
+    }
+  }
+}
+
+impl PagedMode for Mode5 {
+  type Pixel = Color;
+  const WIDTH: usize = Mode5::WIDTH;
+  const HEIGHT: usize = Mode5::HEIGHT;
+  fn read(page: Page, col: usize, row: usize) -> Option<Color> {
+    Mode5::read(page, col, row)
+  }
+  #[inline(always)]
+  fn write(page: Page, col: usize, row: usize, pixel: Color) -> Option<()> {
+    Mode5::write(page, col, row, pixel)
+  }
+  fn clear_to(page: Page, pixel: Color) {
+    Mode5::clear_to(page, pixel)
+  }
+  fn dma_clear_to(page: Page, pixel: Color) {
+    Mode5::dma_clear_to(page, pixel)
+  }
+  fn draw_line(page: Page, c1: isize, r1: isize, c2: isize, r2: isize, pixel: Color) {
+    Mode5::draw_line(page, c1, r1, c2, r2, pixel)
+  }
+}
+
+impl Page {
+  /// The other page.
+  pub const fn other(self) -> Self {
+    match self {
+      Page::Zero => Page::One,
+      Page::One => Page::Zero,
+    }
+  }
+}
+
+/// Double buffered drawing for Mode 4 or Mode 5.
+///
+/// You only ever get to draw to the page that isn't being displayed, through
+/// the [`BackPage`] handle. The handle borrows the buffer, so the pages can't
+/// be flipped while you're still holding it.
+///
+/// ```no_run
+/// let mut buffer = DoubleBuffer::<Mode4>::new();
+/// loop {
+///   let mut back = buffer.back_page();
+///   back.clear_to(0);
+///   back.write(10, 10, 1);
+///   bios::vblank_interrupt_wait();
+///   buffer.flip();
+/// }
+/// ```
+#[derive(Debug)]
+pub struct DoubleBuffer<M: PagedMode> {
+  displayed: Page,
+  mode: PhantomData<M>,
+}
+
+impl<M: PagedMode> DoubleBuffer<M> {
+  /// Makes a double buffer, using `DISPCNT` to find which page is displayed.
+  pub fn new() -> Self {
+    let displayed = if DISPCNT.read().frame1() { Page::One } else { Page::Zero };
+    Self { displayed, mode: PhantomData }
+  }
+
+  /// The page that's currently displayed.
+  pub fn displayed_page(&self) -> Page {
+    self.displayed
+  }
+
+  /// Gets the hidden page for drawing.
+  pub fn back_page(&mut self) -> BackPage<'_, M> {
+    BackPage { page: self.displayed.other(), buffer: PhantomData }
+  }
+
+  /// Displays the back page, making the old displayed page the new back page.
+  ///
+  /// Call this during VBlank, otherwise the screen will tear.
+  pub fn flip(&mut self) {
+    self.displayed = self.displayed.other();
+    DISPCNT.write(DISPCNT.read().with_frame1(self.displayed == Page::One));
+  }
+}
+
+impl<M: PagedMode> Default for DoubleBuffer<M> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// Drawing access to the page of a [`DoubleBuffer`] that isn't displayed.
+#[derive(Debug)]
+pub struct BackPage<'a, M: PagedMode> {
+  page: Page,
+  buffer: PhantomData<&'a mut DoubleBuffer<M>>,
+}
+
+impl<'a, M: PagedMode> BackPage<'a, M> {
+  /// Which page this is.
+  #[inline(always)]
+  pub fn page(&self) -> Page {
+    self.page
+  }
+
+  /// Reads the pixel specified.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` if out of bounds
+  pub fn read(&self, col: usize, row: usize) -> Option<M::Pixel> {
+    M::read(self.page, col, row)
+  }
+
+  /// Writes the pixel specified.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` if out of bounds
+  pub fn write(&mut self, col: usize, row: usize, pixel: M::Pixel) -> Option<()> {
+    M::write(self.page, col, row, pixel)
+  }
+
+  /// Clears the page to the pixel specified.
+  pub fn clear_to(&mut self, pixel: M::Pixel) {
+    M::clear_to(self.page, pixel)
+  }
+
+  /// Clears the page to the pixel specified using DMA3.
+  pub fn dma_clear_to(&mut self, pixel: M::Pixel) {
+    M::dma_clear_to(self.page, pixel)
+  }
+
+  /// Draws a line between the two points given `(c1,r1,c2,r2,pixel)`.
+  ///
+  /// Works fine with out of bounds points. It only draws to in bounds
+  /// locations.
+  pub fn draw_line(&mut self, c1: isize, r1: isize, c2: isize, r2: isize, pixel: M::Pixel) {
+    M::draw_line(self.page, c1, r1, c2, r2, pixel)
+  }
+}
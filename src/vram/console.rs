@@ -0,0 +1,359 @@
+//! Module for a text console on a tiled background.
+//!
+//! The console loads a bundled 8x8 font into a charblock (as 4bpp tiles, with
+//! the glyph pixels using palette index 1) and then writes
+//! `TextScreenblockEntry` values into a screenblock as you print. It's an
+//! ordinary text background, so it works on real hardware, unlike the `debug`
+//! macros.
+//!
+//! The color of the text is picked per character by palbank: each character
+//! uses index 1 of the console's current palbank.
+//!
+//! ```no_run
+//! use core::fmt::Write;
+//! let mut console = TextConsole::new(0, 31);
+//! console.load_font();
+//! console.set_palbank_color(0, Color::from_rgb(31, 31, 31));
+//! BG0CNT.write(console.background_control());
+//! DISPCNT.write(DisplayControlSetting::new().with_bg0(true));
+//! writeln!(console, "Hello, {}!", "world").ok();
+//! ```
+
+use super::*;
+use crate::{io::background::BackgroundControlSetting, palram::index_palram_bg_4bpp};
+use core::fmt::{self, Write};
+
+/// The number of columns visible without scrolling the background.
+pub const CONSOLE_COLS: usize = 30;
+
+/// The number of rows visible without scrolling the background.
+pub const CONSOLE_ROWS: usize = 20;
+
+/// The number of glyphs in the font (printable ASCII, `' '` through `'~'`).
+pub const FONT_GLYPH_COUNT: usize = 95;
+
+/// A text console drawn with a tiled background.
+#[derive(Debug, Clone)]
+pub struct TextConsole {
+  char_base_block: usize,
+  screen_base_block: usize,
+  tile_base: u16,
+  palbank: u16,
+  cursor: Cursor,
+}
+
+impl TextConsole {
+  /// Makes a console using the charblock and screenblock given.
+  ///
+  /// The font goes at the start of the charblock, so that the zeroed
+  /// screenblock entry is a space.
+  pub const fn new(char_base_block: usize, screen_base_block: usize) -> Self {
+    let cursor = Cursor { col: 0, row: 0, wrap_pending: false };
+    Self { char_base_block, screen_base_block, tile_base: 0, palbank: 0, cursor }
+  }
+
+  /// Places the font at a different tile index within the charblock.
+  ///
+  /// The font takes `FONT_GLYPH_COUNT` tiles.
+  pub const fn with_tile_base(self, tile_base: u16) -> Self {
+    Self { tile_base, ..self }
+  }
+
+  /// The background control setting that shows this console.
+  pub fn background_control(&self) -> BackgroundControlSetting {
+    BackgroundControlSetting::new()
+      .with_char_base_block(self.char_base_block as u16)
+      .with_screen_base_block(self.screen_base_block as u16)
+  }
+
+  /// Writes the font tiles into the charblock.
+  pub fn load_font(&self) {
+    let block = get_4bpp_character_block(self.char_base_block);
+    for (i, glyph) in FONT_8X8.iter().enumerate() {
+      let mut tile = Tile4bpp([0; 8]);
+      for (row, bits) in glyph.iter().enumerate() {
+        for x in 0..8 {
+          if bits & (1 << x) != 0 {
+            tile.0[row] |= 1 << (x * 4);
+          }
+        }
+      }
+      block.index(self.tile_base as usize + i).write(tile);
+    }
+  }
+
+  /// Sets the text color of a palbank (its index 1).
+  pub fn set_palbank_color(&self, palbank: u16, color: Color) {
+    index_palram_bg_4bpp((palbank & 0xF) as u8, 1).write(color);
+  }
+
+  /// Sets the palbank used for the characters printed after this.
+  pub fn set_palbank(&mut self, palbank: u16) {
+    self.palbank = palbank & 0xF;
+  }
+
+  /// The cursor position as `(col, row)`.
+  ///
+  /// After a line is filled the cursor stays in its last column until the
+  /// next byte is printed.
+  pub fn cursor(&self) -> (usize, usize) {
+    (self.cursor.col, self.cursor.row)
+  }
+
+  /// Moves the cursor, clamping it to the visible area.
+  pub fn set_cursor(&mut self, col: usize, row: usize) {
+    let (col, row) = (col.min(CONSOLE_COLS - 1), row.min(CONSOLE_ROWS - 1));
+    self.cursor = Cursor { col, row, wrap_pending: false };
+  }
+
+  /// Blanks every row and moves the cursor to the top left.
+  pub fn clear(&mut self) {
+    for row in 0..CONSOLE_ROWS {
+      self.clear_row(row);
+    }
+    self.set_cursor(0, 0);
+  }
+
+  /// Prints a single byte.
+  ///
+  /// * `\n` moves to the start of the next line.
+  /// * `\r` moves to the start of the current line.
+  /// * `\t` moves to the next multiple of four columns.
+  /// * Other bytes outside of printable ASCII print as `?`.
+  ///
+  /// Printing past the end of a line wraps, and moving past the last line
+  /// scrolls everything up by one line. A line only wraps once something is
+  /// printed past its end, so a full line followed by `\n` is one line.
+  pub fn put_byte(&mut self, byte: u8) {
+    match byte {
+      b'\n' => {
+        if self.cursor.line_feed() {
+          self.scroll_up();
+        }
+      }
+      b'\r' => self.cursor.carriage_return(),
+      b'\t' => {
+        for _ in 0..self.cursor.tab_width() {
+          self.put_byte(b' ');
+        }
+      }
+      _ => {
+        let glyph = if (b' '..=b'~').contains(&byte) { byte - b' ' } else { b'?' - b' ' };
+        let entry = TextScreenblockEntry::from_tile_id(self.tile_base + glyph as u16)
+          .with_palbank(self.palbank);
+        let (col, row, scroll) = self.cursor.next_cell();
+        if scroll {
+          self.scroll_up();
+        }
+        self.entry(col, row).write(entry);
+      }
+    }
+  }
+
+  /// Moves every row up by one and blanks the last row.
+  fn scroll_up(&mut self) {
+    for row in 1..CONSOLE_ROWS {
+      for col in 0..CONSOLE_COLS {
+        let entry = self.entry(col, row).read();
+        self.entry(col, row - 1).write(entry);
+      }
+    }
+    self.clear_row(CONSOLE_ROWS - 1);
+  }
+
+  fn clear_row(&self, row: usize) {
+    let blank = TextScreenblockEntry::from_tile_id(self.tile_base);
+    for col in 0..CONSOLE_COLS {
+      self.entry(col, row).write(blank);
+    }
+  }
+
+  fn entry(&self, col: usize, row: usize) -> VolAddress<TextScreenblockEntry, Safe, Safe> {
+    get_screen_block(self.screen_base_block).index(row * 32 + col)
+  }
+}
+
+/// Where the next byte goes, kept apart from the VRAM writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Cursor {
+  col: usize,
+  row: usize,
+  /// The line is full, and wraps before the next printable byte.
+  wrap_pending: bool,
+}
+
+impl Cursor {
+  /// Moves to the start of the next line. Gives `true` if the console has to
+  /// scroll up to make room.
+  fn line_feed(&mut self) -> bool {
+    self.col = 0;
+    self.wrap_pending = false;
+    if self.row + 1 < CONSOLE_ROWS {
+      self.row += 1;
+      false
+    } else {
+      true
+    }
+  }
+
+  fn carriage_return(&mut self) {
+    self.col = 0;
+    self.wrap_pending = false;
+  }
+
+  /// The number of spaces to the next multiple of four columns.
+  fn tab_width(&self) -> usize {
+    if self.wrap_pending {
+      4
+    } else {
+      4 - self.col % 4
+    }
+  }
+
+  /// Takes the cell for a printable byte, wrapping first if the line is
+  /// full. Gives `(col, row, scroll)`, where `scroll` is if the console has
+  /// to scroll up before writing the cell.
+  fn next_cell(&mut self) -> (usize, usize, bool) {
+    let scroll = self.wrap_pending && self.line_feed();
+    let (col, row) = (self.col, self.row);
+    if col + 1 < CONSOLE_COLS {
+      self.col += 1;
+    } else {
+      self.wrap_pending = true;
+    }
+    (col, row, scroll)
+  }
+}
+
+impl Write for TextConsole {
+  fn write_str(&mut self, s: &str) -> fmt::Result {
+    for ch in s.chars() {
+      self.put_byte(if ch.is_ascii() { ch as u8 } else { b'?' });
+    }
+    Ok(())
+  }
+}
+
+/// An 8x8 font covering printable ASCII (`' '` through `'~'`).
+///
+/// Each glyph is 8 rows, top to bottom, and bit `n` of a row is the pixel in
+/// column `n` (so the lowest bit is on the left, as with GBA tiles).
+///
+/// The glyphs are the public domain "5x8" misc-fixed font from the X.Org
+/// project, placed one pixel in from the left of each cell.
+pub const FONT_8X8: [[u8; 8]; FONT_GLYPH_COUNT] = [
+  [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+  [0x00, 0x08, 0x08, 0x08, 0x08, 0x00, 0x08, 0x00], // '!'
+  [0x00, 0x14, 0x14, 0x14, 0x00, 0x00, 0x00, 0x00], // '"'
+  [0x14, 0x14, 0x3E, 0x14, 0x3E, 0x14, 0x14, 0x00], // '#'
+  [0x08, 0x1C, 0x0A, 0x1C, 0x28, 0x1C, 0x08, 0x00], // '$'
+  [0x00, 0x04, 0x14, 0x08, 0x14, 0x10, 0x00, 0x00], // '%'
+  [0x04, 0x0A, 0x0A, 0x04, 0x0A, 0x0A, 0x14, 0x00], // '&'
+  [0x00, 0x08, 0x08, 0x08, 0x00, 0x00, 0x00, 0x00], // '\''
+  [0x00, 0x08, 0x04, 0x04, 0x04, 0x04, 0x08, 0x00], // '('
+  [0x00, 0x04, 0x08, 0x08, 0x08, 0x08, 0x04, 0x00], // ')'
+  [0x00, 0x00, 0x12, 0x0C, 0x1E, 0x0C, 0x12, 0x00], // '*'
+  [0x00, 0x00, 0x08, 0x08, 0x3E, 0x08, 0x08, 0x00], // '+'
+  [0x00, 0x00, 0x00, 0x00, 0x00, 0x18, 0x08, 0x04], // ','
+  [0x00, 0x00, 0x00, 0x00, 0x1E, 0x00, 0x00, 0x00], // '-'
+  [0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0x1C, 0x08], // '.'
+  [0x00, 0x10, 0x10, 0x08, 0x04, 0x02, 0x02, 0x00], // '/'
+  [0x00, 0x08, 0x14, 0x14, 0x14, 0x14, 0x08, 0x00], // '0'
+  [0x00, 0x08, 0x0C, 0x08, 0x08, 0x08, 0x1C, 0x00], // '1'
+  [0x00, 0x0C, 0x12, 0x10, 0x0C, 0x02, 0x1E, 0x00], // '2'
+  [0x00, 0x1E, 0x08, 0x0C, 0x10, 0x12, 0x0C, 0x00], // '3'
+  [0x00, 0x08, 0x0C, 0x0A, 0x1E, 0x08, 0x08, 0x00], // '4'
+  [0x00, 0x1E, 0x02, 0x0E, 0x10, 0x12, 0x0C, 0x00], // '5'
+  [0x00, 0x0C, 0x02, 0x0E, 0x12, 0x12, 0x0C, 0x00], // '6'
+  [0x00, 0x1E, 0x10, 0x08, 0x08, 0x04, 0x04, 0x00], // '7'
+  [0x00, 0x0C, 0x12, 0x0C, 0x12, 0x12, 0x0C, 0x00], // '8'
+  [0x00, 0x0C, 0x12, 0x12, 0x1C, 0x10, 0x0C, 0x00], // '9'
+  [0x00, 0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00], // ':'
+  [0x00, 0x00, 0x18, 0x18, 0x00, 0x18, 0x08, 0x04], // ';'
+  [0x00, 0x10, 0x08, 0x04, 0x04, 0x08, 0x10, 0x00], // '<'
+  [0x00, 0x00, 0x00, 0x1E, 0x00, 0x1E, 0x00, 0x00], // '='
+  [0x00, 0x04, 0x08, 0x10, 0x10, 0x08, 0x04, 0x00], // '>'
+  [0x00, 0x08, 0x14, 0x10, 0x08, 0x00, 0x08, 0x00], // '?'
+  [0x18, 0x24, 0x32, 0x2A, 0x2A, 0x12, 0x04, 0x18], // '@'
+  [0x00, 0x0C, 0x12, 0x12, 0x1E, 0x12, 0x12, 0x00], // 'A'
+  [0x00, 0x0E, 0x12, 0x0E, 0x12, 0x12, 0x0E, 0x00], // 'B'
+  [0x00, 0x0C, 0x12, 0x02, 0x02, 0x12, 0x0C, 0x00], // 'C'
+  [0x00, 0x0E, 0x12, 0x12, 0x12, 0x12, 0x0E, 0x00], // 'D'
+  [0x00, 0x1E, 0x02, 0x0E, 0x02, 0x02, 0x1E, 0x00], // 'E'
+  [0x00, 0x1E, 0x02, 0x0E, 0x02, 0x02, 0x02, 0x00], // 'F'
+  [0x00, 0x0C, 0x12, 0x02, 0x1A, 0x12, 0x0C, 0x00], // 'G'
+  [0x00, 0x12, 0x12, 0x1E, 0x12, 0x12, 0x12, 0x00], // 'H'
+  [0x00, 0x1C, 0x08, 0x08, 0x08, 0x08, 0x1C, 0x00], // 'I'
+  [0x00, 0x1C, 0x08, 0x08, 0x08, 0x0A, 0x04, 0x00], // 'J'
+  [0x00, 0x12, 0x0A, 0x06, 0x0A, 0x0A, 0x12, 0x00], // 'K'
+  [0x00, 0x02, 0x02, 0x02, 0x02, 0x02, 0x1E, 0x00], // 'L'
+  [0x00, 0x12, 0x1E, 0x1E, 0x12, 0x12, 0x12, 0x00], // 'M'
+  [0x00, 0x12, 0x16, 0x1E, 0x1A, 0x1A, 0x12, 0x00], // 'N'
+  [0x00, 0x0C, 0x12, 0x12, 0x12, 0x12, 0x0C, 0x00], // 'O'
+  [0x00, 0x0E, 0x12, 0x12, 0x0E, 0x02, 0x02, 0x00], // 'P'
+  [0x00, 0x0C, 0x12, 0x12, 0x16, 0x1A, 0x0C, 0x10], // 'Q'
+  [0x00, 0x0E, 0x12, 0x12, 0x0E, 0x12, 0x12, 0x00], // 'R'
+  [0x00, 0x0C, 0x12, 0x04, 0x08, 0x12, 0x0C, 0x00], // 'S'
+  [0x00, 0x1C, 0x08, 0x08, 0x08, 0x08, 0x08, 0x00], // 'T'
+  [0x00, 0x12, 0x12, 0x12, 0x12, 0x12, 0x0C, 0x00], // 'U'
+  [0x00, 0x12, 0x12, 0x12, 0x12, 0x0C, 0x0C, 0x00], // 'V'
+  [0x00, 0x12, 0x12, 0x12, 0x1E, 0x1E, 0x12, 0x00], // 'W'
+  [0x00, 0x12, 0x12, 0x0C, 0x0C, 0x12, 0x12, 0x00], // 'X'
+  [0x00, 0x22, 0x22, 0x14, 0x08, 0x08, 0x08, 0x00], // 'Y'
+  [0x00, 0x1E, 0x10, 0x08, 0x04, 0x02, 0x1E, 0x00], // 'Z'
+  [0x00, 0x1C, 0x04, 0x04, 0x04, 0x04, 0x1C, 0x00], // '['
+  [0x00, 0x02, 0x02, 0x04, 0x08, 0x10, 0x10, 0x00], // '\\'
+  [0x00, 0x1C, 0x10, 0x10, 0x10, 0x10, 0x1C, 0x00], // ']'
+  [0x00, 0x08, 0x14, 0x00, 0x00, 0x00, 0x00, 0x00], // '^'
+  [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1E], // '_'
+  [0x00, 0x04, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00], // '`'
+  [0x00, 0x00, 0x00, 0x1C, 0x12, 0x12, 0x1C, 0x00], // 'a'
+  [0x00, 0x02, 0x02, 0x0E, 0x12, 0x12, 0x0E, 0x00], // 'b'
+  [0x00, 0x00, 0x00, 0x18, 0x04, 0x04, 0x18, 0x00], // 'c'
+  [0x00, 0x10, 0x10, 0x1C, 0x12, 0x12, 0x1C, 0x00], // 'd'
+  [0x00, 0x00, 0x00, 0x0C, 0x1A, 0x06, 0x0C, 0x00], // 'e'
+  [0x00, 0x08, 0x14, 0x04, 0x0E, 0x04, 0x04, 0x00], // 'f'
+  [0x00, 0x00, 0x00, 0x0C, 0x12, 0x1C, 0x10, 0x0C], // 'g'
+  [0x00, 0x02, 0x02, 0x0E, 0x12, 0x12, 0x12, 0x00], // 'h'
+  [0x00, 0x08, 0x00, 0x0C, 0x08, 0x08, 0x1C, 0x00], // 'i'
+  [0x00, 0x10, 0x00, 0x10, 0x10, 0x10, 0x14, 0x08], // 'j'
+  [0x00, 0x02, 0x02, 0x12, 0x0E, 0x12, 0x12, 0x00], // 'k'
+  [0x00, 0x0C, 0x08, 0x08, 0x08, 0x08, 0x1C, 0x00], // 'l'
+  [0x00, 0x00, 0x00, 0x16, 0x2A, 0x2A, 0x2A, 0x00], // 'm'
+  [0x00, 0x00, 0x00, 0x0E, 0x12, 0x12, 0x12, 0x00], // 'n'
+  [0x00, 0x00, 0x00, 0x0C, 0x12, 0x12, 0x0C, 0x00], // 'o'
+  [0x00, 0x00, 0x00, 0x0E, 0x12, 0x0E, 0x02, 0x02], // 'p'
+  [0x00, 0x00, 0x00, 0x1C, 0x12, 0x1C, 0x10, 0x10], // 'q'
+  [0x00, 0x00, 0x00, 0x0A, 0x16, 0x02, 0x02, 0x00], // 'r'
+  [0x00, 0x00, 0x00, 0x18, 0x0C, 0x10, 0x0C, 0x00], // 's'
+  [0x00, 0x04, 0x04, 0x0E, 0x04, 0x14, 0x08, 0x00], // 't'
+  [0x00, 0x00, 0x00, 0x12, 0x12, 0x12, 0x1C, 0x00], // 'u'
+  [0x00, 0x00, 0x00, 0x14, 0x14, 0x14, 0x08, 0x00], // 'v'
+  [0x00, 0x00, 0x00, 0x22, 0x2A, 0x2A, 0x14, 0x00], // 'w'
+  [0x00, 0x00, 0x00, 0x12, 0x0C, 0x0C, 0x12, 0x00], // 'x'
+  [0x00, 0x00, 0x00, 0x12, 0x12, 0x1C, 0x12, 0x0C], // 'y'
+  [0x00, 0x00, 0x00, 0x1E, 0x08, 0x04, 0x1E, 0x00], // 'z'
+  [0x18, 0x04, 0x08, 0x06, 0x08, 0x04, 0x18, 0x00], // '{'
+  [0x00, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x00], // '|'
+  [0x06, 0x08, 0x04, 0x18, 0x04, 0x08, 0x06, 0x00], // '}'
+  [0x00, 0x14, 0x0A, 0x00, 0x00, 0x00, 0x00, 0x00], // '~'
+];
+
+#[test]
+fn test_cursor_wraps_lazily() {
+  let mut cursor = Cursor { col: 0, row: 0, wrap_pending: false };
+  for col in 0..CONSOLE_COLS {
+    assert_eq!(cursor.next_cell(), (col, 0, false));
+  }
+  // A full line then a newline is just one line down.
+  assert!(!cursor.line_feed());
+  assert_eq!((cursor.col, cursor.row), (0, 1));
+  // On the last row, a full line only scrolls once more is printed.
+  cursor.row = CONSOLE_ROWS - 1;
+  for _ in 0..CONSOLE_COLS {
+    assert!(!cursor.next_cell().2);
+  }
+  assert_eq!(cursor.tab_width(), 4);
+  assert_eq!(cursor.next_cell(), (0, CONSOLE_ROWS - 1, true));
+  assert_eq!(cursor.tab_width(), 3);
+}
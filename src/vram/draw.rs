@@ -0,0 +1,487 @@
+//! Module for 2D drawing in the bitmap modes.
+//!
+//! Everything here is written once against the [`Surface`] trait, which only
+//! needs single pixel access and a span fill. The span fill is where each mode
+//! does its own thing: Mode 4 in particular can't write single bytes to VRAM,
+//! so its spans are written as `u16` pixel pairs.
+//!
+//! All of the drawing methods accept out of bounds coordinates and only draw
+//! the parts that are in bounds.
+
+use super::{
+  bitmap::{BackPage, Mode3, Page, PagedMode},
+  tile::TileCanvas,
+  *,
+};
+use core::marker::PhantomData;
+
+/// A rectangle of pixels, used to select part of an image.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Rect {
+  pub col: isize,
+  pub row: isize,
+  pub width: usize,
+  pub height: usize,
+}
+
+/// A borrowed image, row-major.
+#[derive(Debug, Clone, Copy)]
+pub struct Image<'a, P> {
+  pub pixels: &'a [P],
+  pub width: usize,
+}
+impl<'a, P: Copy> Image<'a, P> {
+  /// The number of full rows in the image, 0 if the width is 0.
+  pub fn height(&self) -> usize {
+    self.pixels.len().checked_div(self.width).unwrap_or(0)
+  }
+}
+
+/// Something that can be drawn on.
+pub trait Surface {
+  /// The pixel type: a palette index or a full color.
+  type Pixel: Copy;
+
+  /// The width in pixels.
+  fn width(&self) -> usize;
+
+  /// The height in pixels.
+  fn height(&self) -> usize;
+
+  /// Reads a pixel, `None` if out of bounds.
+  fn read_pixel(&self, col: usize, row: usize) -> Option<Self::Pixel>;
+
+  /// Writes a pixel, doing nothing if out of bounds.
+  fn write_pixel(&mut self, col: usize, row: usize, pixel: Self::Pixel);
+
+  /// Fills the columns `col_start..col_end` of a row, doing nothing for any
+  /// pixels that are out of bounds.
+  fn fill_span(&mut self, row: usize, col_start: usize, col_end: usize, pixel: Self::Pixel) {
+    for col in col_start..col_end {
+      self.write_pixel(col, row, pixel);
+    }
+  }
+
+  /// Writes a pixel at a signed position.
+  fn plot(&mut self, col: isize, row: isize, pixel: Self::Pixel) {
+    if col >= 0 && row >= 0 {
+      self.write_pixel(col as usize, row as usize, pixel);
+    }
+  }
+
+  /// Draws a horizontal line from `c1` to `c2` (inclusive) on `row`.
+  fn hline(&mut self, c1: isize, c2: isize, row: isize, pixel: Self::Pixel) {
+    let (c1, c2) = if c1 <= c2 { (c1, c2) } else { (c2, c1) };
+    if row < 0 || row as usize >= self.height() || c2 < 0 {
+      return;
+    }
+    let start = c1.max(0) as usize;
+    let end = ((c2 + 1) as usize).min(self.width());
+    if start < end {
+      self.fill_span(row as usize, start, end, pixel);
+    }
+  }
+
+  /// Draws a vertical line from `r1` to `r2` (inclusive) on `col`.
+  fn vline(&mut self, col: isize, r1: isize, r2: isize, pixel: Self::Pixel) {
+    let (r1, r2) = if r1 <= r2 { (r1, r2) } else { (r2, r1) };
+    for row in r1.max(0)..=r2.min(self.height() as isize - 1) {
+      self.plot(col, row, pixel);
+    }
+  }
+
+  /// Draws a line between the two points given.
+  ///
+  /// Only the steps that are on the surface's columns (or rows, for a steep
+  /// line) are walked, so far off screen endpoints don't cost any more than
+  /// ones at the edge.
+  fn line(&mut self, c1: isize, r1: isize, c2: isize, r2: isize, pixel: Self::Pixel) {
+    let (c1, r1, c2, r2) = (c1 as i128, r1 as i128, c2 as i128, r2 as i128);
+    let (width, height) = (self.width() as i128, self.height() as i128);
+    let (dx, dy) = ((c2 - c1).abs(), -(r2 - r1).abs());
+    let (step_c, step_r) = ((c2 - c1).signum(), (r2 - r1).signum());
+    // Every step moves one pixel along the major axis, and the minor axis
+    // position after `n` steps is `(2 * minor * n + major) / (2 * major)`.
+    let x_major = dx >= -dy;
+    let (start, step, extent, steps) =
+      if x_major { (c1, step_c, width, dx) } else { (r1, step_r, height, -dy) };
+    let (to_low, to_high) = (-start * step, (extent - 1 - start) * step);
+    let first = to_low.min(to_high).max(0);
+    let last = to_low.max(to_high).min(steps);
+    if steps == 0 {
+      self.plot(c1 as isize, r1 as isize, pixel);
+      return;
+    } else if first > last {
+      return;
+    }
+    let (mut i, mut j) = if x_major {
+      (first, (-2 * dy * first + dx).div_euclid(2 * dx))
+    } else {
+      ((2 * dx * first - dy).div_euclid(-2 * dy), first)
+    };
+    let mut err = dx + dy + i * dy + j * dx;
+    for _ in first..=last {
+      let (col, row) = (c1 + i * step_c, r1 + j * step_r);
+      if (0..width).contains(&col) && (0..height).contains(&row) {
+        self.write_pixel(col as usize, row as usize, pixel);
+      }
+      let e2 = 2 * err;
+      if e2 >= dy {
+        err += dy;
+        i += 1;
+      }
+      if e2 <= dx {
+        err += dx;
+        j += 1;
+      }
+    }
+  }
+
+  /// Draws the outline of a rectangle.
+  fn rect(&mut self, rect: Rect, pixel: Self::Pixel) {
+    if rect.width == 0 || rect.height == 0 {
+      return;
+    }
+    let right = rect.col + rect.width as isize - 1;
+    let bottom = rect.row + rect.height as isize - 1;
+    self.hline(rect.col, right, rect.row, pixel);
+    self.hline(rect.col, right, bottom, pixel);
+    self.vline(rect.col, rect.row, bottom, pixel);
+    self.vline(right, rect.row, bottom, pixel);
+  }
+
+  /// Fills a rectangle.
+  fn fill_rect(&mut self, rect: Rect, pixel: Self::Pixel) {
+    if rect.width == 0 {
+      return;
+    }
+    let right = rect.col + rect.width as isize - 1;
+    let bottom = (rect.row + rect.height as isize).min(self.height() as isize);
+    for row in rect.row.max(0)..bottom {
+      self.hline(rect.col, right, row, pixel);
+    }
+  }
+
+  /// Draws the outline of a circle.
+  fn circle(&mut self, col: isize, row: isize, radius: isize, pixel: Self::Pixel) {
+    let (mut x, mut y, mut err) = (radius, 0, 1 - radius);
+    while x >= y {
+      self.plot(col + x, row + y, pixel);
+      self.plot(col - x, row + y, pixel);
+      self.plot(col + x, row - y, pixel);
+      self.plot(col - x, row - y, pixel);
+      self.plot(col + y, row + x, pixel);
+      self.plot(col - y, row + x, pixel);
+      self.plot(col + y, row - x, pixel);
+      self.plot(col - y, row - x, pixel);
+      y += 1;
+      if err < 0 {
+        err += 2 * y + 1;
+      } else {
+        x -= 1;
+        err += 2 * (y - x) + 1;
+      }
+    }
+  }
+
+  /// Fills a circle.
+  fn fill_circle(&mut self, col: isize, row: isize, radius: isize, pixel: Self::Pixel) {
+    let (mut x, mut y, mut err) = (radius, 0, 1 - radius);
+    while x >= y {
+      self.hline(col - x, col + x, row + y, pixel);
+      self.hline(col - x, col + x, row - y, pixel);
+      self.hline(col - y, col + y, row + x, pixel);
+      self.hline(col - y, col + y, row - x, pixel);
+      y += 1;
+      if err < 0 {
+        err += 2 * y + 1;
+      } else {
+        x -= 1;
+        err += 2 * (y - x) + 1;
+      }
+    }
+  }
+
+  /// Draws the outline of a polygon, closing it back to the first point.
+  fn polygon(&mut self, points: &[(isize, isize)], pixel: Self::Pixel) {
+    for (i, &(c1, r1)) in points.iter().enumerate() {
+      let (c2, r2) = points[(i + 1) % points.len()];
+      self.line(c1, r1, c2, r2, pixel);
+    }
+  }
+
+  /// Fills a convex polygon.
+  ///
+  /// Concave polygons don't work, each row is filled between the leftmost and
+  /// rightmost edges that cross it.
+  fn fill_convex_polygon(&mut self, points: &[(isize, isize)], pixel: Self::Pixel) {
+    if points.is_empty() {
+      return;
+    }
+    let top = points.iter().map(|p| p.1).min().unwrap().max(0);
+    let bottom = points.iter().map(|p| p.1).max().unwrap().min(self.height() as isize - 1);
+    for row in top..=bottom {
+      let mut left = isize::MAX;
+      let mut right = isize::MIN;
+      for (i, &(c1, r1)) in points.iter().enumerate() {
+        let (c2, r2) = points[(i + 1) % points.len()];
+        let ((c1, r1), (c2, r2)) =
+          if r1 <= r2 { ((c1, r1), (c2, r2)) } else { ((c2, r2), (c1, r1)) };
+        if row < r1 || row > r2 {
+          continue;
+        }
+        if r1 == r2 {
+          left = left.min(c1.min(c2));
+          right = right.max(c1.max(c2));
+        } else {
+          let col = c1 + (row - r1) * (c2 - c1) / (r2 - r1);
+          left = left.min(col);
+          right = right.max(col);
+        }
+      }
+      if left <= right {
+        self.hline(left, right, row, pixel);
+      }
+    }
+  }
+
+  /// Draws the outline of a triangle.
+  fn triangle(&mut self, points: [(isize, isize); 3], pixel: Self::Pixel) {
+    self.polygon(&points, pixel);
+  }
+
+  /// Fills a triangle.
+  fn fill_triangle(&mut self, points: [(isize, isize); 3], pixel: Self::Pixel) {
+    self.fill_convex_polygon(&points, pixel);
+  }
+
+  /// Copies part of an image to the position given.
+  fn blit(&mut self, image: &Image<'_, Self::Pixel>, src: Rect, col: isize, row: isize) {
+    blit_with(self, image, src, col, row, |_| true);
+  }
+
+  /// Copies part of an image to the position given, skipping any pixels that
+  /// equal `key` (usually the transparent color or palette index 0).
+  fn blit_keyed(
+    &mut self, image: &Image<'_, Self::Pixel>, src: Rect, col: isize, row: isize,
+    key: Self::Pixel,
+  ) where
+    Self::Pixel: PartialEq,
+  {
+    blit_with(self, image, src, col, row, |pixel| pixel != key);
+  }
+}
+
+/// Copies the pixels of an image that pass the filter given.
+fn blit_with<S: Surface + ?Sized, F: Fn(S::Pixel) -> bool>(
+  surface: &mut S, image: &Image<'_, S::Pixel>, src: Rect, col: isize, row: isize, filter: F,
+) {
+  for y in 0..src.height as isize {
+    let (src_row, dest_row) = (src.row + y, row + y);
+    if src_row < 0 || src_row as usize >= image.height() {
+      continue;
+    }
+    for x in 0..src.width as isize {
+      let src_col = src.col + x;
+      if src_col < 0 || src_col as usize >= image.width {
+        continue;
+      }
+      let pixel = image.pixels[src_row as usize * image.width + src_col as usize];
+      if filter(pixel) {
+        surface.plot(col + x, dest_row, pixel);
+      }
+    }
+  }
+}
+
+impl Surface for Mode3 {
+  type Pixel = Color;
+  fn width(&self) -> usize {
+    Mode3::WIDTH
+  }
+  fn height(&self) -> usize {
+    Mode3::HEIGHT
+  }
+  fn read_pixel(&self, col: usize, row: usize) -> Option<Color> {
+    Mode3::read(col, row)
+  }
+  #[inline(always)]
+  fn write_pixel(&mut self, col: usize, row: usize, pixel: Color) {
+    if col < Mode3::WIDTH {
+      Mode3::write(col, row, pixel);
+    }
+  }
+  #[cfg_attr(target_arch = "arm", link_section = ".text_iwram", instruction_set(arm::a32))]
+  fn fill_span(&mut self, row: usize, col_start: usize, col_end: usize, pixel: Color) {
+    for col in col_start..col_end.min(Mode3::WIDTH) {
+      Mode3::write(col, row, pixel);
+    }
+  }
+}
+
+/// A single page of Mode 4 or Mode 5, for drawing to directly.
+///
+/// To draw only to the hidden page, use a `DoubleBuffer` instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageSurface<M: PagedMode> {
+  page: Page,
+  mode: PhantomData<M>,
+}
+impl<M: PagedMode> PageSurface<M> {
+  /// Targets the page given.
+  pub const fn new(page: Page) -> Self {
+    Self { page, mode: PhantomData }
+  }
+}
+
+impl<M: PagedMode> Surface for PageSurface<M> {
+  type Pixel = M::Pixel;
+  fn width(&self) -> usize {
+    M::WIDTH
+  }
+  fn height(&self) -> usize {
+    M::HEIGHT
+  }
+  fn read_pixel(&self, col: usize, row: usize) -> Option<M::Pixel> {
+    M::read(self.page, col, row)
+  }
+  #[inline(always)]
+  fn write_pixel(&mut self, col: usize, row: usize, pixel: M::Pixel) {
+    if col < M::WIDTH {
+      M::write(self.page, col, row, pixel);
+    }
+  }
+  #[inline(always)]
+  fn fill_span(&mut self, row: usize, col_start: usize, col_end: usize, pixel: M::Pixel) {
+    M::fill_span(self.page, row, col_start, col_end, pixel)
+  }
+}
+
+impl<'a, M: PagedMode> Surface for BackPage<'a, M> {
+  type Pixel = M::Pixel;
+  fn width(&self) -> usize {
+    M::WIDTH
+  }
+  fn height(&self) -> usize {
+    M::HEIGHT
+  }
+  fn read_pixel(&self, col: usize, row: usize) -> Option<M::Pixel> {
+    M::read(self.page(), col, row)
+  }
+  #[inline(always)]
+  fn write_pixel(&mut self, col: usize, row: usize, pixel: M::Pixel) {
+    if col < M::WIDTH {
+      M::write(self.page(), col, row, pixel);
+    }
+  }
+  #[inline(always)]
+  fn fill_span(&mut self, row: usize, col_start: usize, col_end: usize, pixel: M::Pixel) {
+    M::fill_span(self.page(), row, col_start, col_end, pixel)
+  }
+}
+
+impl Surface for TileCanvas {
+  type Pixel = u8;
+  fn width(&self) -> usize {
+    self.size().0 * 8
+  }
+  fn height(&self) -> usize {
+    self.size().1 * 8
+  }
+  fn read_pixel(&self, col: usize, row: usize) -> Option<u8> {
+    self.tile(col / 8, row / 8)?.get_pixel(col % 8, row % 8)
+  }
+  fn write_pixel(&mut self, col: usize, row: usize, pixel: u8) {
+    if let Some(tile) = self.tile(col / 8, row / 8) {
+      tile.set_pixel(col % 8, row % 8, pixel);
+    }
+  }
+  fn fill_span(&mut self, row: usize, col_start: usize, col_end: usize, pixel: u8) {
+    let mut col = col_start;
+    while col < col_end.min(self.width()) {
+      let end = col_end.min((col / 8 + 1) * 8);
+      if let Some(tile) = self.tile(col / 8, row / 8) {
+        tile.fill_rect(col % 8, row % 8, end - col, 1, pixel);
+      }
+      col = end;
+    }
+  }
+}
+
+/// A surface in normal memory, such as an off-screen buffer.
+#[derive(Debug)]
+pub struct Framebuffer<'a, P> {
+  pixels: &'a mut [P],
+  width: usize,
+}
+impl<'a, P: Copy> Framebuffer<'a, P> {
+  /// Wraps a row-major slice of pixels that's `width` pixels wide.
+  ///
+  /// ## Panics
+  ///
+  /// If `width` is 0.
+  pub fn new(pixels: &'a mut [P], width: usize) -> Self {
+    assert!(width != 0, "framebuffer width must not be 0");
+    Self { pixels, width }
+  }
+
+  /// The pixels as a slice.
+  pub fn pixels(&self) -> &[P] {
+    self.pixels
+  }
+}
+
+impl<'a, P: Copy> Surface for Framebuffer<'a, P> {
+  type Pixel = P;
+  fn width(&self) -> usize {
+    self.width
+  }
+  fn height(&self) -> usize {
+    self.pixels.len() / self.width
+  }
+  fn read_pixel(&self, col: usize, row: usize) -> Option<P> {
+    if col < self.width {
+      self.pixels.get(row * self.width + col).copied()
+    } else {
+      None
+    }
+  }
+  fn write_pixel(&mut self, col: usize, row: usize, pixel: P) {
+    if col < self.width {
+      if let Some(p) = self.pixels.get_mut(row * self.width + col) {
+        *p = pixel;
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_fill_rect_clips() {
+    let mut pixels = [0_u8; 16];
+    let mut fb = Framebuffer::new(&mut pixels, 4);
+    fb.fill_rect(Rect { col: -1, row: 2, width: 3, height: 5 }, 1);
+    assert_eq!(fb.pixels(), &[0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 0, 0, 1, 1, 0, 0]);
+  }
+
+  #[test]
+  fn test_fill_triangle() {
+    let mut pixels = [0_u8; 16];
+    let mut fb = Framebuffer::new(&mut pixels, 4);
+    fb.fill_triangle([(0, 0), (3, 3), (0, 3)], 1);
+    assert_eq!(fb.pixels(), &[1, 0, 0, 0, 1, 1, 0, 0, 1, 1, 1, 0, 1, 1, 1, 1]);
+  }
+
+  #[test]
+  fn test_line_clips() {
+    let mut pixels = [0_u8; 16];
+    let mut fb = Framebuffer::new(&mut pixels, 4);
+    fb.line(-1_000_000, -1_000_000, 1_000_003, 1_000_003, 1);
+    fb.line(isize::MIN, 2, isize::MAX, 2, 2);
+    fb.line(-5, -1, 5, -1, 3);
+    assert_eq!(fb.pixels(), &[1, 0, 0, 0, 0, 1, 0, 0, 2, 2, 2, 2, 0, 0, 0, 1]);
+  }
+}
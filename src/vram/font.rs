@@ -0,0 +1,405 @@
+//! Module for variable width fonts.
+//!
+//! A `Font` is plain `const` data: a width for each glyph and the glyph
+//! bitmaps, one `u32` per row with the leftmost pixel in the lowest bits (as
+//! with tiles). Glyphs are 1bpp (ink or nothing) or 2bpp (three inks, such as
+//! a color, a shadow, and an outline). Pixel value 0 is always transparent.
+//!
+//! Text is drawn onto any [`Surface`], with the ink colors given as that
+//! surface's pixel type. The same font can draw into a `TileCanvas` (palette
+//! indexes, shown on a text background), into Mode 4 (palette indexes), or
+//! into Mode 3 (colors).
+//!
+//! ```no_run
+//! let mut canvas = TileCanvas::new(0, 1, 28, 4).unwrap();
+//! canvas.clear();
+//! canvas.map_to(&TextMap32x32::new(31).unwrap(), 1, 15, 0);
+//! SYSTEM_FONT.draw_wrapped(&mut canvas, dialogue, 0, 0, 28 * 8, [1, 2, 3]);
+//! ```
+
+use super::{
+  console::{FONT_8X8, FONT_GLYPH_COUNT},
+  draw::Surface,
+  *,
+};
+
+/// The number of bits per pixel in a font's glyphs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GlyphDepth {
+  /// Up to 32 pixels wide, one ink.
+  One,
+  /// Up to 16 pixels wide, three inks.
+  Two,
+}
+
+impl GlyphDepth {
+  /// The number of bits per pixel.
+  pub const fn bits(self) -> usize {
+    match self {
+      GlyphDepth::One => 1,
+      GlyphDepth::Two => 2,
+    }
+  }
+}
+
+/// An adjustment to the gap between two characters, such as to tuck a `o`
+/// under a `T`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KerningPair {
+  pub left: char,
+  pub right: char,
+  /// Added to the gap between the two, usually negative.
+  pub adjust: i8,
+}
+
+impl KerningPair {
+  /// Makes a pair.
+  pub const fn new(left: char, right: char, adjust: i8) -> Self {
+    Self { left, right, adjust }
+  }
+}
+
+/// A variable width bitmap font.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Font<'a> {
+  /// The number of rows in every glyph.
+  pub height: u8,
+  /// The bits per pixel of the glyph rows.
+  pub depth: GlyphDepth,
+  /// The character of the first glyph. Glyphs go in character order from
+  /// here.
+  pub first_char: char,
+  /// The width of each glyph in pixels.
+  pub widths: &'a [u8],
+  /// The glyph rows, `height` rows per glyph, glyph after glyph.
+  pub rows: &'a [u32],
+  /// Gap adjustments, sorted by `left` then `right`.
+  pub kerning: &'a [KerningPair],
+  /// The gap between glyphs in pixels.
+  pub spacing: u8,
+  /// The distance from the top of one line to the top of the next.
+  pub line_height: u8,
+  /// Drawn for characters that the font doesn't have.
+  pub fallback: char,
+}
+
+impl<'a> Font<'a> {
+  /// Makes a font with no kerning, 1 pixel between glyphs, 1 pixel between
+  /// lines, and `?` for missing characters.
+  pub const fn new(
+    height: u8, depth: GlyphDepth, first_char: char, widths: &'a [u8], rows: &'a [u32],
+  ) -> Self {
+    Self {
+      height,
+      depth,
+      first_char,
+      widths,
+      rows,
+      kerning: &[],
+      spacing: 1,
+      line_height: height.saturating_add(1),
+      fallback: '?',
+    }
+  }
+
+  /// Sets the kerning pairs, which must be sorted by `left` then `right`.
+  pub const fn with_kerning(self, kerning: &'a [KerningPair]) -> Self {
+    Self { kerning, ..self }
+  }
+
+  /// Sets the gap between glyphs.
+  pub const fn with_spacing(self, spacing: u8) -> Self {
+    Self { spacing, ..self }
+  }
+
+  /// Sets the distance from the top of one line to the top of the next.
+  pub const fn with_line_height(self, line_height: u8) -> Self {
+    Self { line_height, ..self }
+  }
+
+  /// Sets the character drawn for characters the font doesn't have.
+  pub const fn with_fallback(self, fallback: char) -> Self {
+    Self { fallback, ..self }
+  }
+
+  fn index_of(&self, ch: char) -> Option<usize> {
+    let index = (ch as u32).checked_sub(self.first_char as u32)? as usize;
+    let rows_end = (index + 1) * self.height as usize;
+    if index < self.widths.len() && rows_end <= self.rows.len() {
+      Some(index)
+    } else {
+      None
+    }
+  }
+
+  /// The glyph for a character, or for the fallback character if the font
+  /// doesn't have it.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` if the font has neither.
+  pub fn glyph(&self, ch: char) -> Option<Glyph<'a>> {
+    let index = self.index_of(ch).or_else(|| self.index_of(self.fallback))?;
+    let height = self.height as usize;
+    Some(Glyph {
+      width: self.widths[index],
+      rows: &self.rows[index * height..(index + 1) * height],
+      depth: self.depth,
+    })
+  }
+
+  /// The kerning adjustment between two characters.
+  pub fn kerning(&self, left: char, right: char) -> i32 {
+    self
+      .kerning
+      .binary_search_by(|pair| (pair.left, pair.right).cmp(&(left, right)))
+      .map(|i| self.kerning[i].adjust as i32)
+      .unwrap_or(0)
+  }
+
+  /// How far a glyph is drawn from the pen position, given the character
+  /// before it on the line.
+  fn offset(&self, prev: Option<char>, ch: char) -> i32 {
+    prev.map(|prev| self.kerning(prev, ch)).unwrap_or(0)
+  }
+
+  /// How far the pen moves after drawing a character.
+  fn advance(&self, ch: char) -> i32 {
+    self.glyph(ch).map(|g| g.width as i32).unwrap_or(0) + self.spacing as i32
+  }
+
+  /// The width of a line in pixels. Stops at the first `\n`.
+  pub fn line_width(&self, line: &str) -> u32 {
+    let mut pen = 0;
+    let mut right = 0;
+    let mut prev = None;
+    for ch in line.chars().take_while(|&ch| ch != '\n') {
+      pen += self.offset(prev, ch);
+      right = right.max(pen + self.glyph(ch).map(|g| g.width as i32).unwrap_or(0));
+      pen += self.advance(ch);
+      prev = Some(ch);
+    }
+    right.max(0) as u32
+  }
+
+  /// The size of some text in pixels, as `(width, height)`, with lines split
+  /// at each `\n`.
+  pub fn measure(&self, text: &str) -> (u32, u32) {
+    let width = text.split('\n').map(|line| self.line_width(line)).max().unwrap_or(0);
+    let lines = text.split('\n').count() as u32;
+    (width, (lines - 1) * self.line_height as u32 + self.height as u32)
+  }
+
+  /// Splits text into lines no wider than `max_width`.
+  ///
+  /// Lines break at `\n`, and at the last space that fits. A word that's too
+  /// wide for a line by itself is broken where it reaches the edge.
+  pub fn wrap<'f, 't>(&'f self, text: &'t str, max_width: u32) -> LineBreaks<'f, 't> {
+    LineBreaks { font: self, text: Some(text), max_width: max_width as i32 }
+  }
+
+  /// Draws a glyph with its top left at `(x, y)`, using `inks[n - 1]` for
+  /// pixel value `n`.
+  pub fn draw_glyph<S: Surface + ?Sized>(
+    &self, surface: &mut S, glyph: &Glyph<'_>, x: isize, y: isize, inks: [S::Pixel; 3],
+  ) {
+    for (row, &bits) in glyph.rows.iter().enumerate() {
+      if bits == 0 {
+        continue;
+      }
+      for col in 0..glyph.width as usize {
+        let value = glyph.pixel(col, row);
+        if value != 0 {
+          surface.plot(x + col as isize, y + row as isize, inks[value as usize - 1]);
+        }
+      }
+    }
+  }
+
+  /// Draws text with its top left at `(x, y)`, with lines split at each `\n`.
+  ///
+  /// Gives the pen position after the last character, which is where more
+  /// text on the same line would go.
+  pub fn draw<S: Surface + ?Sized>(
+    &self, surface: &mut S, text: &str, x: isize, y: isize, inks: [S::Pixel; 3],
+  ) -> (isize, isize) {
+    let (mut pen_x, mut pen_y) = (x, y);
+    let mut prev = None;
+    for ch in text.chars() {
+      if ch == '\n' {
+        pen_x = x;
+        pen_y += self.line_height as isize;
+        prev = None;
+        continue;
+      }
+      pen_x += self.offset(prev, ch) as isize;
+      if let Some(glyph) = self.glyph(ch) {
+        self.draw_glyph(surface, &glyph, pen_x, pen_y, inks);
+      }
+      pen_x += self.advance(ch) as isize;
+      prev = Some(ch);
+    }
+    (pen_x, pen_y)
+  }
+
+  /// Draws text wrapped to `max_width`, see `wrap`.
+  ///
+  /// Gives the number of lines drawn.
+  pub fn draw_wrapped<S: Surface + ?Sized>(
+    &self, surface: &mut S, text: &str, x: isize, y: isize, max_width: u32,
+    inks: [S::Pixel; 3],
+  ) -> usize {
+    let mut count = 0;
+    for (i, line) in self.wrap(text, max_width).enumerate() {
+      self.draw(surface, line, x, y + (i * self.line_height as usize) as isize, inks);
+      count += 1;
+    }
+    count
+  }
+}
+
+/// One glyph of a font.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Glyph<'a> {
+  /// The width in pixels.
+  pub width: u8,
+  rows: &'a [u32],
+  depth: GlyphDepth,
+}
+
+impl<'a> Glyph<'a> {
+  /// The value of a pixel: 0 for transparent, or which ink to use. Pixels
+  /// outside the glyph are 0.
+  pub fn pixel(&self, x: usize, y: usize) -> u8 {
+    let bits = self.depth.bits();
+    match self.rows.get(y) {
+      Some(&row) if x < self.width as usize && x * bits < 32 => {
+        ((row >> (x * bits)) & ((1 << bits) - 1)) as u8
+      }
+      _ => 0,
+    }
+  }
+}
+
+/// The lines of some text wrapped to a width, see `Font::wrap`.
+#[derive(Debug, Clone)]
+pub struct LineBreaks<'a, 't> {
+  font: &'a Font<'a>,
+  text: Option<&'t str>,
+  max_width: i32,
+}
+
+impl<'a, 't> Iterator for LineBreaks<'a, 't> {
+  type Item = &'t str;
+
+  fn next(&mut self) -> Option<&'t str> {
+    let text = self.text?;
+    let font = self.font;
+    let mut pen = 0;
+    let mut prev = None;
+    let mut last_space = None;
+    for (i, ch) in text.char_indices() {
+      if ch == '\n' {
+        self.text = Some(&text[i + 1..]);
+        return Some(&text[..i]);
+      }
+      let left = pen + font.offset(prev, ch);
+      let right = left + font.glyph(ch).map(|g| g.width as i32).unwrap_or(0);
+      if ch == ' ' {
+        last_space = Some(i);
+      } else if right > self.max_width && prev.is_some() {
+        let (line, rest) = match last_space {
+          Some(space) => (&text[..space], &text[space + 1..]),
+          None => (&text[..i], &text[i..]),
+        };
+        self.text = Some(rest);
+        return Some(line);
+      }
+      pen = left + font.advance(ch);
+      prev = Some(ch);
+    }
+    self.text = None;
+    Some(text)
+  }
+}
+
+/// The widths of `SYSTEM_FONT`: each glyph of `FONT_8X8` trimmed to the
+/// columns it uses, with spaces 3 pixels wide.
+const fn system_font_widths() -> [u8; FONT_GLYPH_COUNT] {
+  let mut widths = [0; FONT_GLYPH_COUNT];
+  let mut i = 0;
+  while i < FONT_GLYPH_COUNT {
+    let used = glyph_columns(i);
+    widths[i] =
+      if used == 0 { 3 } else { (8 - used.leading_zeros() - used.trailing_zeros()) as u8 };
+    i += 1;
+  }
+  widths
+}
+
+/// The rows of `SYSTEM_FONT`: each glyph of `FONT_8X8` moved over to the
+/// left edge.
+const fn system_font_rows() -> [u32; FONT_GLYPH_COUNT * 8] {
+  let mut rows = [0; FONT_GLYPH_COUNT * 8];
+  let mut i = 0;
+  while i < FONT_GLYPH_COUNT {
+    let shift = glyph_columns(i).trailing_zeros() % 8;
+    let mut row = 0;
+    while row < 8 {
+      rows[i * 8 + row] = (FONT_8X8[i][row] >> shift) as u32;
+      row += 1;
+    }
+    i += 1;
+  }
+  rows
+}
+
+/// The columns that a glyph of `FONT_8X8` uses, as a bit mask.
+const fn glyph_columns(glyph: usize) -> u8 {
+  let mut used = 0;
+  let mut row = 0;
+  while row < 8 {
+    used |= FONT_8X8[glyph][row];
+    row += 1;
+  }
+  used
+}
+
+const SYSTEM_FONT_WIDTHS: [u8; FONT_GLYPH_COUNT] = system_font_widths();
+const SYSTEM_FONT_ROWS: [u32; FONT_GLYPH_COUNT * 8] = system_font_rows();
+
+/// A proportional version of the console's 8x8 font, covering printable
+/// ASCII.
+pub const SYSTEM_FONT: Font<'static> =
+  Font::new(8, GlyphDepth::One, ' ', &SYSTEM_FONT_WIDTHS, &SYSTEM_FONT_ROWS);
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::vram::draw::Framebuffer;
+
+  #[test]
+  fn test_measure_wrap_and_draw() {
+    // 'A' is 2 wide, 'B' is 3 wide, both 2 rows; 'B' uses two inks.
+    const WIDTHS: [u8; 2] = [2, 3];
+    const ROWS: [u32; 4] = [0b0101, 0b0101, 0b10_00_01, 0b01_01_10];
+    const KERNING: [KerningPair; 1] = [KerningPair::new('A', 'B', -1)];
+    let font = Font::new(2, GlyphDepth::Two, 'A', &WIDTHS, &ROWS).with_kerning(&KERNING);
+    assert_eq!(font.line_width("AB"), 2 + 1 - 1 + 3);
+    assert_eq!(font.line_width("BA"), 3 + 1 + 2);
+    assert_eq!(font.measure("AB\nBAB"), (9, 5));
+    let mut wrapped = font.wrap("AB AB BAAB\nA", 6);
+    assert_eq!(wrapped.next(), Some("AB"));
+    assert_eq!(wrapped.next(), Some("AB"));
+    assert_eq!(wrapped.next(), Some("BA"));
+    assert_eq!(wrapped.next(), Some("AB"));
+    assert_eq!(wrapped.next(), Some("A"));
+    assert_eq!(wrapped.next(), None);
+    let mut pixels = [0_u8; 16];
+    let mut fb = Framebuffer::new(&mut pixels, 8);
+    assert_eq!(font.draw(&mut fb, "AB", 0, 0, [7, 8, 9]), (6, 0));
+    assert_eq!(fb.pixels(), &[7, 7, 7, 0, 8, 0, 0, 0, 7, 7, 8, 7, 7, 0, 0, 0]);
+    assert_eq!(SYSTEM_FONT.glyph('!').unwrap().width, 1);
+    assert_eq!(SYSTEM_FONT.glyph('\u{7f}'), SYSTEM_FONT.glyph('?'));
+  }
+}
@@ -0,0 +1,387 @@
+//! Module for planning where things go in VRAM, checked at compile time.
+//!
+//! A `VramLayout` describes the tilesets (which charblock each one starts in
+//! and how many tiles it has), the map of each background (which tileset it
+//! uses, and where its screenblocks start at its `BGSize`), the object tile
+//! region, and the bitmap in the bitmap modes. Build it as a `const` and call
+//! `check`: if any two regions overlap, or a region goes past the end of
+//! VRAM, the build fails with a message saying what went wrong.
+//!
+//! ```no_run
+//! const LAYOUT: VramLayout = VramLayout::new()
+//!   .with_tileset(0, Tileset::new_4bpp(0, 512))
+//!   .with_tileset(1, Tileset::new_4bpp(1, 128))
+//!   .with_bg(0, BgMap::text(0, 28, BGSize::One))
+//!   .with_bg(1, BgMap::text(1, 30, BGSize::Zero))
+//!   .with_obj_tiles(0, 1024)
+//!   .check();
+//! const BG0_CONTROL: BackgroundControlSetting = LAYOUT.bg_control(0);
+//! ```
+//!
+//! The first tileset would run into the second if it had more than 512
+//! tiles, and the second map can't start at screenblock 29, because the
+//! first map takes screenblocks 28 and 29:
+//!
+//! ```compile_fail
+//! # use gba::{io::background::BGSize, vram::layout::*};
+//! const LAYOUT: VramLayout = VramLayout::new()
+//!   .with_tileset(0, Tileset::new_4bpp(0, 512))
+//!   .with_bg(0, BgMap::text(0, 28, BGSize::One))
+//!   .with_bg(1, BgMap::text(0, 29, BGSize::Zero))
+//!   .check();
+//! ```
+//!
+//! Backgrounds may share a tileset, but not a map.
+
+use super::*;
+use crate::io::{
+  background::{BGSize, BackgroundControlSetting},
+  display::DisplayMode,
+};
+
+/// The number of tilesets a layout can describe.
+pub const MAX_TILESETS: usize = 4;
+
+/// The number of bytes of VRAM that backgrounds can read.
+const BG_VRAM_BYTES: u32 = 0x1_0000;
+
+/// The number of bytes of VRAM.
+const VRAM_BYTES: u32 = 0x1_8000;
+
+/// The first byte of object tile VRAM.
+const OBJ_VRAM_START: u32 = 0x1_0000;
+
+/// Background tiles, starting at the beginning of a charblock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tileset {
+  /// The charblock the tiles start in, 0 through 3.
+  pub char_base_block: u16,
+  /// The number of tiles.
+  pub tile_count: u16,
+  /// If the tiles are 8bpp.
+  pub is_8bpp: bool,
+}
+
+impl Tileset {
+  /// A set of 4bpp tiles (32 bytes each).
+  pub const fn new_4bpp(char_base_block: u16, tile_count: u16) -> Self {
+    Self { char_base_block, tile_count, is_8bpp: false }
+  }
+
+  /// A set of 8bpp tiles (64 bytes each).
+  pub const fn new_8bpp(char_base_block: u16, tile_count: u16) -> Self {
+    Self { char_base_block, tile_count, is_8bpp: true }
+  }
+
+  const fn region(&self) -> Region {
+    let start = self.char_base_block as u32 * 0x4000;
+    let tile_bytes = if self.is_8bpp { 64 } else { 32 };
+    Region { start, end: start + self.tile_count as u32 * tile_bytes, kind: RegionKind::Tileset }
+  }
+}
+
+/// A background's map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BgMap {
+  /// The index of the tileset the background uses.
+  pub tileset: usize,
+  /// The screenblock the map starts in, 0 through 31.
+  pub screen_base_block: u16,
+  /// The size of the background.
+  pub size: BGSize,
+  /// If the background is affine (otherwise it's text).
+  pub affine: bool,
+}
+
+impl BgMap {
+  /// The map of a text background.
+  pub const fn text(tileset: usize, screen_base_block: u16, size: BGSize) -> Self {
+    Self { tileset, screen_base_block, size, affine: false }
+  }
+
+  /// The map of an affine background.
+  pub const fn affine(tileset: usize, screen_base_block: u16, size: BGSize) -> Self {
+    Self { tileset, screen_base_block, size, affine: true }
+  }
+
+  /// The number of bytes the map uses.
+  pub const fn byte_size(&self) -> u32 {
+    match (self.affine, self.size) {
+      (false, BGSize::Zero) => 0x800,
+      (false, BGSize::One) | (false, BGSize::Two) => 0x1000,
+      (false, BGSize::Three) => 0x2000,
+      (true, BGSize::Zero) => 16 * 16,
+      (true, BGSize::One) => 32 * 32,
+      (true, BGSize::Two) => 64 * 64,
+      (true, BGSize::Three) => 128 * 128,
+    }
+  }
+
+  const fn region(&self) -> Region {
+    let start = self.screen_base_block as u32 * 0x800;
+    Region { start, end: start + self.byte_size(), kind: RegionKind::Map }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RegionKind {
+  Tileset,
+  Map,
+  ObjTiles,
+  Bitmap,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Region {
+  start: u32,
+  end: u32,
+  kind: RegionKind,
+}
+
+impl Region {
+  const fn overlaps(&self, other: &Region) -> bool {
+    self.start < other.end
+      && other.start < self.end
+      && self.start < self.end
+      && other.start < other.end
+  }
+}
+
+/// A plan of what goes where in VRAM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VramLayout {
+  tilesets: [Option<Tileset>; MAX_TILESETS],
+  bgs: [Option<BgMap>; 4],
+  obj_tiles: Option<(u16, u16)>,
+  bitmap: Option<DisplayMode>,
+}
+
+impl VramLayout {
+  /// An empty layout.
+  pub const fn new() -> Self {
+    Self { tilesets: [None; MAX_TILESETS], bgs: [None; 4], obj_tiles: None, bitmap: None }
+  }
+
+  /// Adds a tileset, replacing any other with the same index.
+  pub const fn with_tileset(mut self, index: usize, tileset: Tileset) -> Self {
+    self.tilesets[index] = Some(tileset);
+    self
+  }
+
+  /// Sets the map of a background (0 through 3).
+  pub const fn with_bg(mut self, bg: usize, map: BgMap) -> Self {
+    self.bgs[bg] = Some(map);
+    self
+  }
+
+  /// Reserves object tile memory, as a run of 32 byte units (the units that
+  /// `OBJAttr2::tile_id` counts in).
+  pub const fn with_obj_tiles(mut self, first_unit: u16, unit_count: u16) -> Self {
+    self.obj_tiles = Some((first_unit, unit_count));
+    self
+  }
+
+  /// Reserves the bitmap of one of the bitmap modes, both pages for modes 4
+  /// and 5.
+  pub const fn with_bitmap(mut self, mode: DisplayMode) -> Self {
+    self.bitmap = Some(mode);
+    self
+  }
+
+  /// Checks the layout, and gives it back if it's good.
+  ///
+  /// ## Panics
+  ///
+  /// If any regions overlap or don't fit, a background uses a tileset that
+  /// isn't in the layout, or an affine background uses 4bpp tiles. In a
+  /// `const` this stops the build.
+  pub const fn check(self) -> Self {
+    let mut regions = [Region { start: 0, end: 0, kind: RegionKind::Map }; 11];
+    let mut count = 0;
+    let mut i = 0;
+    while i < MAX_TILESETS {
+      if let Some(tileset) = self.tilesets[i] {
+        let region = tileset.region();
+        if tileset.char_base_block > 3 || region.end > BG_VRAM_BYTES {
+          panic!("VRAM layout: a tileset goes past the background area");
+        }
+        regions[count] = region;
+        count += 1;
+      }
+      i += 1;
+    }
+    i = 0;
+    while i < 4 {
+      if let Some(map) = self.bgs[i] {
+        let tileset = self.map_tileset(i, map);
+        if map.affine && !tileset.is_8bpp {
+          panic!("VRAM layout: affine backgrounds need 8bpp tiles");
+        }
+        if (map.affine && tileset.tile_count > 256) || tileset.tile_count > 1024 {
+          panic!("VRAM layout: a background's tileset has more tiles than it can use");
+        }
+        let region = map.region();
+        if region.end > BG_VRAM_BYTES {
+          panic!("VRAM layout: a map goes past the background area");
+        }
+        regions[count] = region;
+        count += 1;
+      }
+      i += 1;
+    }
+    if let Some((first_unit, unit_count)) = self.obj_tiles {
+      let start = OBJ_VRAM_START + first_unit as u32 * 32;
+      let region =
+        Region { start, end: start + unit_count as u32 * 32, kind: RegionKind::ObjTiles };
+      if region.end > VRAM_BYTES {
+        panic!("VRAM layout: the object tiles go past the end of VRAM");
+      }
+      if self.bitmap.is_some() && first_unit < 512 {
+        panic!("VRAM layout: the bitmap modes can only use object tiles from unit 512");
+      }
+      regions[count] = region;
+      count += 1;
+    }
+    if let Some(mode) = self.bitmap {
+      let (page_bytes, pages) = match mode {
+        DisplayMode::Mode3 => (240 * 160 * 2, 1),
+        DisplayMode::Mode4 => (240 * 160, 2),
+        DisplayMode::Mode5 => (160 * 128 * 2, 2),
+        _ => panic!("VRAM layout: the bitmap mode must be 3, 4 or 5"),
+      };
+      regions[count] = Region { start: 0, end: page_bytes, kind: RegionKind::Bitmap };
+      count += 1;
+      if pages == 2 {
+        let start = PAGE1_OFFSET as u32;
+        regions[count] = Region { start, end: start + page_bytes, kind: RegionKind::Bitmap };
+        count += 1;
+      }
+    }
+    i = 0;
+    while i < count {
+      let mut j = i + 1;
+      while j < count {
+        if regions[i].overlaps(&regions[j]) {
+          panic!("{}", overlap_message(regions[i].kind, regions[j].kind));
+        }
+        j += 1;
+      }
+      i += 1;
+    }
+    self
+  }
+
+  /// The control setting for a background, with its char base block,
+  /// screen base block, size and color depth set.
+  ///
+  /// Other fields (such as the priority) are left at 0.
+  ///
+  /// ## Panics
+  ///
+  /// If the background isn't in the layout, or the layout doesn't `check`.
+  pub const fn bg_control(&self, bg: usize) -> BackgroundControlSetting {
+    let layout = self.check();
+    let map = match layout.bgs[bg] {
+      Some(map) => map,
+      None => panic!("VRAM layout: that background isn't in the layout"),
+    };
+    let tileset = layout.map_tileset(bg, map);
+    BackgroundControlSetting::new()
+      .with_char_base_block(tileset.char_base_block)
+      .with_screen_base_block(map.screen_base_block)
+      .with_size(map.size)
+      .with_is_8bpp(tileset.is_8bpp)
+  }
+
+  /// The tileset a background's map uses.
+  const fn map_tileset(&self, bg: usize, map: BgMap) -> Tileset {
+    if map.tileset >= MAX_TILESETS {
+      panic!("{}", tileset_message(bg, true));
+    }
+    match self.tilesets[map.tileset] {
+      Some(tileset) => tileset,
+      None => panic!("{}", tileset_message(bg, false)),
+    }
+  }
+
+  /// The first object tile id that's reserved, for use with
+  /// `OBJAttr2::with_tile_id`.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` if the layout doesn't reserve object tiles.
+  pub const fn obj_tile_base(&self) -> Option<u16> {
+    match self.obj_tiles {
+      Some((first_unit, _)) => Some(first_unit),
+      None => None,
+    }
+  }
+}
+
+impl Default for VramLayout {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+const fn tileset_message(bg: usize, out_of_range: bool) -> &'static str {
+  match (bg, out_of_range) {
+    (0, true) => "VRAM layout: the BG0 map's tileset index is out of range",
+    (1, true) => "VRAM layout: the BG1 map's tileset index is out of range",
+    (2, true) => "VRAM layout: the BG2 map's tileset index is out of range",
+    (_, true) => "VRAM layout: the BG3 map's tileset index is out of range",
+    (0, false) => "VRAM layout: the BG0 map uses a tileset that isn't in the layout",
+    (1, false) => "VRAM layout: the BG1 map uses a tileset that isn't in the layout",
+    (2, false) => "VRAM layout: the BG2 map uses a tileset that isn't in the layout",
+    (_, false) => "VRAM layout: the BG3 map uses a tileset that isn't in the layout",
+  }
+}
+
+const fn overlap_message(a: RegionKind, b: RegionKind) -> &'static str {
+  match (a, b) {
+    (RegionKind::Tileset, RegionKind::Tileset) => "VRAM layout: two tilesets overlap",
+    (RegionKind::Map, RegionKind::Map) => "VRAM layout: two background maps overlap",
+    (RegionKind::Tileset, RegionKind::Map) | (RegionKind::Map, RegionKind::Tileset) => {
+      "VRAM layout: a tileset overlaps a background map"
+    }
+    (RegionKind::Bitmap, _) | (_, RegionKind::Bitmap) => {
+      "VRAM layout: the bitmap overlaps another region"
+    }
+    _ => "VRAM layout: the object tiles overlap another region",
+  }
+}
+
+#[test]
+fn test_layout_bg_control() {
+  const LAYOUT: VramLayout = VramLayout::new()
+    .with_tileset(0, Tileset::new_4bpp(0, 512))
+    .with_tileset(1, Tileset::new_8bpp(1, 256))
+    .with_bg(0, BgMap::text(0, 28, BGSize::One))
+    .with_bg(2, BgMap::affine(1, 31, BGSize::One))
+    .with_obj_tiles(0, 1024)
+    .check();
+  const BG2: BackgroundControlSetting = LAYOUT.bg_control(2);
+  assert_eq!(BG2.char_base_block(), 1);
+  assert_eq!(BG2.screen_base_block(), 31);
+  assert!(BG2.is_8bpp());
+  assert_eq!(LAYOUT.bg_control(0).size(), BGSize::One);
+}
+
+#[test]
+#[should_panic(expected = "VRAM layout: two background maps overlap")]
+fn test_layout_rejects_overlap() {
+  VramLayout::new()
+    .with_tileset(0, Tileset::new_4bpp(0, 512))
+    .with_bg(0, BgMap::text(0, 28, BGSize::One))
+    .with_bg(1, BgMap::text(0, 29, BGSize::Zero))
+    .check();
+}
+
+#[test]
+#[should_panic(expected = "VRAM layout: the BG2 map's tileset index is out of range")]
+fn test_layout_rejects_bad_tileset_index() {
+  VramLayout::new()
+    .with_tileset(0, Tileset::new_8bpp(0, 256))
+    .with_bg(2, BgMap::affine(MAX_TILESETS, 31, BGSize::One))
+    .check();
+}
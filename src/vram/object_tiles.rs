@@ -0,0 +1,328 @@
+//! Module for allocating object tile memory.
+//!
+//! Objects take their tiles from charblocks 4 and 5 (32k at `0x601_0000`).
+//! An object's `OBJAttr2::tile_id` always counts in 32 byte units (the size of
+//! a 4bpp tile), so an 8bpp tile takes two units and must start on an even
+//! unit.
+//!
+//! How an object's tiles are arranged depends on the mapping mode, set by
+//! `DisplayControlSetting::oam_memory_1d`:
+//!
+//! * **1D:** The tiles of an object are one contiguous run.
+//! * **2D:** The tile memory is a 32x32 grid of units, and an object's tiles
+//!   are a rectangle within that grid.
+//!
+//! In the bitmap modes the bitmap overlaps charblock 4, so only charblock 5
+//! (units 512 and up) can be used.
+
+use super::*;
+use crate::{
+  io::display::{DisplayControlSetting, DisplayMode},
+  sync::Mutex,
+};
+
+/// The number of 32 byte units in the object tile region.
+const UNIT_COUNT: usize = 1024;
+
+/// The number of units per row when using 2D mapping.
+const UNITS_PER_ROW: usize = 32;
+
+/// The first unit that's usable in the bitmap modes.
+const BITMAP_MODE_FIRST_UNIT: usize = 512;
+
+/// Object tile memory, viewed as 4bpp tiles.
+const OBJ_TILES_4BPP: VolBlock<Tile4bpp, Safe, Safe, UNIT_COUNT> =
+  unsafe { VolBlock::new(VRAM_BASE_USIZE + 0x1_0000) };
+
+#[derive(Debug, Clone, Copy)]
+struct AllocatorState {
+  used: [u32; UNIT_COUNT / 32],
+  one_d: bool,
+  bitmap_mode: bool,
+}
+impl AllocatorState {
+  fn is_used(&self, unit: usize) -> bool {
+    self.used[unit / 32] & (1 << (unit % 32)) != 0
+  }
+
+  fn set_used(&mut self, unit: usize, used: bool) {
+    if used {
+      self.used[unit / 32] |= 1 << (unit % 32);
+    } else {
+      self.used[unit / 32] &= !(1 << (unit % 32));
+    }
+  }
+
+  fn first_unit(&self) -> usize {
+    if self.bitmap_mode {
+      BITMAP_MODE_FIRST_UNIT
+    } else {
+      0
+    }
+  }
+
+  /// Finds the smallest free run that fits, to keep large runs available.
+  fn find_run(&self, count: usize, align: usize) -> Option<usize> {
+    let mut best: Option<(usize, usize)> = None;
+    let mut unit = self.first_unit();
+    while unit < UNIT_COUNT {
+      if self.is_used(unit) {
+        unit += 1;
+        continue;
+      }
+      let run_start = unit;
+      while unit < UNIT_COUNT && !self.is_used(unit) {
+        unit += 1;
+      }
+      let aligned_start = (run_start + align - 1) & !(align - 1);
+      if aligned_start + count <= unit {
+        let slack = unit - aligned_start - count;
+        if best.map(|(_, best_slack)| slack < best_slack).unwrap_or(true) {
+          best = Some((aligned_start, slack));
+        }
+      }
+    }
+    best.map(|(start, _)| start)
+  }
+
+  /// Finds the first free rectangle that fits.
+  fn find_rect(&self, width: usize, height: usize, align: usize) -> Option<usize> {
+    if width > UNITS_PER_ROW {
+      return None;
+    }
+    let first_row = self.first_unit() / UNITS_PER_ROW;
+    let row_count = UNIT_COUNT / UNITS_PER_ROW;
+    for row in first_row..=(row_count.checked_sub(height)?) {
+      for col in (0..=(UNITS_PER_ROW - width)).step_by(align) {
+        let base = row * UNITS_PER_ROW + col;
+        if block_units(base, width, height, false).all(|unit| !self.is_used(unit)) {
+          return Some(base);
+        }
+      }
+    }
+    None
+  }
+
+  /// Finds space for a block of `unit_width` by `height` units and marks it
+  /// used, giving its first unit.
+  fn reserve(&mut self, unit_width: usize, height: usize, align: usize) -> Option<usize> {
+    let base = if self.one_d {
+      self.find_run(unit_width * height, align)?
+    } else {
+      self.find_rect(unit_width, height, align)?
+    };
+    for unit in block_units(base, unit_width, height, self.one_d) {
+      self.set_used(unit, true);
+    }
+    Some(base)
+  }
+
+  /// Marks a block from `reserve` as free again.
+  fn release(&mut self, base: usize, unit_width: usize, height: usize, one_d: bool) {
+    for unit in block_units(base, unit_width, height, one_d) {
+      self.set_used(unit, false);
+    }
+  }
+}
+
+/// Every unit in a block of `unit_width` by `height` units: one run with 1D
+/// mapping, or a rectangle of the grid with 2D mapping.
+fn block_units(
+  base: usize, unit_width: usize, height: usize, one_d: bool,
+) -> impl Iterator<Item = usize> {
+  let row_stride = if one_d { unit_width } else { UNITS_PER_ROW };
+  (0..height).flat_map(move |row| {
+    let row_base = base + row * row_stride;
+    row_base..row_base + unit_width
+  })
+}
+
+/// Allocates object tile memory.
+///
+/// This is safe to keep in a `static`, and handles free their tiles when
+/// they're dropped.
+///
+/// The allocator starts out configured for 1D mapping in a tiled mode, use
+/// `configure` to match your display setting.
+pub struct ObjTileAllocator {
+  state: Mutex<AllocatorState>,
+}
+impl ObjTileAllocator {
+  /// Makes an allocator with all object tile memory free.
+  pub const fn new() -> Self {
+    Self {
+      state: Mutex::new(AllocatorState {
+        used: [0; UNIT_COUNT / 32],
+        one_d: true,
+        bitmap_mode: false,
+      }),
+    }
+  }
+
+  /// Matches the mapping mode and usable region to a display setting.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` (and changes nothing) if any tiles are allocated.
+  pub fn configure(&self, setting: DisplayControlSetting) -> Option<()> {
+    let mut state = self.state.lock();
+    if state.used.iter().any(|&word| word != 0) {
+      return None;
+    }
+    state.one_d = setting.oam_memory_1d();
+    state.bitmap_mode =
+      matches!(setting.mode(), DisplayMode::Mode3 | DisplayMode::Mode4 | DisplayMode::Mode5);
+    Some(())
+  }
+
+  /// Allocates space for a 4bpp object that's `width` by `height` tiles.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` if there's no free space large enough.
+  pub fn alloc_4bpp(&self, width: usize, height: usize) -> Option<ObjTiles<'_>> {
+    self.alloc(width, height, 1)
+  }
+
+  /// Allocates space for an 8bpp object that's `width` by `height` tiles.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` if there's no free space large enough.
+  pub fn alloc_8bpp(&self, width: usize, height: usize) -> Option<ObjTiles<'_>> {
+    self.alloc(width, height, 2)
+  }
+
+  /// The number of free 32 byte units.
+  pub fn free_units(&self) -> usize {
+    let state = self.state.lock();
+    (state.first_unit()..UNIT_COUNT).filter(|&unit| !state.is_used(unit)).count()
+  }
+
+  fn alloc(&self, width: usize, height: usize, units_per_tile: usize) -> Option<ObjTiles<'_>> {
+    if width == 0 || height == 0 {
+      return None;
+    }
+    let mut state = self.state.lock();
+    let one_d = state.one_d;
+    let base = state.reserve(width * units_per_tile, height, units_per_tile)?;
+    Some(ObjTiles { allocator: self, base, width, height, units_per_tile, one_d })
+  }
+}
+
+/// A block of object tile memory, freed when dropped.
+pub struct ObjTiles<'a> {
+  allocator: &'a ObjTileAllocator,
+  base: usize,
+  width: usize,
+  height: usize,
+  units_per_tile: usize,
+  one_d: bool,
+}
+impl<'a> ObjTiles<'a> {
+  /// The tile id to use with `OBJAttr2::with_tile_id`.
+  pub fn tile_id(&self) -> u16 {
+    self.base as u16
+  }
+
+  /// The size of the block in tiles, as `(width, height)`.
+  pub fn size(&self) -> (usize, usize) {
+    (self.width, self.height)
+  }
+
+  /// Writes a 4bpp tile, counting left to right then top to bottom.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` if out of bounds or if this is an 8bpp block.
+  pub fn write_4bpp(&self, index: usize, tile: Tile4bpp) -> Option<()> {
+    if self.units_per_tile != 1 {
+      return None;
+    }
+    let unit = self.tile_unit(index)?;
+    OBJ_TILES_4BPP.index(unit).write(tile);
+    Some(())
+  }
+
+  /// Writes an 8bpp tile, counting left to right then top to bottom.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` if out of bounds or if this is a 4bpp block.
+  pub fn write_8bpp(&self, index: usize, tile: Tile8bpp) -> Option<()> {
+    if self.units_per_tile != 2 {
+      return None;
+    }
+    let unit = self.tile_unit(index)?;
+    unsafe { OBJ_TILES_4BPP.index(unit).cast::<Tile8bpp>().write(tile) };
+    Some(())
+  }
+
+  /// Gets the first unit of the tile given.
+  fn tile_unit(&self, index: usize) -> Option<usize> {
+    if index >= self.width * self.height {
+      None
+    } else if self.one_d {
+      Some(self.base + index * self.units_per_tile)
+    } else {
+      let (col, row) = (index % self.width, index / self.width);
+      Some(self.base + row * UNITS_PER_ROW + col * self.units_per_tile)
+    }
+  }
+}
+impl<'a> Drop for ObjTiles<'a> {
+  fn drop(&mut self) {
+    let unit_width = self.width * self.units_per_tile;
+    self.allocator.state.lock().release(self.base, unit_width, self.height, self.one_d);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn state(one_d: bool, bitmap_mode: bool) -> AllocatorState {
+    AllocatorState { used: [0; UNIT_COUNT / 32], one_d, bitmap_mode }
+  }
+
+  #[test]
+  fn test_1d_best_fit_and_coalescing() {
+    let mut state = state(true, false);
+    assert_eq!(state.reserve(4, 1, 1), Some(0));
+    assert_eq!(state.reserve(3, 1, 1), Some(4));
+    assert_eq!(state.reserve(4, 1, 1), Some(7));
+    // The 3 unit hole is a better fit than the rest of memory.
+    state.release(4, 3, 1, true);
+    assert_eq!(state.reserve(3, 1, 1), Some(4));
+    // Freeing both blocks makes one 7 unit hole.
+    state.release(4, 3, 1, true);
+    state.release(0, 4, 1, true);
+    assert_eq!(state.reserve(7, 1, 1), Some(0));
+    // 8bpp tiles start on an even unit.
+    assert_eq!(state.reserve(2, 1, 2), Some(12));
+    assert_eq!(state.reserve(UNIT_COUNT, 1, 1), None);
+  }
+
+  #[test]
+  fn test_2d_rect_search() {
+    let mut state = state(false, false);
+    assert_eq!(state.reserve(2, 2, 1), Some(0));
+    assert!(state.is_used(33) && !state.is_used(34));
+    // Too wide for the rest of rows 0 and 1.
+    assert_eq!(state.reserve(31, 1, 1), Some(64));
+    assert_eq!(state.reserve(3, 1, 1), Some(2));
+    assert_eq!(state.reserve(2, 1, 2), Some(6));
+    assert_eq!(state.reserve(33, 1, 1), None);
+    state.release(0, 2, 2, false);
+    assert_eq!(state.reserve(2, 2, 1), Some(0));
+  }
+
+  #[test]
+  fn test_bitmap_mode_floor() {
+    let mut one_d = state(true, true);
+    assert_eq!(one_d.reserve(1, 1, 1), Some(BITMAP_MODE_FIRST_UNIT));
+    assert_eq!(one_d.reserve(UNIT_COUNT - BITMAP_MODE_FIRST_UNIT, 1, 1), None);
+    let mut two_d = state(false, true);
+    assert_eq!(two_d.reserve(4, 4, 1), Some(BITMAP_MODE_FIRST_UNIT));
+  }
+}
@@ -0,0 +1,654 @@
+//! Module for a small fixed point 3D renderer for the bitmap modes.
+//!
+//! A triangle goes through these steps on its way to the screen:
+//!
+//! 1. A `Transform` moves its corners into camera space, where the camera is
+//!    at the origin looking down `+z`, with `+x` to the right and `+y` up.
+//! 2. It's clipped against the near plane (`Projection::near`), which leaves
+//!    zero, one, or two triangles.
+//! 3. The `Projection` maps the corners to screen pixels, with 4 bits of
+//!    subpixel precision.
+//! 4. If back face culling is on, it's dropped unless its corners go
+//!    counter-clockwise on screen.
+//! 5. It's filled with one color (flat shading, using the surface's span
+//!    fill) or with its corner shades blended across it (Gouraud shading).
+//!
+//! Everything draws to a [`Surface`], so the same code renders to a Mode 4 or
+//! Mode 5 `BackPage` on the GBA or to a `Framebuffer` in a host test. On the
+//! GBA the fill functions are built as ARM code and placed in IWRAM, which is
+//! much faster than Thumb code running from ROM.
+//!
+//! That only covers the per-pixel work that's part of the fill. The pixel
+//! writes of `Mode3`, `PageSurface` and `BackPage` (and the `Shade`
+//! conversions) are `#[inline(always)]` so they're built into the fill, and
+//! their span fills are ARM code in IWRAM too. Other surfaces, such as a
+//! `TileCanvas` or your own, are called like any other function, so their
+//! writes run from wherever they were placed.
+//!
+//! There's no depth buffer, so triangles are drawn in the order given. To
+//! draw them back to front (the painter's algorithm), collect them in a
+//! `DepthSortedBatch` first.
+//!
+//! ```no_run
+//! let mut buffer = DoubleBuffer::<Mode5>::new();
+//! let spin = Transform::rotation_y(angle).then(&Transform::translation(0, 0, 64 << 12));
+//! let pipeline = Pipeline::new(Projection::new(80, 80, 64, Fx::from_raw(1 << 12)))
+//!   .with_transform(spin)
+//!   .with_shading(Shading::Gouraud);
+//! pipeline.draw_mesh(&mut buffer.back_page(), &CUBE_VERTICES, &CUBE_INDICES);
+//! ```
+//!
+//! Camera space coordinates should stay within 2048 units of the origin, and
+//! `near` should be at least 1/16, or the math can overflow.
+
+use super::{draw::Surface, *};
+use crate::fixed_point::{cos, fx20_12, sin, Fx};
+use core::{cmp::Reverse, mem::swap};
+
+/// A point in 3D.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Vec3 {
+  pub x: fx20_12,
+  pub y: fx20_12,
+  pub z: fx20_12,
+}
+
+impl Vec3 {
+  /// Makes a point.
+  pub const fn new(x: fx20_12, y: fx20_12, z: fx20_12) -> Self {
+    Self { x, y, z }
+  }
+
+  /// Makes a point from whole numbers.
+  pub const fn from_int(x: i32, y: i32, z: i32) -> Self {
+    Self { x: Fx::from_raw(x << 12), y: Fx::from_raw(y << 12), z: Fx::from_raw(z << 12) }
+  }
+}
+
+/// A rotation, scale and translation, as a 3x4 matrix.
+///
+/// A point `p` becomes `rows * (p.x, p.y, p.z, 1)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Transform {
+  pub rows: [[fx20_12; 4]; 3],
+}
+
+const ZERO: fx20_12 = Fx::from_raw(0);
+const ONE: fx20_12 = Fx::from_raw(1 << 12);
+
+impl Transform {
+  /// Leaves points where they are.
+  pub const IDENTITY: Self = Self::scale(ONE);
+
+  /// Moves points by the amount given.
+  pub const fn translation(x: fx20_12, y: fx20_12, z: fx20_12) -> Self {
+    Self { rows: [[ONE, ZERO, ZERO, x], [ZERO, ONE, ZERO, y], [ZERO, ZERO, ONE, z]] }
+  }
+
+  /// Scales points towards or away from the origin.
+  pub const fn scale(s: fx20_12) -> Self {
+    Self { rows: [[s, ZERO, ZERO, ZERO], [ZERO, s, ZERO, ZERO], [ZERO, ZERO, s, ZERO]] }
+  }
+
+  /// Rotates around the x axis, turning `+y` towards `+z`.
+  ///
+  /// Angles are in `fixed_point::sin` units, where `0x10000` is a full turn.
+  pub fn rotation_x(angle: u16) -> Self {
+    let (s, c) = (sin(angle), cos(angle));
+    Self { rows: [[ONE, ZERO, ZERO, ZERO], [ZERO, c, -s, ZERO], [ZERO, s, c, ZERO]] }
+  }
+
+  /// Rotates around the y axis, turning `+z` towards `+x`.
+  pub fn rotation_y(angle: u16) -> Self {
+    let (s, c) = (sin(angle), cos(angle));
+    Self { rows: [[c, ZERO, s, ZERO], [ZERO, ONE, ZERO, ZERO], [-s, ZERO, c, ZERO]] }
+  }
+
+  /// Rotates around the z axis, turning `+x` towards `+y`.
+  pub fn rotation_z(angle: u16) -> Self {
+    let (s, c) = (sin(angle), cos(angle));
+    Self { rows: [[c, -s, ZERO, ZERO], [s, c, ZERO, ZERO], [ZERO, ZERO, ONE, ZERO]] }
+  }
+
+  /// The transform that does this one and then `next`.
+  pub fn then(&self, next: &Transform) -> Transform {
+    let mut rows = [[ZERO; 4]; 3];
+    for (next_row, out_row) in next.rows.iter().zip(rows.iter_mut()) {
+      for (col, out) in out_row.iter_mut().enumerate() {
+        let dot: i64 = next_row[..3]
+          .iter()
+          .zip(self.rows.iter())
+          .map(|(n, row)| n.into_raw() as i64 * row[col].into_raw() as i64)
+          .sum();
+        let offset = if col == 3 { next_row[3].into_raw() } else { 0 };
+        *out = Fx::from_raw((dot >> 12) as i32 + offset);
+      }
+    }
+    Transform { rows }
+  }
+
+  /// Moves a point.
+  pub fn apply(&self, point: Vec3) -> Vec3 {
+    let p = [point.x.into_raw() as i64, point.y.into_raw() as i64, point.z.into_raw() as i64];
+    let row = |row: &[fx20_12; 4]| {
+      let dot: i64 = row[..3].iter().zip(p.iter()).map(|(m, v)| m.into_raw() as i64 * v).sum();
+      Fx::from_raw((dot >> 12) as i32 + row[3].into_raw())
+    };
+    Vec3 { x: row(&self.rows[0]), y: row(&self.rows[1]), z: row(&self.rows[2]) }
+  }
+}
+
+/// Maps camera space to the screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Projection {
+  /// How far the eye is from the screen, in pixels. Half the screen width
+  /// gives a 90 degree field of view across.
+  pub focal_length: i32,
+  /// The pixel that the `z` axis goes through.
+  pub center: (i32, i32),
+  /// Anything closer than this is clipped away.
+  pub near: fx20_12,
+}
+
+impl Projection {
+  /// Makes a projection.
+  pub const fn new(focal_length: i32, center_x: i32, center_y: i32, near: fx20_12) -> Self {
+    Self { focal_length, center: (center_x, center_y), near }
+  }
+
+  /// The screen position of a camera space point, in pixels with 4 fraction
+  /// bits.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` if the point is closer than the near plane.
+  pub fn project(&self, point: Vec3) -> Option<(i32, i32)> {
+    let z = point.z.into_raw();
+    if z < self.near.into_raw() || z <= 0 {
+      return None;
+    }
+    let scale = ((self.focal_length as i64) << 28) / z as i64;
+    let x = ((point.x.into_raw() as i64 * scale) >> 24) as i32;
+    let y = ((point.y.into_raw() as i64 * scale) >> 24) as i32;
+    Some((self.center.0 * 16 + x, self.center.1 * 16 - y))
+  }
+}
+
+/// A pixel type that can be blended across a triangle for Gouraud shading.
+pub trait Shade: Copy {
+  /// Splits the pixel into (up to) three channels.
+  fn to_channels(self) -> [i32; 3];
+
+  /// Puts blended channels back together, clamping them to range.
+  fn from_channels(channels: [i32; 3]) -> Self;
+}
+
+/// Palette indexes blend as numbers, so Gouraud shading in Mode 4 needs a
+/// palette with a ramp of colors.
+impl Shade for u8 {
+  #[inline(always)]
+  fn to_channels(self) -> [i32; 3] {
+    [self as i32, 0, 0]
+  }
+  #[inline(always)]
+  fn from_channels(channels: [i32; 3]) -> Self {
+    channels[0].clamp(0, 255) as u8
+  }
+}
+
+impl Shade for Color {
+  #[inline(always)]
+  fn to_channels(self) -> [i32; 3] {
+    [self.red() as i32, self.green() as i32, self.blue() as i32]
+  }
+  #[inline(always)]
+  fn from_channels(channels: [i32; 3]) -> Self {
+    let [r, g, b] = channels.map(|c| c.clamp(0, 31) as u16);
+    Color::from_rgb(r, g, b)
+  }
+}
+
+/// A corner of a triangle.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Vertex<P> {
+  pub position: Vec3,
+  /// The corner's color. Flat shading uses the first corner's.
+  pub shade: P,
+}
+
+impl<P> Vertex<P> {
+  /// Makes a corner.
+  pub const fn new(position: Vec3, shade: P) -> Self {
+    Self { position, shade }
+  }
+}
+
+/// How a triangle is filled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shading {
+  /// In the first corner's shade.
+  Flat,
+  /// With the corner shades blended across the triangle.
+  Gouraud,
+}
+
+/// A triangle that's ready to fill.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScreenTriangle<P> {
+  /// The corners, in pixels with 4 fraction bits.
+  pub points: [(i32, i32); 3],
+  /// The shade for flat shading.
+  pub pixel: P,
+  /// The average camera space depth of the corners.
+  pub depth: fx20_12,
+  /// How the triangle is filled.
+  pub shading: Shading,
+  /// The corner shades, kept as channels so that the shades of corners made
+  /// by clipping don't get rounded.
+  channels: [[i32; 3]; 3],
+}
+
+impl<P: Shade> ScreenTriangle<P> {
+  /// Fills the triangle.
+  pub fn draw<S: Surface<Pixel = P> + ?Sized>(&self, surface: &mut S) {
+    match self.shading {
+      Shading::Flat => fill_triangle_flat(surface, self.points, self.pixel),
+      Shading::Gouraud => fill_gouraud(surface, self.points, self.channels),
+    }
+  }
+}
+
+/// A camera space corner while it's being clipped.
+#[derive(Debug, Clone, Copy)]
+struct ClipVertex {
+  position: Vec3,
+  channels: [i32; 3],
+}
+
+/// The point where the edge from `a` to `b` crosses the near plane.
+fn clip_edge(a: ClipVertex, b: ClipVertex, near: i32) -> ClipVertex {
+  let (za, zb) = (a.position.z.into_raw() as i64, b.position.z.into_raw() as i64);
+  let t = ((near as i64 - za) << 16) / (zb - za);
+  let lerp = |from: i32, to: i32| (from as i64 + (((to as i64 - from as i64) * t) >> 16)) as i32;
+  let (pa, pb) = (a.position, b.position);
+  let position = Vec3::new(
+    Fx::from_raw(lerp(pa.x.into_raw(), pb.x.into_raw())),
+    Fx::from_raw(lerp(pa.y.into_raw(), pb.y.into_raw())),
+    Fx::from_raw(near),
+  );
+  let mut channels = a.channels;
+  for (c, &to) in channels.iter_mut().zip(b.channels.iter()) {
+    *c = lerp(*c, to);
+  }
+  ClipVertex { position, channels }
+}
+
+/// Takes triangles from model space to the screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pipeline {
+  /// From model space to camera space.
+  pub transform: Transform,
+  /// From camera space to the screen.
+  pub projection: Projection,
+  /// If triangles that go clockwise on screen are dropped.
+  pub cull_back_faces: bool,
+  /// How triangles are filled.
+  pub shading: Shading,
+}
+
+impl Pipeline {
+  /// Makes a pipeline with the identity transform, back face culling, and
+  /// flat shading.
+  pub const fn new(projection: Projection) -> Self {
+    Self {
+      transform: Transform::IDENTITY,
+      projection,
+      cull_back_faces: true,
+      shading: Shading::Flat,
+    }
+  }
+
+  /// Sets the model to camera space transform.
+  pub const fn with_transform(self, transform: Transform) -> Self {
+    Self { transform, ..self }
+  }
+
+  /// Sets if back faces are dropped.
+  pub const fn with_back_face_culling(self, cull_back_faces: bool) -> Self {
+    Self { cull_back_faces, ..self }
+  }
+
+  /// Sets how triangles are filled.
+  pub const fn with_shading(self, shading: Shading) -> Self {
+    Self { shading, ..self }
+  }
+
+  /// Transforms, clips, projects and culls a triangle, and passes each
+  /// triangle that's left (up to two) to `emit`.
+  pub fn prepare<P: Shade>(
+    &self, triangle: [Vertex<P>; 3], mut emit: impl FnMut(ScreenTriangle<P>),
+  ) {
+    let near = self.projection.near.into_raw();
+    let camera = triangle.map(|v| ClipVertex {
+      position: self.transform.apply(v.position),
+      channels: v.shade.to_channels(),
+    });
+    let mut polygon = [camera[0]; 4];
+    let mut count = 0;
+    for (i, &a) in camera.iter().enumerate() {
+      let b = camera[(i + 1) % 3];
+      let (a_inside, b_inside) = (a.position.z.into_raw() >= near, b.position.z.into_raw() >= near);
+      if a_inside {
+        polygon[count] = a;
+        count += 1;
+      }
+      if a_inside != b_inside {
+        polygon[count] = clip_edge(a, b, near);
+        count += 1;
+      }
+    }
+    for i in 1..count.saturating_sub(1) {
+      let corners = [polygon[0], polygon[i], polygon[i + 1]];
+      let mut points = [(0, 0); 3];
+      for (point, corner) in points.iter_mut().zip(corners.iter()) {
+        match self.projection.project(corner.position) {
+          Some(p) => *point = p,
+          None => return,
+        }
+      }
+      let area = signed_area(points);
+      if area == 0 || (self.cull_back_faces && area > 0) {
+        continue;
+      }
+      let depth_sum: i32 = corners.iter().map(|c| c.position.z.into_raw()).sum();
+      emit(ScreenTriangle {
+        points,
+        pixel: triangle[0].shade,
+        depth: Fx::from_raw(crate::bios::div(depth_sum, 3)),
+        shading: self.shading,
+        channels: corners.map(|c| c.channels),
+      });
+    }
+  }
+
+  /// Draws a triangle.
+  pub fn draw_triangle<S: Surface + ?Sized>(&self, surface: &mut S, triangle: [Vertex<S::Pixel>; 3])
+  where
+    S::Pixel: Shade,
+  {
+    self.prepare(triangle, |t| t.draw(surface));
+  }
+
+  /// Draws triangles given as corners of a vertex list. Triangles with an
+  /// index past the end of the list are skipped.
+  pub fn draw_mesh<S: Surface + ?Sized>(
+    &self, surface: &mut S, vertices: &[Vertex<S::Pixel>], indices: &[[u16; 3]],
+  ) where
+    S::Pixel: Shade,
+  {
+    for &[a, b, c] in indices {
+      let corner = |i: u16| vertices.get(i as usize).copied();
+      if let (Some(a), Some(b), Some(c)) = (corner(a), corner(b), corner(c)) {
+        self.draw_triangle(surface, [a, b, c]);
+      }
+    }
+  }
+}
+
+/// Up to `N` triangles, drawn back to front.
+///
+/// Each triangle holds 60 to 70 bytes, so keep big batches in a `static`
+/// rather than on the stack.
+#[derive(Debug, Clone)]
+pub struct DepthSortedBatch<P, const N: usize> {
+  triangles: [Option<ScreenTriangle<P>>; N],
+  count: usize,
+}
+
+impl<P: Shade, const N: usize> DepthSortedBatch<P, N> {
+  /// Makes an empty batch.
+  pub const fn new() -> Self {
+    Self { triangles: [None; N], count: 0 }
+  }
+
+  /// The number of triangles in the batch.
+  pub fn len(&self) -> usize {
+    self.count
+  }
+
+  /// If the batch is empty.
+  pub fn is_empty(&self) -> bool {
+    self.count == 0
+  }
+
+  /// Removes every triangle.
+  pub fn clear(&mut self) {
+    self.count = 0;
+  }
+
+  /// Adds a triangle that's already been through a pipeline.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` if the batch is full.
+  pub fn push(&mut self, triangle: ScreenTriangle<P>) -> Option<()> {
+    *self.triangles.get_mut(self.count)? = Some(triangle);
+    self.count += 1;
+    Some(())
+  }
+
+  /// Puts a triangle through a pipeline and adds what's left of it.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` if the batch filled up, in which case the triangle might
+  /// only be partly added.
+  pub fn add(&mut self, pipeline: &Pipeline, triangle: [Vertex<P>; 3]) -> Option<()> {
+    let mut result = Some(());
+    pipeline.prepare(triangle, |t| {
+      if self.push(t).is_none() {
+        result = None;
+      }
+    });
+    result
+  }
+
+  /// Draws every triangle, farthest first, and empties the batch.
+  pub fn draw<S: Surface<Pixel = P> + ?Sized>(&mut self, surface: &mut S) {
+    let triangles = &mut self.triangles[..self.count];
+    triangles.sort_unstable_by_key(|t| Reverse(t.map(|t| t.depth)));
+    for triangle in triangles.iter().flatten() {
+      triangle.draw(surface);
+    }
+    self.count = 0;
+  }
+}
+
+impl<P: Shade, const N: usize> Default for DepthSortedBatch<P, N> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// Twice the signed area of a screen triangle. Negative when the corners go
+/// counter-clockwise on screen.
+fn signed_area(points: [(i32, i32); 3]) -> i64 {
+  let [(x0, y0), (x1, y1), (x2, y2)] = points;
+  (x1 - x0) as i64 * (y2 - y0) as i64 - (x2 - x0) as i64 * (y1 - y0) as i64
+}
+
+/// One edge of a triangle, from its top corner to its bottom corner.
+struct Edge {
+  /// The top corner's column, with 16 fraction bits.
+  x: i64,
+  /// The top corner's row, with 4 fraction bits.
+  y: i32,
+  /// Columns moved per row, with 16 fraction bits.
+  step: i64,
+}
+
+impl Edge {
+  fn new(top: (i32, i32), bottom: (i32, i32)) -> Self {
+    let dy = bottom.1 - top.1;
+    let step = if dy > 0 { ((bottom.0 - top.0) as i64 * 0x10000) / dy as i64 } else { 0 };
+    Self { x: (top.0 as i64) << 12, y: top.1, step }
+  }
+
+  /// The column where the edge crosses the middle of a row.
+  fn x_at(&self, row: i32) -> i64 {
+    self.x + ((self.step * (row * 16 + 8 - self.y) as i64) >> 4)
+  }
+}
+
+/// The first row whose middle is at or below `y` (with 4 fraction bits).
+fn first_row(y: i32) -> i32 {
+  (y + 7) >> 4
+}
+
+/// The first column whose middle is at or right of `x` (with 16 fraction
+/// bits).
+fn first_col(x: i64) -> i32 {
+  ((x + 0x7FFF) >> 16) as i32
+}
+
+/// Gives the span of each row of a triangle as `(row, col_start, col_end)`,
+/// clipped to the surface size.
+///
+/// A pixel is drawn when its middle is inside the triangle. Middles exactly
+/// on a top or left edge count as inside and ones on a bottom or right edge
+/// don't, so triangles that share an edge never both draw a pixel.
+#[inline(always)]
+fn for_each_span(
+  points: [(i32, i32); 3], width: usize, height: usize, mut span: impl FnMut(usize, usize, usize),
+) {
+  let [mut a, mut b, mut c] = points;
+  if a.1 > b.1 {
+    swap(&mut a, &mut b);
+  }
+  if b.1 > c.1 {
+    swap(&mut b, &mut c);
+  }
+  if a.1 > b.1 {
+    swap(&mut a, &mut b);
+  }
+  // Positive when the middle corner is left of the long edge.
+  let side = signed_area([a, c, b]);
+  if side == 0 {
+    return;
+  }
+  let long = Edge::new(a, c);
+  let upper = Edge::new(a, b);
+  let lower = Edge::new(b, c);
+  let middle = first_row(b.1);
+  for row in first_row(a.1).max(0)..first_row(c.1).min(height as i32) {
+    let short = if row < middle { &upper } else { &lower };
+    let (left, right) = if side > 0 { (short, &long) } else { (&long, short) };
+    let start = first_col(left.x_at(row)).max(0);
+    let end = first_col(right.x_at(row)).min(width as i32);
+    if start < end {
+      span(row as usize, start as usize, end as usize);
+    }
+  }
+}
+
+/// Fills a triangle with one pixel value.
+///
+/// The corners are in pixels with 4 fraction bits.
+#[cfg_attr(target_arch = "arm", link_section = ".text_iwram", instruction_set(arm::a32))]
+pub fn fill_triangle_flat<S: Surface + ?Sized>(
+  surface: &mut S, points: [(i32, i32); 3], pixel: S::Pixel,
+) {
+  let (width, height) = (surface.width(), surface.height());
+  for_each_span(points, width, height, |row, start, end| surface.fill_span(row, start, end, pixel));
+}
+
+/// Fills a triangle, blending the corner shades across it.
+///
+/// The corners are in pixels with 4 fraction bits.
+pub fn fill_triangle_gouraud<S: Surface + ?Sized>(
+  surface: &mut S, points: [(i32, i32); 3], shades: [S::Pixel; 3],
+) where
+  S::Pixel: Shade,
+{
+  fill_gouraud(surface, points, shades.map(Shade::to_channels));
+}
+
+/// As `fill_triangle_gouraud`, with the shades already split into channels.
+#[cfg_attr(target_arch = "arm", link_section = ".text_iwram", instruction_set(arm::a32))]
+fn fill_gouraud<S: Surface + ?Sized>(
+  surface: &mut S, points: [(i32, i32); 3], channels: [[i32; 3]; 3],
+) where
+  S::Pixel: Shade,
+{
+  let area = signed_area(points);
+  if area == 0 {
+    return;
+  }
+  let [(x0, y0), (x1, y1), (x2, y2)] = points;
+  // Each channel is a plane over the triangle: its value at the first corner
+  // and how much it changes per pixel across and down, with 16 fraction bits.
+  let plane = |k: usize| {
+    let c0 = (channels[0][k] as i64) << 16;
+    let (d1, d2) = (((channels[1][k] as i64) << 16) - c0, ((channels[2][k] as i64) << 16) - c0);
+    let dx = (d1 * (y2 - y0) as i64 - d2 * (y1 - y0) as i64) * 16 / area;
+    let dy = (d2 * (x1 - x0) as i64 - d1 * (x2 - x0) as i64) * 16 / area;
+    (c0, dx, dy)
+  };
+  let planes = [plane(0), plane(1), plane(2)];
+  let (width, height) = (surface.width(), surface.height());
+  for_each_span(points, width, height, |row, start, end| {
+    let px = (start as i64) * 16 + 8 - x0 as i64;
+    let py = (row as i64) * 16 + 8 - y0 as i64;
+    let mut values = planes.map(|(c0, dx, dy)| c0 + ((dx * px + dy * py) >> 4));
+    for col in start..end {
+      let pixel = S::Pixel::from_channels(values.map(|v| ((v + 0x8000) >> 16) as i32));
+      surface.write_pixel(col, row, pixel);
+      for (value, &(_, dx, _)) in values.iter_mut().zip(planes.iter()) {
+        *value += dx;
+      }
+    }
+  });
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::vram::draw::Framebuffer;
+
+  #[test]
+  fn test_shared_edges_cover_once() {
+    let mut pixels = [0_u8; 16];
+    let mut fb = Framebuffer::new(&mut pixels, 4);
+    fill_triangle_flat(&mut fb, [(0, 0), (64, 64), (64, 0)], 1);
+    fill_triangle_flat(&mut fb, [(0, 0), (0, 64), (64, 64)], 2);
+    assert_eq!(fb.pixels(), &[1, 1, 1, 1, 2, 1, 1, 1, 2, 2, 1, 1, 2, 2, 2, 1]);
+    fill_triangle_gouraud(&mut fb, [(0, 0), (0, 64), (64, 0)], [0, 64, 128]);
+    assert_eq!(&fb.pixels()[..3], &[24, 56, 88]);
+    assert_eq!(fb.pixels()[4], 40);
+  }
+
+  #[test]
+  fn test_pipeline_culls_and_clips() {
+    let mut pixels = [0_u8; 64];
+    let mut fb = Framebuffer::new(&mut pixels, 8);
+    let pipeline = Pipeline::new(Projection::new(4, 4, 4, ONE));
+    let corner = |x, y, z| Vertex::new(Vec3::from_int(x, y, z), 1_u8);
+    let front = [corner(-4, 4, 4), corner(-4, -4, 4), corner(4, 4, 4)];
+    let back = [front[0], front[2], front[1]];
+    let mut count = 0;
+    pipeline.prepare(back, |_| count += 1);
+    assert_eq!(count, 0);
+    pipeline.prepare(front, |_| count += 1);
+    assert_eq!(count, 1);
+    let crossing = [corner(-4, 4, 8), corner(0, -4, -4), corner(4, 4, 8)];
+    pipeline.prepare(crossing, |_| count += 1);
+    assert_eq!(count, 3);
+    pipeline.draw_triangle(&mut fb, front);
+    assert_eq!(fb.pixels()[0], 1);
+    assert_eq!(fb.pixels()[6 * 8], 1);
+    assert_eq!(fb.pixels()[6], 1);
+    // Pixels with their middle on the long edge belong to the triangle across it.
+    assert_eq!(fb.pixels()[7], 0);
+    assert_eq!(fb.pixels()[63], 0);
+  }
+}
@@ -0,0 +1,215 @@
+//! Module for streaming large tile maps through a text background.
+//!
+//! A text background can hold at most 64x64 tiles (`BGSize::Three`), but the
+//! screen only ever shows 31x21 of them at once (30x20 plus one more on each
+//! axis when the scroll isn't tile aligned). So a map of any size can be shown
+//! by treating the background's screenblocks as a ring: map tile `(x, y)` is
+//! always placed at ring position `(x % ring_width, y % ring_height)`, and as
+//! the camera moves only the newly exposed row or column of tiles is written.
+//!
+//! Because the ring position is just the map position wrapped, the scroll
+//! registers can be set to the camera position directly (the hardware wraps it
+//! the same way).
+
+use super::*;
+use crate::io::background::{BGSize, Background};
+
+/// The number of tile columns that can be visible at once.
+const VISIBLE_COLS: i32 = 31;
+
+/// The number of tile rows that can be visible at once.
+const VISIBLE_ROWS: i32 = 21;
+
+/// A tile map larger than a background, streamed into VRAM as it scrolls.
+///
+/// The map data is a row-major slice of entries, which will usually live in
+/// ROM. Positions outside of the map show the `fill` entry.
+///
+/// All tile writes go straight to VRAM, so move the camera during VBlank (at
+/// the same time as you write the scroll registers) to avoid tearing.
+#[derive(Debug, Clone)]
+pub struct StreamingBackground<'a> {
+  map: &'a [TextScreenblockEntry],
+  map_width: usize,
+  map_height: usize,
+  screen_base_block: usize,
+  size: BGSize,
+  fill: TextScreenblockEntry,
+  camera_x: i32,
+  camera_y: i32,
+}
+
+impl<'a> StreamingBackground<'a> {
+  /// Makes a streaming background over the map given.
+  ///
+  /// * `map_width` is the width of the map in tiles, the height is however
+  ///   many full rows the slice holds.
+  /// * `screen_base_block` and `size` must match the values in the
+  ///   background's control setting.
+  ///
+  /// Nothing is written to VRAM until you call `load_at`.
+  ///
+  /// ## Panics
+  ///
+  /// If `map_width` is 0, or if the screenblocks for `size` starting at
+  /// `screen_base_block` don't fit in VRAM.
+  pub fn new(
+    map: &'a [TextScreenblockEntry], map_width: usize, screen_base_block: usize, size: BGSize,
+  ) -> Self {
+    assert!(map_width > 0, "map width must not be 0");
+    let block_count = match size {
+      BGSize::Zero => 1,
+      BGSize::One | BGSize::Two => 2,
+      BGSize::Three => 4,
+    };
+    assert!(screen_base_block + block_count <= SCREEN_BASE_BLOCKS.len());
+    Self {
+      map,
+      map_width,
+      map_height: map.len() / map_width,
+      screen_base_block,
+      size,
+      fill: TextScreenblockEntry::new(),
+      camera_x: 0,
+      camera_y: 0,
+    }
+  }
+
+  /// Sets the entry shown for positions outside of the map.
+  pub fn with_fill(self, fill: TextScreenblockEntry) -> Self {
+    Self { fill, ..self }
+  }
+
+  /// The current camera position in pixels (the top-left of the screen).
+  pub fn camera(&self) -> (i32, i32) {
+    (self.camera_x, self.camera_y)
+  }
+
+  /// The values to use for the background's scroll registers.
+  pub fn scroll_offsets(&self) -> (u16, u16) {
+    let (ring_width, ring_height) = self.ring_size();
+    (
+      (self.camera_x & (ring_width * 8 - 1)) as u16,
+      (self.camera_y & (ring_height * 8 - 1)) as u16,
+    )
+  }
+
+  /// Copies the scroll offsets into a background handle.
+  ///
+  /// The handle still has to be flushed to reach the hardware.
+  pub fn apply_scroll<const N: usize>(&self, bg: &mut Background<N>) {
+    let (x, y) = self.scroll_offsets();
+    bg.set_scroll(x, y);
+  }
+
+  /// Places the camera and writes every visible tile.
+  ///
+  /// Use this for the first frame, or after a teleport.
+  pub fn load_at(&mut self, x: i32, y: i32) {
+    self.place_camera(x, y, true, Self::write_tile);
+  }
+
+  /// Moves the camera, writing only the tiles that were newly exposed.
+  ///
+  /// Moving more than a screen in one step works, it just writes more tiles.
+  pub fn move_camera_to(&mut self, x: i32, y: i32) {
+    self.place_camera(x, y, false, Self::write_tile);
+  }
+
+  /// Moves the camera by the amount given, see `move_camera_to`.
+  pub fn move_camera_by(&mut self, dx: i32, dy: i32) {
+    self.move_camera_to(self.camera_x + dx, self.camera_y + dy)
+  }
+
+  /// The ring size in tiles.
+  fn ring_size(&self) -> (i32, i32) {
+    match self.size {
+      BGSize::Zero => (32, 32),
+      BGSize::One => (64, 32),
+      BGSize::Two => (32, 64),
+      BGSize::Three => (64, 64),
+    }
+  }
+
+  /// Moves the camera, calling `write` with the map position of each tile
+  /// that has to be written: every visible tile if `reload` is set or the
+  /// camera moved too far, otherwise only the newly exposed ones.
+  fn place_camera(&mut self, x: i32, y: i32, reload: bool, mut write: impl FnMut(&Self, i32, i32)) {
+    let (old_col, old_row) = (self.camera_x >> 3, self.camera_y >> 3);
+    let (new_col, new_row) = (x >> 3, y >> 3);
+    self.camera_x = x;
+    self.camera_y = y;
+    let far =
+      (new_col - old_col).abs() >= VISIBLE_COLS || (new_row - old_row).abs() >= VISIBLE_ROWS;
+    for row in new_row..new_row + VISIBLE_ROWS {
+      let row_exposed = reload || far || row < old_row || row >= old_row + VISIBLE_ROWS;
+      for col in new_col..new_col + VISIBLE_COLS {
+        if row_exposed || col < old_col || col >= old_col + VISIBLE_COLS {
+          write(self, col, row);
+        }
+      }
+    }
+  }
+
+  /// The screenblock (counted from `screen_base_block`) and entry index that
+  /// a map tile position goes in.
+  fn ring_slot(&self, col: i32, row: i32) -> (usize, usize) {
+    let (ring_width, ring_height) = self.ring_size();
+    let ring_col = (col & (ring_width - 1)) as usize;
+    let ring_row = (row & (ring_height - 1)) as usize;
+    let block = match self.size {
+      BGSize::Zero => 0,
+      BGSize::One => ring_col / 32,
+      BGSize::Two => ring_row / 32,
+      BGSize::Three => (ring_row / 32) * 2 + ring_col / 32,
+    };
+    (block, (ring_row % 32) * 32 + ring_col % 32)
+  }
+
+  /// Gets the map entry at a tile position, or the fill entry.
+  fn map_entry(&self, col: i32, row: i32) -> TextScreenblockEntry {
+    if col >= 0 && row >= 0 && (col as usize) < self.map_width && (row as usize) < self.map_height
+    {
+      self.map[row as usize * self.map_width + col as usize]
+    } else {
+      self.fill
+    }
+  }
+
+  /// Writes one map tile into its ring position.
+  fn write_tile(&self, col: i32, row: i32) {
+    let (block, index) = self.ring_slot(col, row);
+    get_screen_block(self.screen_base_block + block).index(index).write(self.map_entry(col, row));
+  }
+}
+
+#[test]
+fn test_ring_across_seams() {
+  // Which map tile each ring cell holds, standing in for VRAM.
+  let mut ring = [[None; 32 * 32]; 2];
+  let mut check = |bg: &mut StreamingBackground, x: i32, y: i32| {
+    bg.place_camera(x, y, false, |bg, col, row| {
+      let (block, index) = bg.ring_slot(col, row);
+      ring[block][index] = Some((col, row));
+    });
+    let (col_start, row_start) = (x >> 3, y >> 3);
+    for row in row_start..row_start + VISIBLE_ROWS {
+      for col in col_start..col_start + VISIBLE_COLS {
+        let (block, index) = bg.ring_slot(col, row);
+        assert_eq!(ring[block][index], Some((col, row)));
+      }
+    }
+  };
+  let mut bg = StreamingBackground::new(&[], 1, 0, BGSize::One);
+  bg.camera_x = -1000;
+  check(&mut bg, 0, 0);
+  // Right and down across the 64 wide, 32 tall ring, then back past 0.
+  for step in 1..80 {
+    check(&mut bg, step * 7, step * 5);
+  }
+  for step in 1..100 {
+    check(&mut bg, 553 - step * 9, 395 - step * 6);
+  }
+  assert_eq!(bg.ring_slot(-1, -1), (1, 31 * 32 + 31));
+  assert_eq!(bg.scroll_offsets(), ((-338_i32 & 511) as u16, (-199_i32 & 255) as u16));
+}
@@ -1,6 +1,7 @@
 //! Module for tiled mode types and operations.
 
 use super::*;
+use crate::io::background::BGSize;
 
 newtype! {
   /// A screenblock entry for use in Text mode.
@@ -27,7 +28,187 @@ newtype! {
   TextScreenblock, [TextScreenblockEntry; 32 * 32], no frills
 }
 
+/// A text background's whole map, which is 1, 2 or 4 screenblocks.
+///
+/// The map is `W` by `H` tiles and must match one of the `BGSize` values, use
+/// the aliases below. Positions are `(x, y)` in tiles across the whole map, so
+/// with a 64 tile wide map `x` values of 32 and up are in the next
+/// screenblock.
+///
+/// All accesses are bounds checked. Methods that write more than one entry
+/// check the whole area first, and write nothing if any of it is out of
+/// bounds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextMap<const W: usize, const H: usize> {
+  screen_base_block: usize,
+}
+
+/// A 32x32 tile text map (`BGSize::Zero`).
+pub type TextMap32x32 = TextMap<32, 32>;
+/// A 64x32 tile text map (`BGSize::One`).
+pub type TextMap64x32 = TextMap<64, 32>;
+/// A 32x64 tile text map (`BGSize::Two`).
+pub type TextMap32x64 = TextMap<32, 64>;
+/// A 64x64 tile text map (`BGSize::Three`).
+pub type TextMap64x64 = TextMap<64, 64>;
+
+impl<const W: usize, const H: usize> TextMap<W, H> {
+  const VALID_SIZE: () =
+    assert!((W == 32 || W == 64) && (H == 32 || H == 64), "text maps must be 32 or 64 tiles");
+
+  /// The width of the map in tiles.
+  pub const WIDTH: usize = W;
+
+  /// The height of the map in tiles.
+  pub const HEIGHT: usize = H;
+
+  /// The number of screenblocks the map uses.
+  pub const SCREENBLOCK_COUNT: usize = (W / 32) * (H / 32);
+
+  /// The `BGSize` to put in the background's control setting.
+  pub const BG_SIZE: BGSize = match (W, H) {
+    (32, 32) => BGSize::Zero,
+    (64, 32) => BGSize::One,
+    (32, 64) => BGSize::Two,
+    _ => BGSize::Three,
+  };
+
+  /// Makes a view of the map starting at a screenblock.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` if the map's screenblocks don't all fit in VRAM.
+  pub fn new(screen_base_block: usize) -> Option<Self> {
+    #[allow(clippy::let_unit_value)]
+    let _ = Self::VALID_SIZE;
+    if screen_base_block + Self::SCREENBLOCK_COUNT <= SCREEN_BASE_BLOCKS.len() {
+      Some(Self { screen_base_block })
+    } else {
+      None
+    }
+  }
+
+  /// The first screenblock of the map.
+  pub const fn screen_base_block(&self) -> usize {
+    self.screen_base_block
+  }
+
+  /// The screenblock (counted from the first) and the index within it for a
+  /// position, which must be in bounds.
+  const fn locate(x: usize, y: usize) -> (usize, usize) {
+    ((y / 32) * (W / 32) + x / 32, (y % 32) * 32 + x % 32)
+  }
+
+  /// The address of the entry at a position.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` if the position is out of bounds.
+  pub fn get(&self, x: usize, y: usize) -> Option<VolAddress<TextScreenblockEntry, Safe, Safe>> {
+    if x < W && y < H {
+      let (block, index) = Self::locate(x, y);
+      Some(get_screen_block(self.screen_base_block + block).index(index))
+    } else {
+      None
+    }
+  }
+
+  /// Reads the entry at a position.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` if the position is out of bounds.
+  pub fn read(&self, x: usize, y: usize) -> Option<TextScreenblockEntry> {
+    self.get(x, y).map(|va| va.read())
+  }
+
+  /// Writes the entry at a position.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` if the position is out of bounds.
+  pub fn write(&self, x: usize, y: usize, entry: TextScreenblockEntry) -> Option<()> {
+    self.get(x, y).map(|va| va.write(entry))
+  }
+
+  /// Sets every entry in the map.
+  pub fn fill(&self, entry: TextScreenblockEntry) {
+    for block in 0..Self::SCREENBLOCK_COUNT {
+      get_screen_block(self.screen_base_block + block).iter().for_each(|va| va.write(entry));
+    }
+  }
+
+  /// Sets every entry in a rectangle.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` (and writes nothing) if the rectangle goes out of bounds.
+  pub fn fill_rect(
+    &self, x: usize, y: usize, width: usize, height: usize, entry: TextScreenblockEntry,
+  ) -> Option<()> {
+    if !rect_fits(x, y, width, height, (W, H)) {
+      return None;
+    }
+    for row in y..y + height {
+      for col in x..x + width {
+        self.write(col, row, entry)?;
+      }
+    }
+    Some(())
+  }
+
+  /// Copies a row-major rectangle of entries `width` tiles wide into the map,
+  /// with its top left at `(x, y)`.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` (and writes nothing) if `width` is 0, the entries aren't a
+  /// whole number of rows, or the rectangle goes out of bounds.
+  pub fn copy_rect(
+    &self, x: usize, y: usize, width: usize, entries: &[TextScreenblockEntry],
+  ) -> Option<()> {
+    if width == 0 || entries.len() % width != 0 {
+      return None;
+    }
+    if !rect_fits(x, y, width, entries.len() / width, (W, H)) {
+      return None;
+    }
+    for (i, row) in entries.chunks(width).enumerate() {
+      for (j, &entry) in row.iter().enumerate() {
+        self.write(x + j, y + i, entry)?;
+      }
+    }
+    Some(())
+  }
+
+  /// Writes entries going right from `(x, y)`.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` (and writes nothing) if the row goes out of bounds.
+  pub fn write_row(&self, x: usize, y: usize, entries: &[TextScreenblockEntry]) -> Option<()> {
+    self.copy_rect(x, y, entries.len().max(1), entries)
+  }
+
+  /// Writes entries going down from `(x, y)`.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` (and writes nothing) if the column goes out of bounds.
+  pub fn write_col(&self, x: usize, y: usize, entries: &[TextScreenblockEntry]) -> Option<()> {
+    self.copy_rect(x, y, 1, entries)
+  }
+}
+
 #[test]
 pub fn test_text_screen_block_size() {
   assert_eq!(core::mem::size_of::<TextScreenblock>(), 0x800);
 }
+
+#[test]
+pub fn test_text_map_locate() {
+  assert_eq!(TextMap64x32::locate(40, 10), (1, 10 * 32 + 8));
+  assert_eq!(TextMap32x64::locate(5, 40), (1, 8 * 32 + 5));
+  assert_eq!(TextMap64x64::locate(33, 33), (3, 32 + 1));
+  assert!(TextMap64x64::new(29).is_none());
+}
@@ -0,0 +1,484 @@
+//! Module for working with the pixels of individual tiles.
+//!
+//! A `Tile4bpp` holds one row per `u32`, with the leftmost pixel in the low
+//! nibble. A `Tile8bpp` holds one row per two `u32` values, with the leftmost
+//! pixel in the low byte of the first one. In both cases palette index 0 is
+//! transparent.
+//!
+//! VRAM can't take byte writes, so changing one pixel of a tile that's
+//! already in VRAM means reading the word that holds it and writing the whole
+//! word back. The `TileEditor4bpp` and `TileEditor8bpp` types do that for you.
+//! A `TileCanvas` puts a rectangle of 4bpp tiles together into one surface
+//! for drawing.
+
+use super::{text::TextMap, *};
+
+impl Tile4bpp {
+  /// Makes a tile from rows of palette indexes (0 to 15, higher bits are
+  /// ignored).
+  pub const fn from_rows(rows: [[u8; 8]; 8]) -> Self {
+    let mut words = [0; 8];
+    let mut y = 0;
+    while y < 8 {
+      words[y] = pack_4bpp_row(rows[y]);
+      y += 1;
+    }
+    Self(words)
+  }
+
+  /// The palette index of a pixel.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` if `x` or `y` is 8 or more.
+  pub const fn get_pixel(&self, x: usize, y: usize) -> Option<u8> {
+    if x < 8 && y < 8 {
+      Some(((self.0[y] >> (x * 4)) & 0xF) as u8)
+    } else {
+      None
+    }
+  }
+
+  /// Sets the palette index of a pixel (higher bits are ignored).
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` if `x` or `y` is 8 or more.
+  pub fn set_pixel(&mut self, x: usize, y: usize, index: u8) -> Option<()> {
+    let word = self.0.get_mut(y).filter(|_| x < 8)?;
+    *word = set_nibble(*word, x, index);
+    Some(())
+  }
+
+  /// The tile mirrored left to right.
+  pub const fn hflip(self) -> Self {
+    let mut words = self.0;
+    let mut y = 0;
+    while y < 8 {
+      words[y] = hflip_4bpp_row(words[y]);
+      y += 1;
+    }
+    Self(words)
+  }
+
+  /// The tile mirrored top to bottom.
+  pub const fn vflip(self) -> Self {
+    let mut words = [0; 8];
+    let mut y = 0;
+    while y < 8 {
+      words[y] = self.0[7 - y];
+      y += 1;
+    }
+    Self(words)
+  }
+
+  /// Converts to an 8bpp tile that uses the colors of a palbank.
+  ///
+  /// Index 0 stays 0 (transparent), any other index `i` becomes
+  /// `palbank * 16 + i`.
+  pub const fn to_8bpp(self, palbank: u8) -> Tile8bpp {
+    let offset = (palbank & 0xF) << 4;
+    let mut words = [0; 16];
+    let mut i = 0;
+    while i < 64 {
+      let index = ((self.0[i / 8] >> ((i % 8) * 4)) & 0xF) as u8;
+      if index != 0 {
+        words[i / 4] |= ((offset | index) as u32) << ((i % 4) * 8);
+      }
+      i += 1;
+    }
+    Tile8bpp(words)
+  }
+}
+
+impl Tile8bpp {
+  /// Makes a tile from rows of palette indexes.
+  pub const fn from_rows(rows: [[u8; 8]; 8]) -> Self {
+    let mut words = [0; 16];
+    let mut i = 0;
+    while i < 64 {
+      words[i / 4] |= (rows[i / 8][i % 8] as u32) << ((i % 4) * 8);
+      i += 1;
+    }
+    Self(words)
+  }
+
+  /// The palette index of a pixel.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` if `x` or `y` is 8 or more.
+  pub const fn get_pixel(&self, x: usize, y: usize) -> Option<u8> {
+    if x < 8 && y < 8 {
+      Some((self.0[y * 2 + x / 4] >> ((x % 4) * 8)) as u8)
+    } else {
+      None
+    }
+  }
+
+  /// Sets the palette index of a pixel.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` if `x` or `y` is 8 or more.
+  pub fn set_pixel(&mut self, x: usize, y: usize, index: u8) -> Option<()> {
+    if x >= 8 || y >= 8 {
+      return None;
+    }
+    let word = &mut self.0[y * 2 + x / 4];
+    *word = set_byte(*word, x % 4, index);
+    Some(())
+  }
+
+  /// The tile mirrored left to right.
+  pub const fn hflip(self) -> Self {
+    let mut words = [0; 16];
+    let mut y = 0;
+    while y < 8 {
+      words[y * 2] = self.0[y * 2 + 1].swap_bytes();
+      words[y * 2 + 1] = self.0[y * 2].swap_bytes();
+      y += 1;
+    }
+    Self(words)
+  }
+
+  /// The tile mirrored top to bottom.
+  pub const fn vflip(self) -> Self {
+    let mut words = [0; 16];
+    let mut y = 0;
+    while y < 8 {
+      words[y * 2] = self.0[14 - y * 2];
+      words[y * 2 + 1] = self.0[15 - y * 2];
+      y += 1;
+    }
+    Self(words)
+  }
+
+  /// Converts to a 4bpp tile for use with a palbank.
+  ///
+  /// Index 0 stays 0 (transparent), any other index must be in the palbank
+  /// given (`palbank * 16` to `palbank * 16 + 15`).
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` if a pixel isn't transparent and isn't in the palbank, or
+  /// if it's the palbank's index 0 (which 4bpp can't show).
+  pub const fn to_4bpp(self, palbank: u8) -> Option<Tile4bpp> {
+    let mut words = [0; 8];
+    let mut i = 0;
+    while i < 64 {
+      let index = (self.0[i / 4] >> ((i % 4) * 8)) as u8;
+      if index != 0 {
+        if index >> 4 != palbank || index & 0xF == 0 {
+          return None;
+        }
+        words[i / 8] |= ((index & 0xF) as u32) << ((i % 8) * 4);
+      }
+      i += 1;
+    }
+    Some(Tile4bpp(words))
+  }
+}
+
+/// Edits a 4bpp tile in place in VRAM.
+///
+/// Every write is a whole `u32`, so this is safe to use on any tile in VRAM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TileEditor4bpp {
+  address: usize,
+}
+
+impl TileEditor4bpp {
+  /// Makes an editor for a tile, such as one from `get_4bpp_character_block`.
+  pub fn new(tile: VolAddress<Tile4bpp, Safe, Safe>) -> Self {
+    Self { address: tile.as_usize() }
+  }
+
+  fn rows(&self) -> VolBlock<u32, Safe, Safe, 8> {
+    unsafe { VolBlock::new(self.address) }
+  }
+
+  /// Reads the whole tile.
+  pub fn read(&self) -> Tile4bpp {
+    let mut tile = Tile4bpp([0; 8]);
+    for (word, va) in tile.0.iter_mut().zip(self.rows().iter()) {
+      *word = va.read();
+    }
+    tile
+  }
+
+  /// Writes the whole tile.
+  pub fn write(&self, tile: Tile4bpp) {
+    for (&word, va) in tile.0.iter().zip(self.rows().iter()) {
+      va.write(word);
+    }
+  }
+
+  /// The palette index of a pixel.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` if `x` or `y` is 8 or more.
+  pub fn get_pixel(&self, x: usize, y: usize) -> Option<u8> {
+    let row = self.rows().get(y).filter(|_| x < 8)?;
+    Some(((row.read() >> (x * 4)) & 0xF) as u8)
+  }
+
+  /// Sets the palette index of a pixel (higher bits are ignored).
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` if `x` or `y` is 8 or more.
+  pub fn set_pixel(&self, x: usize, y: usize, index: u8) -> Option<()> {
+    let row = self.rows().get(y).filter(|_| x < 8)?;
+    row.write(set_nibble(row.read(), x, index));
+    Some(())
+  }
+
+  /// Sets every pixel in a rectangle, with one read and write per row.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` (and writes nothing) if the rectangle goes outside the
+  /// tile.
+  pub fn fill_rect(
+    &self, x: usize, y: usize, width: usize, height: usize, index: u8,
+  ) -> Option<()> {
+    if !rect_fits(x, y, width, height, (8, 8)) {
+      return None;
+    }
+    let mask = if width == 8 { u32::MAX } else { ((1 << (width * 4)) - 1) << (x * 4) };
+    let bits = (index as u32 & 0xF) * 0x1111_1111 & mask;
+    for row in self.rows().iter().skip(y).take(height) {
+      row.write(row.read() & !mask | bits);
+    }
+    Some(())
+  }
+}
+
+/// Edits an 8bpp tile in place in VRAM.
+///
+/// Every write is a whole `u32`, so this is safe to use on any tile in VRAM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TileEditor8bpp {
+  address: usize,
+}
+
+impl TileEditor8bpp {
+  /// Makes an editor for a tile, such as one from `get_8bpp_character_block`.
+  pub fn new(tile: VolAddress<Tile8bpp, Safe, Safe>) -> Self {
+    Self { address: tile.as_usize() }
+  }
+
+  fn words(&self) -> VolBlock<u32, Safe, Safe, 16> {
+    unsafe { VolBlock::new(self.address) }
+  }
+
+  /// Reads the whole tile.
+  pub fn read(&self) -> Tile8bpp {
+    let mut tile = Tile8bpp([0; 16]);
+    for (word, va) in tile.0.iter_mut().zip(self.words().iter()) {
+      *word = va.read();
+    }
+    tile
+  }
+
+  /// Writes the whole tile.
+  pub fn write(&self, tile: Tile8bpp) {
+    for (&word, va) in tile.0.iter().zip(self.words().iter()) {
+      va.write(word);
+    }
+  }
+
+  /// The palette index of a pixel.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` if `x` or `y` is 8 or more.
+  pub fn get_pixel(&self, x: usize, y: usize) -> Option<u8> {
+    if x >= 8 || y >= 8 {
+      return None;
+    }
+    Some((self.words().index(y * 2 + x / 4).read() >> ((x % 4) * 8)) as u8)
+  }
+
+  /// Sets the palette index of a pixel.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` if `x` or `y` is 8 or more.
+  pub fn set_pixel(&self, x: usize, y: usize, index: u8) -> Option<()> {
+    if x >= 8 || y >= 8 {
+      return None;
+    }
+    let word = self.words().index(y * 2 + x / 4);
+    word.write(set_byte(word.read(), x % 4, index));
+    Some(())
+  }
+
+  /// Sets every pixel in a rectangle.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` (and writes nothing) if the rectangle goes outside the
+  /// tile.
+  pub fn fill_rect(
+    &self, x: usize, y: usize, width: usize, height: usize, index: u8,
+  ) -> Option<()> {
+    if !rect_fits(x, y, width, height, (8, 8)) {
+      return None;
+    }
+    let mask: u64 = if width == 8 { u64::MAX } else { ((1 << (width * 8)) - 1) << (x * 8) };
+    let bits = (index as u64 * 0x0101_0101_0101_0101) & mask;
+    for row in y..y + height {
+      for half in 0..2 {
+        let shift = half * 32;
+        let (word_mask, word_bits) = ((mask >> shift) as u32, (bits >> shift) as u32);
+        if word_mask != 0 {
+          let word = self.words().index(row * 2 + half);
+          word.write(word.read() & !word_mask | word_bits);
+        }
+      }
+    }
+    Some(())
+  }
+}
+
+/// The number of 4bpp tiles in the background part of VRAM.
+const BG_4BPP_TILE_COUNT: usize = 2048;
+
+/// A rectangle of 4bpp tiles, used as a small bitmap.
+///
+/// The canvas is a run of `width * height` tiles starting at tile
+/// `first_tile` of a charblock, in rows, so the tile at `(col, row)` is
+/// `first_tile + row * width + col`. Draw on it with the `draw::Surface`
+/// methods (pixels are palette indexes), then `map_to` a text background to
+/// show it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TileCanvas {
+  char_base_block: usize,
+  first_tile: usize,
+  width: usize,
+  height: usize,
+}
+
+impl TileCanvas {
+  /// Makes a canvas `width` by `height` tiles.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` if the run of tiles goes past tile id 1023 or past the end
+  /// of background VRAM.
+  pub fn new(
+    char_base_block: usize, first_tile: usize, width: usize, height: usize,
+  ) -> Option<Self> {
+    let end = first_tile.checked_add(width.checked_mul(height)?)?;
+    if end > 1024 || char_base_block >= 4 || char_base_block * 512 + end > BG_4BPP_TILE_COUNT {
+      return None;
+    }
+    Some(Self { char_base_block, first_tile, width, height })
+  }
+
+  /// The size in tiles, as `(width, height)`.
+  pub const fn size(&self) -> (usize, usize) {
+    (self.width, self.height)
+  }
+
+  /// An editor for one of the tiles.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` if the position is outside the canvas.
+  pub fn tile(&self, col: usize, row: usize) -> Option<TileEditor4bpp> {
+    if col >= self.width || row >= self.height {
+      return None;
+    }
+    let index = self.first_tile + row * self.width + col;
+    let address = CHAR_BASE_BLOCKS.index(self.char_base_block).as_usize() + index * 32;
+    Some(TileEditor4bpp::new(unsafe { VolAddress::new(address) }))
+  }
+
+  /// Sets every pixel to palette index 0.
+  pub fn clear(&self) {
+    for row in 0..self.height {
+      for col in 0..self.width {
+        if let Some(tile) = self.tile(col, row) {
+          tile.write(Tile4bpp([0; 8]));
+        }
+      }
+    }
+  }
+
+  /// Points a rectangle of a text map at the canvas, with its top left at
+  /// `(x, y)` and using the palbank given.
+  ///
+  /// The background must use this canvas's charblock.
+  ///
+  /// ## Failure
+  ///
+  /// Gives `None` (and writes nothing) if the canvas doesn't fit in the map
+  /// there.
+  pub fn map_to<const W: usize, const H: usize>(
+    &self, map: &TextMap<W, H>, x: usize, y: usize, palbank: u16,
+  ) -> Option<()> {
+    if !rect_fits(x, y, self.width, self.height, (W, H)) {
+      return None;
+    }
+    for row in 0..self.height {
+      for col in 0..self.width {
+        let id = (self.first_tile + row * self.width + col) as u16;
+        map.write(x + col, y + row, TextScreenblockEntry::from_tile_id(id).with_palbank(palbank))?;
+      }
+    }
+    Some(())
+  }
+}
+
+const fn pack_4bpp_row(row: [u8; 8]) -> u32 {
+  let mut word = 0;
+  let mut x = 0;
+  while x < 8 {
+    word |= ((row[x] & 0xF) as u32) << (x * 4);
+    x += 1;
+  }
+  word
+}
+
+const fn hflip_4bpp_row(word: u32) -> u32 {
+  let mut out = 0;
+  let mut x = 0;
+  while x < 8 {
+    out |= ((word >> (x * 4)) & 0xF) << ((7 - x) * 4);
+    x += 1;
+  }
+  out
+}
+
+fn set_nibble(word: u32, x: usize, index: u8) -> u32 {
+  let shift = x * 4;
+  word & !(0xF << shift) | ((index as u32 & 0xF) << shift)
+}
+
+fn set_byte(word: u32, i: usize, index: u8) -> u32 {
+  let shift = i * 8;
+  word & !(0xFF << shift) | ((index as u32) << shift)
+}
+
+#[test]
+fn test_tile_flips_and_conversion() {
+  let mut rows = [[0; 8]; 8];
+  rows[0] = [1, 2, 3, 4, 5, 6, 7, 8];
+  rows[7][0] = 15;
+  let tile = Tile4bpp::from_rows(rows);
+  assert_eq!(tile.get_pixel(3, 0), Some(4));
+  assert_eq!(tile.hflip().get_pixel(0, 0), Some(8));
+  assert_eq!(tile.vflip().get_pixel(0, 0), Some(15));
+  let wide = tile.to_8bpp(2);
+  assert_eq!(wide.get_pixel(7, 0), Some(0x28));
+  assert_eq!(wide.get_pixel(1, 1), Some(0));
+  assert_eq!(wide.hflip().get_pixel(0, 0), Some(0x28));
+  assert_eq!(wide.vflip().get_pixel(0, 0), Some(0x2F));
+  assert_eq!(wide.to_4bpp(2).map(|t| t.0), Some(tile.0));
+  assert!(wide.to_4bpp(3).is_none());
+  assert_eq!(Tile8bpp::from_rows(rows).get_pixel(7, 0), Some(8));
+}